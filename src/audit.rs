@@ -0,0 +1,459 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::config::AppConfig;
+use crate::safety::SafetyResult;
+
+/// `prev_hash` of the first record
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Settings for the audit log (`[audit]` in config.toml)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub enabled: bool,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// What the user (or auto-execution) did with a suggestion
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    AutoExecuted,
+    Approved,
+    Declined,
+    /// Ran in the sandbox only
+    Sandboxed,
+    /// Ran in the sandbox first, then for real
+    SandboxedThenApproved,
+    /// Blocked by the safety checker or the post-block cooldown
+    Refused,
+}
+
+/// One suggestion and its outcome, before it is chained into the log
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    /// `oneshot`, `repl` or `hook`
+    pub mode: &'static str,
+    pub request: String,
+    pub command: String,
+    pub model: String,
+    pub verdict: &'static str,
+    pub findings: Vec<String>,
+    pub decision: Decision,
+    pub forced: bool,
+    pub exit_code: Option<i32>,
+    pub cwd: String,
+}
+
+impl AuditEntry {
+    pub fn new(mode: &'static str, request: &str, command: &str, model: &str, result: &SafetyResult, forced: bool) -> Self {
+        let findings = result.reason()
+            .map(|reason| {
+                reason.lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            mode,
+            request: request.to_string(),
+            command: command.to_string(),
+            model: model.to_string(),
+            verdict: result.verdict(),
+            findings,
+            decision: Decision::Declined,
+            forced,
+            exit_code: None,
+            cwd: std::env::current_dir()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// A line of the audit log. `hash` is an HMAC-SHA256 of every other field, including `prev_hash`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub seq: u64,
+    pub timestamp: String,
+    pub mode: String,
+    pub request: String,
+    pub command: String,
+    pub model: String,
+    pub verdict: String,
+    pub findings: Vec<String>,
+    pub decision: Decision,
+    pub forced: bool,
+    pub exit_code: Option<i32>,
+    pub cwd: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditRecord {
+    fn compute_hash(&self, key: &hmac::Key) -> Result<String> {
+        let unhashed = AuditRecord { hash: String::new(), ..self.clone() };
+        let bytes = serde_json::to_vec(&unhashed).context("Failed to serialize audit record")?;
+        Ok(hmac::sign(key, &bytes)
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// A JSON array
+    Json,
+    /// One JSON record per line
+    Jsonl,
+}
+
+/// Result of checking the hash chain
+#[derive(Debug, Default)]
+pub struct Verification {
+    pub records: usize,
+    pub problems: Vec<String>,
+}
+
+impl Verification {
+    pub fn is_intact(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Append-only, HMAC-chained log at `~/.commandgpt/audit.log`. The sequence number and
+/// hash of the newest record are also kept in `audit.head`, so truncating the log is
+/// detected as well as editing it. The key lives outside the log directory (see
+/// `AppConfig::audit_key`), so rewriting the chain and its head needs the key too
+pub struct AuditLog {
+    path: PathBuf,
+    head_path: PathBuf,
+    key: hmac::Key,
+}
+
+impl AuditLog {
+    pub fn new(config: &AppConfig) -> Result<Self> {
+        Ok(Self::at(&config.config_dir.join("audit.log"), &config.audit_key()?))
+    }
+
+    pub fn at(path: &Path, key: &[u8]) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            head_path: path.with_extension("head"),
+            key: hmac::Key::new(hmac::HMAC_SHA256, key),
+        }
+    }
+
+    pub fn append(&self, entry: AuditEntry) -> Result<AuditRecord> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).context("Failed to create audit log directory")?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open audit log {}", self.path.display()))?;
+
+        // Serialize concurrent writers (several shells running the hook)
+        let _lock = FileLock::exclusive(&file)?;
+
+        let last = last_record(&file)?;
+        let mut record = AuditRecord {
+            seq: last.as_ref().map(|r| r.seq + 1).unwrap_or(1),
+            timestamp: Utc::now().to_rfc3339(),
+            mode: entry.mode.to_string(),
+            request: entry.request,
+            command: entry.command,
+            model: entry.model,
+            verdict: entry.verdict.to_string(),
+            findings: entry.findings,
+            decision: entry.decision,
+            forced: entry.forced,
+            exit_code: entry.exit_code,
+            cwd: entry.cwd,
+            prev_hash: last.map(|r| r.hash).unwrap_or_else(|| GENESIS_HASH.to_string()),
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash(&self.key)?;
+
+        let mut line = serde_json::to_string(&record).context("Failed to serialize audit record")?;
+        line.push('\n');
+        (&file).write_all(line.as_bytes()).context("Failed to write audit record")?;
+        file.sync_data().context("Failed to sync audit log")?;
+        fs::write(&self.head_path, format!("{} {}\n", record.seq, record.hash))
+            .context("Failed to update audit head")?;
+
+        Ok(record)
+    }
+
+    /// All records in order; lines that do not parse are skipped (see `verify`)
+    pub fn records(&self) -> Result<Vec<AuditRecord>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let file = File::open(&self.path).context("Failed to open audit log")?;
+        let mut records = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.context("Failed to read audit log")?;
+            if let Ok(record) = serde_json::from_str(&line) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    /// Recompute every hash and check the chain links, sequence numbers and head
+    pub fn verify(&self) -> Result<Verification> {
+        let mut verification = Verification::default();
+        let mut prev_hash = GENESIS_HASH.to_string();
+        let mut expected_seq = 1;
+
+        let lines = match File::open(&self.path) {
+            Ok(file) => BufReader::new(file).lines().collect::<std::io::Result<Vec<_>>>()
+                .context("Failed to read audit log")?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).context("Failed to open audit log"),
+        };
+        let has_lines = lines.iter().any(|line| !line.trim().is_empty());
+
+        for (index, line) in lines.into_iter().enumerate() {
+            let line_no = index + 1;
+            let record: AuditRecord = match serde_json::from_str(&line) {
+                Ok(record) => record,
+                Err(_) => {
+                    verification.problems.push(format!("line {}: not a valid audit record", line_no));
+                    continue;
+                }
+            };
+            verification.records += 1;
+
+            if record.seq != expected_seq {
+                verification.problems.push(format!(
+                    "line {}: record {} found where record {} was expected (records removed or reordered)",
+                    line_no, record.seq, expected_seq
+                ));
+            }
+            if record.prev_hash != prev_hash {
+                verification.problems.push(format!(
+                    "line {}: record {} does not link to the previous record",
+                    line_no, record.seq
+                ));
+            }
+            if record.compute_hash(&self.key)? != record.hash {
+                verification.problems.push(format!(
+                    "line {}: record {} was modified after it was written",
+                    line_no, record.seq
+                ));
+            }
+
+            // Continue from this record so one edit is reported once
+            prev_hash = record.hash;
+            expected_seq = record.seq + 1;
+        }
+
+        match fs::read_to_string(&self.head_path) {
+            Ok(head) => {
+                let expected = format!("{} {}", expected_seq - 1, prev_hash);
+                if head.trim() != expected {
+                    verification.problems.push(format!(
+                        "the log ends at record {} but the newest record written was {} (log truncated or replaced)",
+                        expected_seq - 1,
+                        head.split_whitespace().next().unwrap_or("?")
+                    ));
+                }
+            }
+            // Without the head a truncated log looks complete
+            Err(_) if has_lines => verification.problems.push(format!(
+                "{} is missing or unreadable, so truncation cannot be ruled out",
+                self.head_path.display()
+            )),
+            Err(_) => {}
+        }
+
+        Ok(verification)
+    }
+
+    pub fn export(&self, format: ExportFormat) -> Result<String> {
+        let records = self.records()?;
+        match format {
+            ExportFormat::Json => Ok(serde_json::to_string_pretty(&records)? + "\n"),
+            ExportFormat::Jsonl => {
+                let mut out = String::new();
+                for record in &records {
+                    out.push_str(&serde_json::to_string(record)?);
+                    out.push('\n');
+                }
+                Ok(out)
+            }
+        }
+    }
+}
+
+/// Append an entry if auditing is enabled; failures are logged rather than interrupting the user
+pub fn record(config: &AppConfig, entry: AuditEntry) {
+    if !config.audit.enabled {
+        return;
+    }
+    if let Err(e) = AuditLog::new(config).and_then(|log| log.append(entry)) {
+        log::warn!("Failed to write audit record: {}", e);
+    }
+}
+
+fn last_record(file: &File) -> Result<Option<AuditRecord>> {
+    let mut last = None;
+    for line in BufReader::new(file).lines() {
+        let line = line.context("Failed to read audit log")?;
+        if !line.trim().is_empty() {
+            last = Some(line);
+        }
+    }
+    match last {
+        Some(line) => Ok(Some(serde_json::from_str(&line).context("Last audit record is corrupt")?)),
+        None => Ok(None),
+    }
+}
+
+/// An advisory `flock` held until dropped
+struct FileLock<'a> {
+    file: &'a File,
+}
+
+impl<'a> FileLock<'a> {
+    fn exclusive(file: &'a File) -> Result<Self> {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to lock audit log");
+        }
+        Ok(Self { file })
+    }
+}
+
+impl Drop for FileLock<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            libc::flock(self.file.as_raw_fd(), libc::LOCK_UN);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const KEY: &[u8] = b"test audit key";
+
+    fn entry(command: &str, result: &SafetyResult) -> AuditEntry {
+        let mut entry = AuditEntry::new("oneshot", "clean up", command, "gpt-4", result, false);
+        entry.decision = Decision::Approved;
+        entry.exit_code = Some(0);
+        entry
+    }
+
+    fn write_three(log: &AuditLog) {
+        log.append(entry("ls", &SafetyResult::Safe)).unwrap();
+        log.append(entry("rm notes.txt", &SafetyResult::NeedsConfirmation("Destructive command 'rm'".to_string()))).unwrap();
+        log.append(entry("pwd", &SafetyResult::Safe)).unwrap();
+    }
+
+    #[test]
+    fn test_append_chains_records() {
+        let dir = TempDir::new().unwrap();
+        let log = AuditLog::at(&dir.path().join("audit.log"), KEY);
+        write_three(&log);
+
+        let records = log.records().unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].prev_hash, GENESIS_HASH);
+        assert_eq!(records[1].prev_hash, records[0].hash);
+        assert_eq!(records[2].seq, 3);
+        assert_eq!(records[1].verdict, "confirm");
+        assert_eq!(records[1].findings, vec!["Destructive command 'rm'"]);
+
+        let verification = log.verify().unwrap();
+        assert_eq!(verification.records, 3);
+        assert!(verification.is_intact(), "{:?}", verification.problems);
+    }
+
+    #[test]
+    fn test_verify_detects_tampering() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("audit.log");
+        let log = AuditLog::at(&path, KEY);
+        write_three(&log);
+        let original = fs::read_to_string(&path).unwrap();
+
+        // Editing a field breaks that record's hash
+        fs::write(&path, original.replacen("rm notes.txt", "rm other.txt", 1)).unwrap();
+        let problems = log.verify().unwrap().problems;
+        assert_eq!(problems, vec!["line 2: record 2 was modified after it was written"]);
+
+        // Removing a record breaks the chain
+        let lines: Vec<&str> = original.lines().collect();
+        fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        let problems = log.verify().unwrap().problems;
+        assert!(problems.iter().any(|p| p.contains("record 3 found where record 2 was expected")));
+
+        // Truncating the tail is caught by the head file
+        fs::write(&path, format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+        let problems = log.verify().unwrap().problems;
+        assert!(problems[0].contains("ends at record 2 but the newest record written was 3"));
+
+        // ...and removing the head along with the tail does not hide it
+        fs::remove_file(path.with_extension("head")).unwrap();
+        let problems = log.verify().unwrap().problems;
+        assert!(problems[0].contains("audit.head is missing or unreadable"));
+
+        // Removing the log but not the head is caught too
+        fs::remove_file(&path).unwrap();
+        fs::write(path.with_extension("head"), "3 abc\n").unwrap();
+        let problems = log.verify().unwrap().problems;
+        assert!(problems[0].contains("ends at record 0 but the newest record written was 3"));
+    }
+
+    #[test]
+    fn test_rewritten_chain_needs_key() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("audit.log");
+        write_three(&AuditLog::at(&path, KEY));
+
+        // A chain and head rebuilt from scratch without the key do not verify
+        let forged = dir.path().join("forged.log");
+        write_three(&AuditLog::at(&forged, b"guessed key"));
+        fs::rename(&forged, &path).unwrap();
+        fs::rename(forged.with_extension("head"), path.with_extension("head")).unwrap();
+
+        let problems = AuditLog::at(&path, KEY).verify().unwrap().problems;
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems.iter().all(|p| p.contains("was modified after it was written")));
+    }
+
+    #[test]
+    fn test_export() {
+        let dir = TempDir::new().unwrap();
+        let log = AuditLog::at(&dir.path().join("audit.log"), KEY);
+        write_three(&log);
+
+        let json: serde_json::Value = serde_json::from_str(&log.export(ExportFormat::Json).unwrap()).unwrap();
+        assert_eq!(json[2]["command"], "pwd");
+        assert_eq!(json[0]["decision"], "approved");
+        assert_eq!(log.export(ExportFormat::Jsonl).unwrap().lines().count(), 3);
+    }
+}
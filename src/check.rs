@@ -0,0 +1,263 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::safety::{Challenge, SafetyChecker, SafetyConfig, SafetyResult};
+
+/// Exit code when at least one command needs confirmation
+pub const EXIT_NEEDS_CONFIRMATION: i32 = 12;
+/// Exit code when at least one command is blocked
+pub const EXIT_BLOCKED: i32 = 13;
+
+/// How `commandgpt check` splits its input into commands
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum InputFormat {
+    /// One command per line; blank lines and `#` comments are skipped
+    Lines,
+    /// One JSON object with a `command` field (or a JSON string) per line
+    Jsonl,
+    /// A shell script, checked statement by statement
+    Script,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+}
+
+/// A command to check and where it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckItem {
+    /// e.g. `line 3` or the `id` of a JSONL record
+    pub source: String,
+    pub command: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonlRecord {
+    command: String,
+    #[serde(default)]
+    id: Option<serde_json::Value>,
+}
+
+/// A policy file for testing custom rules: any TOML file with a `[safety]` table,
+/// such as `config.toml` itself
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PolicyFile {
+    safety: SafetyConfig,
+}
+
+pub fn load_policy(path: &Path) -> Result<SafetyConfig> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read policy file {}", path.display()))?;
+    let policy: PolicyFile = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse policy file {}", path.display()))?;
+    Ok(policy.safety)
+}
+
+/// One command per line, skipping blank lines and `#` comments
+pub fn parse_lines(text: &str) -> Vec<CheckItem> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| CheckItem { source: format!("line {}", index + 1), command: line.to_string() })
+        .collect()
+}
+
+/// One JSON record per line: `{"command": "...", "id": ...}` or a bare JSON string
+pub fn parse_jsonl(text: &str) -> Result<Vec<CheckItem>> {
+    let mut items = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let source = format!("line {}", index + 1);
+        let value: serde_json::Value = serde_json::from_str(line)
+            .with_context(|| format!("Invalid JSON on {}", source))?;
+
+        let item = match value {
+            serde_json::Value::String(command) => CheckItem { source, command },
+            value => {
+                let record: JsonlRecord = serde_json::from_value(value)
+                    .with_context(|| format!("Expected a \"command\" field on {}", source))?;
+                let source = match record.id {
+                    Some(serde_json::Value::String(id)) => id,
+                    Some(id) => id.to_string(),
+                    None => source,
+                };
+                CheckItem { source, command: record.command }
+            }
+        };
+        items.push(item);
+    }
+    Ok(items)
+}
+
+/// The verdict for one checked command
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckOutcome {
+    pub source: String,
+    pub command: String,
+    /// `safe`, `confirm` or `blocked`
+    pub verdict: &'static str,
+    /// For `confirm`: `yes-no`, `program`, `resource` or `challenge-word`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmation: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl CheckOutcome {
+    fn new(source: String, command: String, result: SafetyResult) -> Self {
+        let (verdict, confirmation, reason) = match result {
+            SafetyResult::Safe => ("safe", None, None),
+            SafetyResult::NeedsConfirmation(reason) => ("confirm", Some("yes-no"), Some(reason)),
+            SafetyResult::NeedsTypedConfirmation { reason, challenge } => {
+                let tier = match challenge {
+                    Challenge::Program(_) => "program",
+                    Challenge::Resource(_) => "resource",
+                    Challenge::RandomWord => "challenge-word",
+                };
+                ("confirm", Some(tier), Some(reason))
+            }
+            SafetyResult::Blocked(reason) => ("blocked", None, Some(reason)),
+        };
+        Self { source, command, verdict, confirmation, reason }
+    }
+
+    fn severity(&self) -> u8 {
+        match self.verdict {
+            "blocked" => 2,
+            "confirm" => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// Run every command through the full safety pipeline; no model is involved
+pub fn check_items(checker: &SafetyChecker, items: Vec<CheckItem>, force: bool) -> Result<Vec<CheckOutcome>> {
+    items.into_iter()
+        .map(|item| {
+            let result = checker.validate(&item.command, force)?;
+            Ok(CheckOutcome::new(item.source, item.command, result))
+        })
+        .collect()
+}
+
+/// Check a script statement by statement, reporting each statement's starting line
+pub fn check_script(checker: &SafetyChecker, script: &str, force: bool) -> Result<Vec<CheckOutcome>> {
+    let report = checker.validate_script(script, force)?;
+    Ok(report.lines.into_iter()
+        .map(|line| CheckOutcome::new(format!("line {}", line.line), line.text, line.result))
+        .collect())
+}
+
+/// Check input text in the given format
+pub fn check_text(checker: &SafetyChecker, text: &str, format: InputFormat, force: bool) -> Result<Vec<CheckOutcome>> {
+    match format {
+        InputFormat::Lines => check_items(checker, parse_lines(text), force),
+        InputFormat::Jsonl => check_items(checker, parse_jsonl(text)?, force),
+        InputFormat::Script => check_script(checker, text, force),
+    }
+}
+
+/// Exit code for the worst verdict
+pub fn exit_code(outcomes: &[CheckOutcome]) -> i32 {
+    match outcomes.iter().map(CheckOutcome::severity).max() {
+        Some(2) => EXIT_BLOCKED,
+        Some(1) => EXIT_NEEDS_CONFIRMATION,
+        _ => 0,
+    }
+}
+
+pub fn render_json(outcomes: &[CheckOutcome]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(outcomes)?)
+}
+
+pub fn render_table(outcomes: &[CheckOutcome]) -> String {
+    let width = outcomes.iter().map(|o| o.source.len()).max().unwrap_or(0).max("Source".len());
+    let mut table = format!("{:<width$}  {:<8}  Command\n", "Source", "Verdict", width = width);
+
+    for outcome in outcomes {
+        let verdict = match (outcome.verdict, outcome.confirmation) {
+            ("confirm", Some("yes-no")) | ("confirm", None) => "CONFIRM",
+            ("confirm", Some(_)) => "TYPE",
+            ("blocked", _) => "BLOCKED",
+            _ => "ok",
+        };
+        table.push_str(&format!("{:<width$}  {:<8}  {}\n", outcome.source, verdict, outcome.command, width = width));
+        if let Some(reason) = outcome.reason.as_deref().and_then(|reason| reason.lines().next()) {
+            table.push_str(&format!("{:<width$}  {:<8}  ↳ {}\n", "", "", reason, width = width));
+        }
+    }
+
+    let count = |verdict: &str| outcomes.iter().filter(|o| o.verdict == verdict).count();
+    table.push_str(&format!(
+        "\n{} checked: {} safe, {} need confirmation, {} blocked",
+        outcomes.len(),
+        count("safe"),
+        count("confirm"),
+        count("blocked")
+    ));
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lines_and_jsonl() {
+        let items = parse_lines("ls -la\n\n# comment\nrm -rf /\n");
+        assert_eq!(items, vec![
+            CheckItem { source: "line 1".to_string(), command: "ls -la".to_string() },
+            CheckItem { source: "line 4".to_string(), command: "rm -rf /".to_string() },
+        ]);
+
+        let jsonl = "{\"id\": \"deploy-3\", \"command\": \"kubectl get pods\"}\n\"pwd\"\n{\"command\": \"ls\", \"id\": 7}";
+        let items = parse_jsonl(jsonl).unwrap();
+        assert_eq!(items[0].source, "deploy-3");
+        assert_eq!(items[1], CheckItem { source: "line 2".to_string(), command: "pwd".to_string() });
+        assert_eq!(items[2].source, "7");
+
+        assert!(parse_jsonl("{\"cmd\": \"ls\"}").is_err());
+    }
+
+    #[test]
+    fn test_verdicts_and_exit_code() {
+        let checker = SafetyChecker::default();
+        let outcomes = check_text(&checker, "pwd\nrm notes.txt\nrm -rf /", InputFormat::Lines, false).unwrap();
+
+        let verdicts: Vec<_> = outcomes.iter().map(|o| o.verdict).collect();
+        assert_eq!(verdicts, vec!["safe", "confirm", "blocked"]);
+        assert_eq!(exit_code(&outcomes), EXIT_BLOCKED);
+        assert_eq!(exit_code(&outcomes[..2]), EXIT_NEEDS_CONFIRMATION);
+        assert_eq!(exit_code(&outcomes[..1]), 0);
+
+        let json: serde_json::Value = serde_json::from_str(&render_json(&outcomes).unwrap()).unwrap();
+        assert_eq!(json[1]["confirmation"], "yes-no");
+        assert!(json[0].get("reason").is_none());
+        assert!(render_table(&outcomes).ends_with("3 checked: 1 safe, 1 need confirmation, 1 blocked"));
+    }
+
+    #[test]
+    fn test_script_and_policy() {
+        let checker = SafetyChecker::default();
+        let script = "for f in *.log; do\n  gzip \"$f\"\ndone\nrm -rf ~";
+        let outcomes = check_text(&checker, script, InputFormat::Script, false).unwrap();
+        assert_eq!(outcomes.last().unwrap().source, "line 4");
+        assert_eq!(outcomes.last().unwrap().verdict, "blocked");
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("policy.toml");
+        std::fs::write(&path, "[safety]\nprotected_paths = [\"/srv/data\"]\n").unwrap();
+        let policy = load_policy(&path).unwrap();
+        assert_eq!(policy.protected_paths, vec!["/srv/data"]);
+        // Unspecified sections keep their defaults
+        assert!(!policy.egress.allowed_hosts.is_empty());
+    }
+}
@@ -5,10 +5,25 @@ use std::env;
 use std::fs;
 use std::path::PathBuf;
 
+use crate::audit::AuditConfig;
+use crate::confirm::ConfirmationConfig;
+use crate::environment::EnvironmentConfig;
+use crate::limits::LimitsConfig;
+use crate::output::{self, OutputConfig};
+use crate::portability::PortabilityConfig;
+use crate::redact::RedactionConfig;
+use crate::safety::SafetyConfig;
+use crate::sandbox::SandboxConfig;
+use crate::shell::{Shell, ShellConfig};
+
 const KEYCHAIN_SERVICE: &str = "commandgpt";
 const KEYCHAIN_ACCOUNT: &str = "openai";
+const KEYCHAIN_AUDIT_ACCOUNT: &str = "audit-key";
+/// `errSecItemNotFound`: the Keychain has no such item
+const ERR_SEC_ITEM_NOT_FOUND: i32 = -25300;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppConfig {
     pub openai_model: String,
     pub openai_base_url: String,
@@ -20,6 +35,20 @@ pub struct AppConfig {
     pub context_dir: PathBuf,
     pub history_path: PathBuf,
     pub system_prompt_path: PathBuf,
+    pub settings_path: PathBuf,
+    pub sandbox: SandboxConfig,
+    pub safety: SafetyConfig,
+    pub redaction: RedactionConfig,
+    pub confirmation: ConfirmationConfig,
+    pub audit: AuditConfig,
+    pub portability: PortabilityConfig,
+    pub environment: EnvironmentConfig,
+    pub output: OutputConfig,
+    pub shell: ShellConfig,
+    pub limits: LimitsConfig,
+    /// Print the exact payload sent to the provider (set from --show-payload)
+    #[serde(skip)]
+    pub show_payload: bool,
 }
 
 impl Default for AppConfig {
@@ -38,6 +67,18 @@ impl Default for AppConfig {
             context_dir: config_dir.join("context"),
             history_path: config_dir.join("history.db"),
             system_prompt_path: config_dir.join("system.md"),
+            settings_path: config_dir.join("config.toml"),
+            sandbox: SandboxConfig::default(),
+            safety: SafetyConfig::default(),
+            redaction: RedactionConfig::default(),
+            confirmation: ConfirmationConfig::default(),
+            audit: AuditConfig::default(),
+            portability: PortabilityConfig::default(),
+            environment: EnvironmentConfig::default(),
+            output: OutputConfig::default(),
+            shell: ShellConfig::default(),
+            limits: LimitsConfig::default(),
+            show_payload: false,
             config_dir,
         }
     }
@@ -46,6 +87,14 @@ impl Default for AppConfig {
 impl AppConfig {
     pub fn load() -> Result<Self> {
         let mut config = Self::default();
+
+        // Merge user settings from ~/.commandgpt/config.toml if present
+        if config.settings_path.exists() {
+            let content = fs::read_to_string(&config.settings_path)
+                .context("Failed to read config.toml")?;
+            config = toml::from_str(&content)
+                .context("Failed to parse config.toml")?;
+        }
         
         // Allow environment variable override for model
         if let Ok(model) = env::var("OPENAI_MODEL") {
@@ -89,20 +138,39 @@ impl AppConfig {
         }
     }
 
+    /// Secret for the audit log's HMAC chain, kept in the Keychain rather than next to the log.
+    /// Generated on first use; any other Keychain error (locked, access denied) is returned
+    /// so an existing key is never replaced
+    pub fn audit_key(&self) -> Result<Vec<u8>> {
+        match get_generic_password(KEYCHAIN_SERVICE, KEYCHAIN_AUDIT_ACCOUNT) {
+            Ok(key) => return Ok(key),
+            Err(e) if e.code() == ERR_SEC_ITEM_NOT_FOUND => {}
+            Err(e) => return Err(e).context("Failed to read audit key from Keychain"),
+        }
+
+        let mut key = vec![0u8; 32];
+        ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut key)
+            .map_err(|_| anyhow::anyhow!("Failed to generate audit key"))?;
+        set_generic_password(KEYCHAIN_SERVICE, KEYCHAIN_AUDIT_ACCOUNT, &key)
+            .context("Failed to store audit key in Keychain. You may need to grant permission when prompted.")?;
+        Ok(key)
+    }
+
     fn default_system_prompt() -> &'static str {
-        r#"You are a helpful command-line assistant that generates shell commands for macOS/zsh.
+        r#"You are a helpful command-line assistant that generates shell commands for the user's system.
 
 ## Rules:
 1. Always respond with valid JSON in this exact format:
    {
      "command": "string",
      "explanation": "string", 
-     "auto_execute": boolean
+     "auto_execute": boolean,
+     "interactive": boolean
    }
 
 2. Generate commands that are:
    - Safe and non-destructive by default
-   - Compatible with zsh on macOS
+   - Compatible with the OS, shell and core utilities (GNU, BSD or BusyBox) listed under Current Environment
    - Use commonly available tools (prefer built-in commands)
 
 3. Set auto_execute to true only for:
@@ -116,18 +184,20 @@ impl AppConfig {
    - Network operations
    - Package installations
 
-5. Keep explanations concise but helpful.
+5. Set interactive to true for commands that need a terminal: editors, pagers, full-screen tools (top, less, vim), REPLs, ssh sessions and anything that prompts for input.
+
+6. Keep explanations concise but helpful.
 
-6. If the request is unclear or potentially dangerous, ask for clarification in the explanation and provide a safe alternative command.
+7. If the request is unclear or potentially dangerous, ask for clarification in the explanation and provide a safe alternative command.
 
-7. Use absolute paths when possible to avoid ambiguity.
+8. Use absolute paths when possible to avoid ambiguity.
 
 ## Examples:
 User: "show me large files"
-Response: {"command": "find ~ -type f -size +100M -exec ls -lh {} +", "explanation": "Find files larger than 100MB in home directory", "auto_execute": true}
+Response: {"command": "find ~ -type f -size +100M -exec ls -lh {} +", "explanation": "Find files larger than 100MB in home directory", "auto_execute": true, "interactive": false}
 
 User: "install node"
-Response: {"command": "brew install node", "explanation": "Install Node.js using Homebrew (requires confirmation)", "auto_execute": false}
+Response: {"command": "brew install node", "explanation": "Install Node.js using Homebrew (requires confirmation)", "auto_execute": false, "interactive": false}
 "#
     }
 
@@ -200,6 +270,39 @@ pub async fn show_config(config: &AppConfig) -> Result<()> {
     println!("  Temperature: {}", config.temperature);
     println!("  Timeout: {}s", config.timeout_seconds);
     println!("  Config Dir: {}", config.config_dir.display());
+    println!("  Sandbox: {}", if crate::sandbox::is_supported() {
+        "✅ Available (Landlock)"
+    } else {
+        "❌ Not supported on this system"
+    });
+    println!("  Secret Redaction: {}", if config.redaction.enabled {
+        format!("✅ Enabled ({} custom patterns)", config.redaction.extra_patterns.len())
+    } else {
+        "⚠️  Disabled".to_string()
+    });
+    println!("  Cooldown After Block: {}s", config.confirmation.cooldown_seconds);
+    println!("  Audit Log: {}", if config.audit.enabled {
+        format!("✅ {}", config.config_dir.join("audit.log").display())
+    } else {
+        "⚠️  Disabled".to_string()
+    });
+    println!("  Command Environment: {}", if config.environment.allow.is_empty() {
+        format!("inherited, {} patterns withheld", config.environment.deny.len())
+    } else {
+        format!("allowlist of {} patterns", config.environment.allow.len())
+    });
+    println!("  Shell: {}", Shell::detect(&config.shell));
+    println!("  Execution Limits: {}", crate::limits::describe(&config.limits));
+    println!("  Output Capture: first {} and last {} of each stream",
+             output::format_bytes(config.output.head_bytes as u64), output::format_bytes(config.output.tail_bytes as u64));
+    println!("  Portability: {}", match (config.portability.enabled, config.portability.variant) {
+        (false, _) => "⚠️  Disabled".to_string(),
+        (true, variant) => format!(
+            "✅ {} ({})",
+            variant.map_or_else(|| crate::portability::Toolset::detect().summary(), |v| v.to_string()),
+            if config.portability.rewrite { "rewrite" } else { "warn only" }
+        ),
+    });
     
     // Check API key status
     match config.get_api_key() {
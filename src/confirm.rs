@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::AppConfig;
+use crate::safety::{Challenge, SafetyResult};
+
+/// Words for the challenge tier: short, unambiguous and easy to type
+const CHALLENGE_WORDS: &[&str] = &[
+    "anchor", "basalt", "cobalt", "dune", "ember", "fjord", "granite", "harbor",
+    "indigo", "juniper", "kestrel", "lantern", "meadow", "nickel", "orchid", "pepper",
+    "quartz", "ridge", "saffron", "timber", "umber", "velvet", "willow", "zephyr",
+];
+
+/// Settings for confirmation prompts (`[confirmation]` in config.toml)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfirmationConfig {
+    /// Seconds after a blocked command during which nothing can be confirmed (0 disables)
+    pub cooldown_seconds: u64,
+}
+
+impl Default for ConfirmationConfig {
+    fn default() -> Self {
+        Self { cooldown_seconds: 30 }
+    }
+}
+
+/// What the user has to enter to approve a command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Confirmation {
+    /// A plain `y`/`yes`
+    YesNo,
+    /// The exact text of `expected`, described to the user by `hint`
+    Typed { expected: String, hint: &'static str },
+}
+
+impl Confirmation {
+    pub fn for_challenge(challenge: &Challenge) -> Self {
+        match challenge {
+            Challenge::Program(program) => Confirmation::Typed {
+                expected: program.clone(),
+                hint: "the program name",
+            },
+            Challenge::Resource(resource) => Confirmation::Typed {
+                expected: resource.clone(),
+                hint: "the target name",
+            },
+            Challenge::RandomWord => {
+                let index = uuid::Uuid::new_v4().as_bytes()[0] as usize % CHALLENGE_WORDS.len();
+                Confirmation::Typed {
+                    expected: CHALLENGE_WORDS[index].to_string(),
+                    hint: "the challenge word",
+                }
+            }
+        }
+    }
+
+    /// Prompt for typed tiers; `None` means the caller's usual `[y/N]` prompt applies
+    pub fn prompt(&self) -> Option<String> {
+        match self {
+            Confirmation::YesNo => None,
+            Confirmation::Typed { expected, hint } => {
+                Some(format!("Type '{}' ({}) to execute, anything else cancels: ", expected, hint))
+            }
+        }
+    }
+
+    pub fn accepts(&self, input: &str) -> bool {
+        match self {
+            Confirmation::YesNo => matches!(input.trim().to_lowercase().as_str(), "y" | "yes"),
+            Confirmation::Typed { expected, .. } => input.trim() == expected,
+        }
+    }
+}
+
+/// How a safety verdict is presented, shared by the REPL, one-shot mode and the shell hook
+#[derive(Debug, PartialEq)]
+pub enum Review {
+    Safe,
+    Confirm { warning: String, confirmation: Confirmation },
+    /// Blocked now, or still cooling down from an earlier blocked command
+    Refuse(String),
+}
+
+/// Turns safety verdicts into confirmation tiers and enforces the cooldown after a block
+pub struct Gatekeeper {
+    cooldown: Duration,
+    /// Holds the time of the user's last blocked command
+    marker: PathBuf,
+}
+
+impl Gatekeeper {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            cooldown: Duration::from_secs(config.confirmation.cooldown_seconds),
+            marker: config.config_dir.join("last_blocked"),
+        }
+    }
+
+    pub fn review(&self, result: &SafetyResult) -> Review {
+        let (warning, confirmation) = match result {
+            SafetyResult::Safe => return Review::Safe,
+            SafetyResult::Blocked(reason) => {
+                self.record_block();
+                return Review::Refuse(format!("Command blocked: {}", reason));
+            }
+            SafetyResult::NeedsConfirmation(reason) => (reason.clone(), Confirmation::YesNo),
+            SafetyResult::NeedsTypedConfirmation { reason, challenge } => {
+                (reason.clone(), Confirmation::for_challenge(challenge))
+            }
+        };
+
+        if let Some(remaining) = self.cooldown_remaining() {
+            return Review::Refuse(format!(
+                "A command was blocked moments ago, confirmations are paused for another {}s. {}",
+                remaining.as_secs().max(1),
+                warning.lines().next().unwrap_or_default()
+            ));
+        }
+        Review::Confirm { warning, confirmation }
+    }
+
+    fn record_block(&self) {
+        if self.cooldown.is_zero() {
+            return;
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        if let Some(dir) = self.marker.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Err(e) = fs::write(&self.marker, now.to_string()) {
+            log::warn!("Failed to record blocked command for cooldown: {}", e);
+        }
+    }
+
+    fn cooldown_remaining(&self) -> Option<Duration> {
+        let blocked_at: u64 = fs::read_to_string(&self.marker).ok()?.trim().parse().ok()?;
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH + Duration::from_secs(blocked_at))
+            .unwrap_or_default();
+        self.cooldown.checked_sub(elapsed).filter(|remaining| !remaining.is_zero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_gatekeeper(dir: &TempDir, cooldown_seconds: u64) -> Gatekeeper {
+        let mut config = AppConfig::default();
+        config.config_dir = dir.path().to_path_buf();
+        config.confirmation.cooldown_seconds = cooldown_seconds;
+        Gatekeeper::new(&config)
+    }
+
+    #[test]
+    fn test_confirmation_tiers() {
+        assert!(Confirmation::YesNo.accepts("Y\n"));
+        assert!(!Confirmation::YesNo.accepts("rm"));
+
+        let program = Confirmation::for_challenge(&Challenge::Program("rm".to_string()));
+        assert!(program.accepts(" rm\n"));
+        assert!(!program.accepts("y"));
+        assert!(program.prompt().unwrap().contains("'rm'"));
+
+        match Confirmation::for_challenge(&Challenge::RandomWord) {
+            Confirmation::Typed { expected, .. } => assert!(CHALLENGE_WORDS.contains(&expected.as_str())),
+            other => panic!("Expected a challenge word, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cooldown_after_block() {
+        let dir = TempDir::new().unwrap();
+        let gatekeeper = test_gatekeeper(&dir, 30);
+        let confirm = SafetyResult::NeedsConfirmation("Destructive command 'rm' requires confirmation".to_string());
+
+        assert!(matches!(gatekeeper.review(&confirm), Review::Confirm { confirmation: Confirmation::YesNo, .. }));
+        assert!(matches!(gatekeeper.review(&SafetyResult::Blocked("no".to_string())), Review::Refuse(_)));
+
+        // Confirmations are refused during the cooldown, safe commands are unaffected
+        match gatekeeper.review(&confirm) {
+            Review::Refuse(message) => assert!(message.contains("paused")),
+            other => panic!("Expected cooldown refusal, got {:?}", other),
+        }
+        assert_eq!(gatekeeper.review(&SafetyResult::Safe), Review::Safe);
+
+        // A zero cooldown never pauses
+        let dir = TempDir::new().unwrap();
+        let disabled = test_gatekeeper(&dir, 0);
+        disabled.review(&SafetyResult::Blocked("no".to_string()));
+        assert!(matches!(disabled.review(&confirm), Review::Confirm { .. }));
+    }
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use tokio::process::Command as TokioCommand;
+
+/// Variables every command needs, kept even in allowlist mode
+const ESSENTIAL: &[&str] = &["PATH", "HOME", "USER", "LOGNAME", "SHELL", "TERM", "LANG", "LC_*", "TMPDIR", "PWD", "TZ"];
+
+/// Which inherited variables generated commands get (`[environment]` in config.toml)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentConfig {
+    /// When non-empty, only these variables (plus PATH, HOME, TERM and the like) are passed
+    /// on and `deny` is ignored. `*` matches any run of characters
+    pub allow: Vec<String>,
+    /// Variables withheld from commands; matched case-insensitively
+    pub deny: Vec<String>,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            deny: [
+                "OPENAI_*", "*_API_KEY", "*_APIKEY", "*_TOKEN", "*_SECRET", "*_SECRET_*", "*_PASSWORD",
+                "*_PASSWD", "*_PRIVATE_KEY", "*_ACCESS_KEY", "*_ACCESS_KEY_ID", "*_CREDENTIALS",
+                "DATABASE_URL",
+            ]
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect(),
+        }
+    }
+}
+
+impl EnvironmentConfig {
+    /// The variable is kept from commands and from anything sent to the model
+    pub fn withholds(&self, name: &str) -> bool {
+        if self.allow.is_empty() {
+            matches_any(name, &self.deny)
+        } else {
+            !matches_any(name, ESSENTIAL) && !matches_any(name, &self.allow)
+        }
+    }
+}
+
+/// The environment a command runs with, and the names of the variables left out
+#[derive(Debug, Clone, Default)]
+pub struct ChildEnvironment {
+    vars: Vec<(OsString, OsString)>,
+    cwd: Option<PathBuf>,
+    pub withheld: Vec<String>,
+}
+
+impl ChildEnvironment {
+    /// Filter this process's environment
+    pub fn inherit(config: &EnvironmentConfig) -> Self {
+        Self::from_vars(config, std::env::vars_os())
+    }
+
+    pub fn from_vars(config: &EnvironmentConfig, vars: impl IntoIterator<Item = (OsString, OsString)>) -> Self {
+        let mut environment = Self::default();
+        for (name, value) in vars {
+            let passes = match name.to_str() {
+                Some(name) => !config.withholds(name),
+                // Names that are not UTF-8 cannot be matched against patterns, so only pass them when nothing is denied
+                None => config.allow.is_empty() && config.deny.is_empty(),
+            };
+            if passes {
+                environment.vars.push((name, value));
+            } else {
+                environment.withheld.push(name.to_string_lossy().into_owned());
+            }
+        }
+        environment.withheld.sort();
+        environment
+    }
+
+    /// Run the command in `cwd` instead of this process's working directory
+    pub fn in_dir(mut self, cwd: PathBuf) -> Self {
+        self.cwd = Some(cwd);
+        self
+    }
+
+    pub fn cwd(&self) -> Option<&PathBuf> {
+        self.cwd.as_ref()
+    }
+
+    /// Replace the command's inherited environment with the filtered one
+    pub fn apply(&self, cmd: &mut TokioCommand) {
+        cmd.env_clear().envs(self.vars.iter().map(|(name, value)| (name, value)));
+        if let Some(cwd) = &self.cwd {
+            cmd.current_dir(cwd);
+        }
+    }
+}
+
+fn matches_any<S: AsRef<str>>(name: &str, patterns: &[S]) -> bool {
+    patterns.iter().any(|pattern| glob_match(&pattern.as_ref().to_ascii_uppercase(), &name.to_ascii_uppercase()))
+}
+
+/// `*` matches any run of characters; everything else matches literally
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || !name[first.len()..].ends_with(last) || name.len() < first.len() + last.len() {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(names: &[&str]) -> Vec<(OsString, OsString)> {
+        names.iter().map(|name| (OsString::from(name), OsString::from("value"))).collect()
+    }
+
+    impl ChildEnvironment {
+        fn contains(&self, name: &str) -> bool {
+            self.vars.iter().any(|(var, _)| var == name)
+        }
+    }
+
+    #[test]
+    fn test_default_denylist() {
+        let environment = ChildEnvironment::from_vars(
+            &EnvironmentConfig::default(),
+            vars(&["PATH", "HOME", "OPENAI_API_KEY", "GITHUB_TOKEN", "aws_secret_access_key", "EDITOR", "SSH_AUTH_SOCK", "TOKENIZERS_PARALLELISM"]),
+        );
+        assert_eq!(environment.withheld, vec!["GITHUB_TOKEN", "OPENAI_API_KEY", "aws_secret_access_key"]);
+        assert!(environment.contains("PATH"));
+        assert!(environment.contains("SSH_AUTH_SOCK"));
+        assert!(environment.contains("TOKENIZERS_PARALLELISM"));
+    }
+
+    #[test]
+    fn test_allowlist() {
+        let config = EnvironmentConfig {
+            allow: vec!["EDITOR".to_string(), "KUBE*".to_string()],
+            ..EnvironmentConfig::default()
+        };
+        let environment = ChildEnvironment::from_vars(&config, vars(&["PATH", "LC_ALL", "EDITOR", "KUBECONFIG", "GITHUB_TOKEN", "NPM_CONFIG_PREFIX"]));
+        assert_eq!(environment.withheld, vec!["GITHUB_TOKEN", "NPM_CONFIG_PREFIX"]);
+        assert!(environment.contains("LC_ALL"));
+        assert!(environment.contains("KUBECONFIG"));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*_TOKEN", "GITHUB_TOKEN"));
+        assert!(!glob_match("*_TOKEN", "_TOKENS"));
+        assert!(glob_match("*_SECRET_*", "AWS_SECRET_ACCESS_KEY"));
+        assert!(glob_match("A*B*C", "AXXBYYC"));
+        assert!(!glob_match("A*B*C", "AC"));
+        assert!(glob_match("PATH", "PATH"));
+        assert!(!glob_match("PATH", "PATHS"));
+    }
+}
@@ -1,31 +1,90 @@
 use anyhow::{Context, Result};
+use std::ffi::OsString;
 use std::process::Stdio;
 use std::time::Duration;
 use std::io::Write;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::process::{Child, Command as TokioCommand};
+use tokio::sync::{mpsc, watch, Mutex};
 use tokio::time::timeout;
 use tempfile::NamedTempFile;
 use std::fs;
 use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::ExitStatusExt;
+
+use crate::environment::{ChildEnvironment, EnvironmentConfig};
+use crate::limits::{Limit, LimitHit, Limits, LimitsConfig, Risk, DEFAULT_TIMEOUT_SECS};
+use crate::output::{self, OutputChunk, OutputConfig, OutputStats, Tee};
+use crate::process::{self, Foreground, Target};
+use crate::pty;
+use crate::safety::privilege::{self, PrivilegeConfig};
+use crate::sandbox::SandboxPolicy;
+use crate::session::{SessionContext, SessionState, SessionStatus, ShellSession};
+use crate::shell::{Shell, ShellConfig};
+use crate::usage::ResourceUsage;
 
 #[derive(Debug)]
 pub struct ExecutionResult {
     pub success: bool,
     pub exit_code: Option<i32>,
+    /// Captured output; the middle of long output is left out, see `stdout_stats`
     pub stdout: String,
     pub stderr: String,
+    pub stdout_stats: OutputStats,
+    pub stderr_stats: OutputStats,
     pub duration: Duration,
+    pub sandboxed: bool,
+    /// Ran in a pseudo-terminal: the output was shown as it happened and `stdout` holds its transcript
+    pub interactive: bool,
+    /// The output was already echoed to the terminal, so callers should not print it again
+    pub streamed: bool,
+    /// Stopped by the user with Ctrl+C or SIGTERM rather than finishing on its own
+    pub interrupted: bool,
+    /// The `[limits]` policy limit the command ran into, if it was stopped by one
+    pub limit_hit: Option<LimitHit>,
+    /// CPU time, memory and I/O of the command and the processes it waited for, when known
+    pub usage: Option<ResourceUsage>,
+}
+
+impl ExecutionResult {
+    /// Part of the output is missing from `stdout` or `stderr`
+    pub fn truncated(&self) -> bool {
+        self.stdout_stats.truncated || self.stderr_stats.truncated
+    }
 }
 
 pub struct CommandExecutor {
     timeout_duration: Duration,
+    privilege: PrivilegeConfig,
+    environment: EnvironmentConfig,
+    output: Tee,
+    capture: OutputConfig,
+    forward_signals: bool,
+    shell: Shell,
+    persistent: bool,
+    session: Mutex<Option<ShellSession>>,
+    /// Where commands start when there is no session, for executors detached from one
+    origin: Option<SessionState>,
+    detached: bool,
+    process_group: Option<watch::Sender<Option<i32>>>,
+    limits: LimitsConfig,
 }
 
 impl Default for CommandExecutor {
     fn default() -> Self {
         Self {
-            timeout_duration: Duration::from_secs(300), // 5 minutes default
+            timeout_duration: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            privilege: PrivilegeConfig::default(),
+            environment: EnvironmentConfig::default(),
+            output: Tee::default(),
+            capture: OutputConfig::default(),
+            forward_signals: false,
+            shell: Shell::default(),
+            persistent: false,
+            session: Mutex::new(None),
+            origin: None,
+            detached: false,
+            process_group: None,
+            limits: LimitsConfig::default(),
         }
     }
 }
@@ -38,135 +97,474 @@ impl CommandExecutor {
     pub fn with_timeout(timeout_secs: u64) -> Self {
         Self {
             timeout_duration: Duration::from_secs(timeout_secs),
+            ..Self::default()
         }
     }
 
+    /// Use the askpass helper from `[safety.privilege]` when commands need sudo
+    pub fn with_privilege(mut self, config: &PrivilegeConfig) -> Self {
+        self.privilege = config.clone();
+        self
+    }
+
+    /// Filter the inherited environment with `[environment]` instead of the default denylist
+    pub fn with_environment(mut self, config: &EnvironmentConfig) -> Self {
+        self.environment = config.clone();
+        self
+    }
+
+    /// Apply the `[limits]` execution policy: resource limits set in the command's process
+    /// before it starts, and a wall-clock timeout in place of the executor's own
+    pub fn with_limits(mut self, config: &LimitsConfig) -> Self {
+        self.limits = config.clone();
+        self
+    }
+
+    /// Run commands with the shell from `[shell]` instead of $SHELL
+    pub fn with_shell(mut self, config: &ShellConfig) -> Self {
+        self.shell = Shell::detect(config);
+        self
+    }
+
+    pub fn shell(&self) -> &Shell {
+        &self.shell
+    }
+
+    /// Echo output to the terminal while the command runs instead of only capturing it
+    pub fn with_live_output(mut self, echo: bool) -> Self {
+        self.output.echo = echo;
+        self
+    }
+
+    /// Keep as much output as `[output]` allows instead of the default 256 KiB head and tail
+    pub fn with_capture(mut self, config: &OutputConfig) -> Self {
+        self.capture = config.clone();
+        self
+    }
+
+    /// Pass Ctrl+C, SIGTERM and Ctrl+Z on to the running command's process group. This
+    /// installs process-wide handlers, so only applications that own the terminal should use it
+    pub fn with_signal_forwarding(mut self, forward: bool) -> Self {
+        self.forward_signals = forward;
+        self
+    }
+
+    /// Run one-line commands in a long-lived shell, so directory changes, exported variables
+    /// and aliases carry over. Scripts, terminal, elevated and sandboxed runs still get a
+    /// fresh process, started in the session's directory with its environment
+    pub fn with_persistent_session(mut self, persistent: bool) -> Self {
+        self.persistent = persistent;
+        self
+    }
+
+    /// Where the persistent session is and what its commands changed, once it has started
+    pub async fn session_context(&self) -> Option<SessionContext> {
+        self.session.lock().await.as_ref().filter(|session| !session.is_closed()).map(ShellSession::context)
+    }
+
+    async fn session_state(&self) -> Option<SessionState> {
+        let session = self.session.lock().await.as_ref().filter(|session| !session.is_closed()).map(|session| session.state().clone());
+        session.or_else(|| self.origin.clone())
+    }
+
+    /// An executor for commands that run alongside this one's, e.g. in the background: the
+    /// same settings, started where the session is, but without terminal output, signal
+    /// forwarding or a session of its own. It refuses commands that need the terminal
+    pub async fn detached(&self) -> Self {
+        Self {
+            timeout_duration: self.timeout_duration,
+            privilege: self.privilege.clone(),
+            environment: self.environment.clone(),
+            capture: self.capture.clone(),
+            shell: self.shell.clone(),
+            origin: self.session_state().await,
+            detached: true,
+            limits: self.limits.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// The command would run in a pseudo-terminal or may ask for a password there
+    pub async fn needs_terminal(&self, command: &str) -> bool {
+        let cwd = match self.session_state().await {
+            Some(state) => state.cwd,
+            None => std::env::current_dir().unwrap_or_else(|_| "/".into()),
+        };
+        pty::needs_terminal(command) || !privilege::analyze(command, &cwd).is_empty()
+    }
+
+    /// Report the process group of each command as it starts, so it can be signalled from elsewhere
+    pub fn with_process_group_sender(mut self, sender: watch::Sender<Option<i32>>) -> Self {
+        self.process_group = Some(sender);
+        self
+    }
+
+    /// Send output chunks to `sender` as they are read, stdout and stderr interleaved in
+    /// arrival order. Pseudo-terminal runs are not streamed
+    pub fn with_output_channel(mut self, sender: mpsc::Sender<OutputChunk>) -> Self {
+        self.output.sender = Some(sender);
+        self
+    }
+
+    /// Execute a command, in a pseudo-terminal when it needs one (editors, pagers, prompts)
     pub async fn execute(&self, command: &str) -> Result<ExecutionResult> {
+        self.run(command, None, false, Risk::Safe).await
+    }
+
+    /// Execute a command that passed safety review, with the limits for its risk tier, in a
+    /// pseudo-terminal when `interactive` or when it needs one
+    pub async fn execute_reviewed(&self, command: &str, risk: Risk, interactive: bool) -> Result<ExecutionResult> {
+        self.run(command, None, interactive, risk).await
+    }
+
+    /// Execute a command in a pseudo-terminal even if it is not known to need one, e.g.
+    /// because the model flagged it as interactive. Falls back to captured output when
+    /// there is no terminal to pass through
+    pub async fn execute_interactive(&self, command: &str) -> Result<ExecutionResult> {
+        self.run(command, None, true, Risk::Safe).await
+    }
+
+    /// Execute a command with a read-only filesystem (except the policy's writable
+    /// paths) and no network access, so its effects can be inspected first
+    pub async fn execute_sandboxed(&self, command: &str, policy: &SandboxPolicy) -> Result<ExecutionResult> {
+        self.run(command, Some(policy), false, Risk::Safe).await
+    }
+
+    async fn run(&self, command: &str, sandbox: Option<&SandboxPolicy>, wants_terminal: bool, risk: Risk) -> Result<ExecutionResult> {
         let start_time = std::time::Instant::now();
         
         log::debug!("Executing command: {}", command);
-        
+        let limits = self.limits.policy(command, risk);
+
+        // Commands run where the persistent session is, if there is one
+        let state = self.session_state().await;
+        let cwd = match &state {
+            Some(state) => state.cwd.clone(),
+            None => std::env::current_dir().unwrap_or_else(|_| "/".into()),
+        };
+
+        // Sandboxed runs cannot gain privileges, so only real runs prepare for a password prompt
+        let elevations = if sandbox.is_none() { privilege::analyze(command, &cwd) } else { Vec::new() };
+        if elevations.iter().any(|elevation| elevation.tool == "sudo") {
+            self.authenticate_sudo().await?;
+        }
+        // Sandboxed runs stay captured so their effects can be inspected
+        let terminal = sandbox.is_none() && pty::is_available() && (wants_terminal || pty::needs_terminal(command));
+        let interactive = !elevations.is_empty();
+        if self.detached && (terminal || interactive) {
+            anyhow::bail!("'{}' needs the terminal, so it cannot run detached", command);
+        }
+
+        let environment = match state {
+            Some(state) => self.filter_env(state.vars()).in_dir(cwd),
+            None => self.filter_env(std::env::vars_os()),
+        };
+        if !environment.withheld.is_empty() && log::log_enabled!(log::Level::Debug) {
+            eprintln!("🔒 Withheld from the command environment: {}", environment.withheld.join(", "));
+        }
+
+        // Commands the shell cannot parse run on their own, so a syntax error cannot break the
+        // session, and so do commands with resource limits, which would also bind the session
+        let in_session = self.persistent && sandbox.is_none() && !terminal && !interactive && !limits.has_rlimits()
+            && command.lines().count() == 1 && self.validate_syntax(command).await.unwrap_or(false);
+        if in_session {
+            return self.execute_in_session(command, &environment, &limits).await.map(|mut result| {
+                result.duration = start_time.elapsed();
+                result
+            });
+        }
+
         // Check if this is a multi-line script
         if command.lines().count() > 1 {
-            self.execute_script(command).await
+            self.execute_script(command, sandbox, interactive, terminal, &environment, &limits).await
         } else {
-            self.execute_single_command(command).await
+            self.execute_single_command(command, sandbox, interactive, terminal, &environment, &limits).await
         }.map(|mut result| {
             result.duration = start_time.elapsed();
+            result.sandboxed = sandbox.is_some();
             result
         })
     }
 
-    async fn execute_single_command(&self, command: &str) -> Result<ExecutionResult> {
-        let mut cmd = TokioCommand::new("/bin/zsh");
-        cmd.arg("-c")
-           .arg(command)
-           .stdout(Stdio::piped())
-           .stderr(Stdio::piped())
-           .stdin(Stdio::null());
+    /// Ask for the sudo password up front, while the terminal is free: through the askpass
+    /// helper when one is configured, otherwise on the terminal. The command itself then
+    /// runs with sudo's cached credentials while its output is captured
+    async fn authenticate_sudo(&self) -> Result<()> {
+        // Nothing to ask when credentials are cached or no password is required
+        let cached = TokioCommand::new("sudo")
+            .args(["-n", "-v"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .map(|status| status.success())
+            .unwrap_or(false);
+        if cached {
+            return Ok(());
+        }
+
+        let mut cmd = TokioCommand::new("sudo");
+        match &self.privilege.askpass {
+            Some(helper) => {
+                cmd.args(["-A", "-v"]).env("SUDO_ASKPASS", helper).stdin(Stdio::null());
+            }
+            None => {
+                cmd.arg("-v").stdin(Stdio::inherit()).stdout(Stdio::inherit()).stderr(Stdio::inherit());
+            }
+        }
+
+        let status = cmd.status().await.context("Failed to run sudo")?;
+        if !status.success() {
+            anyhow::bail!("sudo authentication failed, the command was not run");
+        }
+        Ok(())
+    }
+
+    /// Elevated commands keep the terminal on stdin so doas, su and pkexec can prompt too
+    fn configure_stdin(&self, cmd: &mut TokioCommand, interactive: bool) {
+        if interactive {
+            cmd.stdin(Stdio::inherit());
+            if let Some(helper) = &self.privilege.askpass {
+                cmd.env("SUDO_ASKPASS", helper);
+            }
+        } else {
+            cmd.stdin(Stdio::null());
+        }
+    }
+
+    /// The environment a command gets from `vars` after `[environment]` filtering
+    fn filter_env(&self, vars: impl IntoIterator<Item = (OsString, OsString)>) -> ChildEnvironment {
+        ChildEnvironment::from_vars(&self.environment, vars)
+    }
+
+    async fn execute_single_command(&self, command: &str, sandbox: Option<&SandboxPolicy>, interactive: bool, terminal: bool, environment: &ChildEnvironment, limits: &Limits) -> Result<ExecutionResult> {
+        let mut cmd = self.shell.command(command);
+        cmd.stdout(Stdio::piped())
+           .stderr(Stdio::piped());
+        environment.apply(&mut cmd);
+        self.configure_stdin(&mut cmd, interactive);
+        apply_limits(&mut cmd, limits);
+
+        log::debug!("Spawning command: {} -c {}", self.shell.path.display(), self.shell.quote(command));
 
-        log::debug!("Spawning command: /bin/zsh -c '{}'", command);
+        let _guard = sandbox.map(|policy| policy.apply(&mut cmd)).transpose()?;
+        if terminal {
+            return self.run_in_terminal(cmd, limits).await;
+        }
+        // Commands that may prompt on the terminal stay in our process group so they can read from it
+        if !interactive {
+            cmd.process_group(0);
+        }
 
         let child = cmd.spawn()
             .context("Failed to spawn command")?;
 
-        self.wait_for_completion(child).await
+        self.wait_for_completion(child, !interactive, limits).await
     }
 
-    async fn execute_script(&self, script: &str) -> Result<ExecutionResult> {
+    async fn execute_script(&self, script: &str, sandbox: Option<&SandboxPolicy>, interactive: bool, terminal: bool, environment: &ChildEnvironment, limits: &Limits) -> Result<ExecutionResult> {
         // Create a temporary script file
         let mut temp_file = NamedTempFile::new()
             .context("Failed to create temporary script file")?;
 
         // Write the script content
-        temp_file.write_all(b"#!/bin/zsh\nset -e\n\n")
+        temp_file.write_all(self.shell.script_header().as_bytes())
             .context("Failed to write script header")?;
         temp_file.write_all(script.as_bytes())
             .context("Failed to write script content")?;
         temp_file.flush()
             .context("Failed to flush script file")?;
 
+        // Close the write handle before spawning, or exec fails with "Text file busy"; the
+        // path still removes the file when dropped
+        let path = temp_file.into_temp_path();
+
         // Make the script executable
-        let path = temp_file.path();
-        let mut perms = fs::metadata(path)?.permissions();
+        let mut perms = fs::metadata(&path)?.permissions();
         perms.set_mode(0o755);
-        fs::set_permissions(path, perms)?;
+        fs::set_permissions(&path, perms)?;
 
         // Execute the script
-        let mut cmd = TokioCommand::new(path);
+        let mut cmd = TokioCommand::new(&path);
         cmd.stdout(Stdio::piped())
-           .stderr(Stdio::piped())
-           .stdin(Stdio::null());
+           .stderr(Stdio::piped());
+        environment.apply(&mut cmd);
+        self.configure_stdin(&mut cmd, interactive);
+        apply_limits(&mut cmd, limits);
 
         log::debug!("Executing script: {}", path.display());
 
+        let _guard = sandbox.map(|policy| policy.apply(&mut cmd)).transpose()?;
+        if terminal {
+            return self.run_in_terminal(cmd, limits).await;
+        }
+        if !interactive {
+            cmd.process_group(0);
+        }
+
         let child = cmd.spawn()
             .context("Failed to spawn script")?;
 
-        self.wait_for_completion(child).await
+        self.wait_for_completion(child, !interactive, limits).await
     }
 
-    async fn wait_for_completion(&self, mut child: Child) -> Result<ExecutionResult> {
+    /// Run a command in the persistent session, starting it first when there is none or the
+    /// last command ended it
+    async fn execute_in_session(&self, command: &str, environment: &ChildEnvironment, limits: &Limits) -> Result<ExecutionResult> {
+        let mut slot = self.session.lock().await;
+        if slot.as_ref().is_none_or(ShellSession::is_closed) {
+            log::debug!("Starting a shell session with {}", self.shell);
+            *slot = Some(ShellSession::start(&self.shell, environment).await?);
+        }
+        let session = slot.as_mut().context("The shell session is not running")?;
+        let pgid = session.pgid().context("The shell session has exited")?;
+        let foreground = self.forward_signals.then(|| Foreground::enter(Target::Group(pgid)));
+
+        let limit = limits.timeout().unwrap_or(self.timeout_duration);
+        let run = match session.run(command, &self.output, &self.capture, limit).await {
+            Ok(run) => run,
+            // The session's state is unknown after a broken pipe, so it is replaced
+            Err(e) => {
+                if let Some(session) = slot.take() {
+                    session.terminate().await;
+                }
+                return Err(e);
+            }
+        };
+
+        let (exit_code, signal, limit_hit) = match run.status {
+            // The shell reports a command killed by a signal as 128 plus the signal number
+            SessionStatus::Exited(code) => (Some(code), (code > 128).then(|| code - 128), None),
+            SessionStatus::Closed(status) => (status.code(), status.signal(), None),
+            SessionStatus::TimedOut => (None, None, Some(LimitHit { limit: Limit::Timeout, value: limit.as_secs() })),
+        };
+        let ((stdout, stdout_stats), (stderr, stderr_stats)) = (run.stdout.finish(), run.stderr.finish());
+        Ok(ExecutionResult {
+            success: exit_code == Some(0),
+            exit_code,
+            stdout,
+            stderr,
+            stdout_stats,
+            stderr_stats,
+            duration: Duration::default(), // Will be set by caller
+            sandboxed: false,
+            interactive: false,
+            streamed: self.output.echo,
+            interrupted: signal == Some(libc::SIGINT) || foreground.as_ref().is_some_and(Foreground::interrupted),
+            limit_hit,
+            usage: run.usage,
+        })
+    }
+
+    /// Interactive sessions are driven by the user, so they run without the timeout
+    async fn run_in_terminal(&self, cmd: TokioCommand, limits: &Limits) -> Result<ExecutionResult> {
+        log::debug!("Running in a pseudo-terminal");
+        let session = pty::run(cmd, &self.capture).await?;
+        Ok(ExecutionResult {
+            success: session.status.success(),
+            exit_code: session.status.code(),
+            limit_hit: limits.diagnose(session.status, &session.transcript),
+            stdout: session.transcript,
+            stderr: String::new(),
+            stdout_stats: session.stats,
+            stderr_stats: OutputStats::default(),
+            duration: Duration::default(), // Will be set by caller
+            sandboxed: false,
+            interactive: true,
+            streamed: true,
+            interrupted: session.status.signal() == Some(libc::SIGINT),
+            usage: Some(session.usage),
+        })
+    }
+
+    /// Wait for the command with its time limit, forwarding signals to it when enabled. A
+    /// `grouped` command leads its own process group, which is stopped as a whole
+    async fn wait_for_completion(&self, mut child: Child, grouped: bool, limits: &Limits) -> Result<ExecutionResult> {
+        let pid = child.id().context("Command exited before it could be tracked")? as i32;
+        let target = if grouped { Target::Group(pid) } else { Target::Shared(pid) };
+        if let (true, Some(sender)) = (grouped, &self.process_group) {
+            let _ = sender.send(Some(pid));
+        }
+        let foreground = self.forward_signals.then(|| Foreground::enter(target));
+        let limit = limits.timeout().unwrap_or(self.timeout_duration);
+
         // Set up stdout and stderr capture
         let stdout = child.stdout.take()
             .context("Failed to capture stdout")?;
         let stderr = child.stderr.take()
             .context("Failed to capture stderr")?;
 
-        // Run with timeout
-        let result = timeout(self.timeout_duration, async {
-            // Capture output streams
-            let (stdout_output, stderr_output, exit_status) = tokio::try_join!(
-                self.read_stream(stdout),
-                self.read_stream(stderr),
-                async { child.wait().await.map_err(|e| anyhow::anyhow!(e)) }
+        // Reaped with its resource usage; `child` stays alive until then so tokio leaves it be
+        let mut exit = Box::pin(process::reap(pid));
+        let mut exited = false;
+
+        // Pass output on as it arrives while waiting; a command past its limit is stopped and
+        // its output up to then kept. Processes that left the group and still hold the pipes
+        // get a little longer before the run is given up on
+        let result = timeout(limit + process::GRACE_PERIOD + Duration::from_secs(1), async {
+            let ((stdout_output, stderr_output), ((exit_status, usage), timed_out)) = tokio::try_join!(
+                output::pump(stdout, stderr, &self.output, &self.capture),
+                async {
+                    let exit = match timeout(limit, &mut exit).await {
+                        Ok(exit) => (exit?, false),
+                        Err(_) => (process::stop(&mut exit, target, process::GRACE_PERIOD).await?, true),
+                    };
+                    exited = true;
+                    Ok(exit)
+                }
             )?;
 
-            Ok::<(String, String, std::process::ExitStatus), anyhow::Error>((
-                stdout_output,
-                stderr_output, 
-                exit_status
-            ))
+            Ok::<_, anyhow::Error>((stdout_output.finish(), stderr_output.finish(), exit_status, usage, timed_out))
         }).await;
 
         match result {
-            Ok(Ok((stdout_output, stderr_output, exit_status))) => {
+            Ok(Ok(((stdout_output, stdout_stats), (stderr_output, stderr_stats), exit_status, usage, timed_out))) => {
+                let limit_hit = if timed_out {
+                    Some(LimitHit { limit: Limit::Timeout, value: limit.as_secs() })
+                } else {
+                    limits.diagnose(exit_status, &stderr_output)
+                };
                 Ok(ExecutionResult {
                     success: exit_status.success(),
                     exit_code: exit_status.code(),
                     stdout: stdout_output,
                     stderr: stderr_output,
+                    stdout_stats,
+                    stderr_stats,
                     duration: Duration::default(), // Will be set by caller
+                    sandboxed: false,
+                    interactive: false,
+                    streamed: self.output.echo,
+                    interrupted: !timed_out && (exit_status.signal() == Some(libc::SIGINT)
+                        || foreground.as_ref().is_some_and(Foreground::interrupted)),
+                    limit_hit,
+                    usage: Some(usage),
                 })
             }
             Ok(Err(e)) => Err(e),
             Err(_) => {
-                // Timeout occurred, kill the process
-                if let Err(kill_err) = child.kill().await {
+                // Output stayed open long after the limit, stop everything still in the group. A
+                // command that already exited cannot be waited for again
+                let stopped = if exited {
+                    process::stop(&mut std::future::ready(Ok(())), target, process::GRACE_PERIOD).await
+                } else {
+                    process::stop(&mut exit, target, process::GRACE_PERIOD).await.map(drop)
+                };
+                if let Err(kill_err) = stopped {
                     log::warn!("Failed to kill timed-out process: {}", kill_err);
                 }
                 
                 anyhow::bail!(
                     "Command timed out after {} seconds", 
-                    self.timeout_duration.as_secs()
+                    limit.as_secs()
                 );
             }
         }
     }
 
-    async fn read_stream<R>(&self, mut reader: R) -> Result<String>
-    where
-        R: AsyncReadExt + Unpin,
-    {
-        let mut buffer = Vec::new();
-        reader.read_to_end(&mut buffer).await
-            .context("Failed to read from stream")?;
-        
-        Ok(String::from_utf8_lossy(&buffer).to_string())
-    }
-
     pub async fn test_command_exists(&self, command: &str) -> bool {
-        let result = self.execute(&format!("which {}", command)).await;
+        let result = self.execute(&format!("which {}", self.shell.quote(command))).await;
         match result {
             Ok(exec_result) => exec_result.success,
             Err(_) => false,
@@ -194,11 +592,22 @@ impl CommandExecutor {
 
     pub async fn validate_syntax(&self, command: &str) -> Result<bool> {
         // Use shell's built-in syntax checking
-        let check_cmd = format!("/bin/zsh -n -c '{}'", command.replace("'", "'\"'\"'"));
-        
-        match self.execute(&check_cmd).await {
-            Ok(result) => Ok(result.success),
-            Err(_) => Ok(false),
+        let status = self.shell.syntax_check(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+        Ok(status.map(|status| status.success()).unwrap_or(false))
+    }
+}
+
+/// Set the policy's resource limits in the child just before it runs the command
+fn apply_limits(cmd: &mut TokioCommand, limits: &Limits) {
+    if limits.has_rlimits() {
+        let limits = limits.clone();
+        unsafe {
+            cmd.pre_exec(move || limits.apply());
         }
     }
 }
@@ -217,6 +626,7 @@ mod tests {
         assert_eq!(result.stdout.trim(), "hello world");
         assert!(result.stderr.is_empty());
         assert!(result.duration.as_millis() > 0);
+        assert!(result.usage.is_some_and(|usage| usage.max_rss().is_some()));
     }
 
     #[tokio::test]
@@ -247,6 +657,51 @@ echo "Done"
         assert!(result.stdout.contains("Done"));
     }
 
+    #[tokio::test]
+    async fn test_secrets_withheld_from_environment() {
+        let executor = CommandExecutor::new();
+        let vars = [("PATH", "/usr/bin:/bin"), ("COMMANDGPT_EXECUTOR_TEST_TOKEN", "s3cret")]
+            .map(|(name, value)| (name.into(), value.into()));
+        let environment = executor.filter_env(vars);
+        assert_eq!(environment.withheld, vec!["COMMANDGPT_EXECUTOR_TEST_TOKEN"]);
+
+        let mut cmd = TokioCommand::new("/usr/bin/env");
+        environment.apply(&mut cmd);
+        let output = cmd.output().await.unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(!stdout.contains("s3cret"));
+        assert!(stdout.contains("PATH=/usr/bin:/bin"));
+    }
+
+    #[tokio::test]
+    async fn test_output_channel() {
+        let (sender, mut receiver) = mpsc::channel(16);
+        let executor = CommandExecutor::new().with_output_channel(sender);
+        let result = executor.execute("echo out; echo err >&2").await.unwrap();
+        drop(executor);
+
+        let mut streamed = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            streamed.push(chunk.stream);
+        }
+        assert!(streamed.contains(&output::Stream::Stdout));
+        assert!(streamed.contains(&output::Stream::Stderr));
+        assert_eq!(result.stdout, "out\n");
+        assert!(!result.streamed);
+    }
+
+    #[tokio::test]
+    async fn test_output_capture_is_bounded() {
+        let executor = CommandExecutor::new().with_capture(&OutputConfig { head_bytes: 16, tail_bytes: 16, ..OutputConfig::default() });
+        let result = executor.execute("seq 1 10000").await.unwrap();
+
+        assert!(result.truncated());
+        assert_eq!(result.stdout_stats.lines, 10000);
+        assert!(result.stdout.starts_with("1\n2\n"));
+        assert!(result.stdout.ends_with("9999\n10000\n"));
+        assert!(result.stdout.len() < 100);
+    }
+
     #[tokio::test]
     async fn test_command_exists() {
         let executor = CommandExecutor::new();
@@ -276,10 +731,53 @@ echo "Done"
     #[tokio::test]
     async fn test_timeout() {
         let executor = CommandExecutor::with_timeout(1); // 1 second timeout
-        let result = executor.execute("sleep 5").await;
+        let result = executor.execute("echo started; sleep 5").await.unwrap();
         
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("timed out"));
+        assert!(!result.success);
+        assert_eq!(result.limit_hit, Some(LimitHit { limit: Limit::Timeout, value: 1 }));
+        assert_eq!(result.stdout, "started\n");
+    }
+
+    #[tokio::test]
+    async fn test_timeout_stops_whole_pipeline() {
+        let marker = tempfile::NamedTempFile::new().unwrap();
+        let path = marker.path().display().to_string();
+        let executor = CommandExecutor::with_timeout(1);
+        let result = executor.execute(&format!("(sleep 2; echo late > {}) | cat", path)).await.unwrap();
+        assert!(result.limit_hit.is_some());
+
+        // The subshell was in the command's process group, so it never got to write
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(std::fs::read_to_string(&path).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_limits_policy() {
+        let config: LimitsConfig = toml::from_str(r#"
+            [[rules]]
+            pattern = "^sh -c 'while"
+            cpu_secs = 1
+
+            [confirm]
+            timeout_secs = 1
+        "#).unwrap();
+        let executor = CommandExecutor::new().with_limits(&config);
+
+        let result = executor.execute("sh -c 'while :; do :; done'").await.unwrap();
+        assert_eq!(result.limit_hit, Some(LimitHit { limit: Limit::Cpu, value: 1 }));
+        assert!(!result.success);
+
+        let result = executor.execute_reviewed("sleep 3", Risk::Confirm, false).await.unwrap();
+        assert_eq!(result.limit_hit.map(|hit| hit.limit), Some(Limit::Timeout));
+        assert!(executor.execute("sleep 0.1").await.unwrap().limit_hit.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_command() {
+        let executor = CommandExecutor::new();
+        let result = executor.execute("kill -INT $$").await.unwrap();
+        assert!(result.interrupted);
+        assert!(!result.success);
     }
 
     #[tokio::test]
@@ -352,4 +850,30 @@ exit 0
         assert!(result.success);
         assert!(result.stdout.contains("special chars: !@#$%^&*()"));
     }
+
+    #[tokio::test]
+    async fn test_persistent_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let executor = CommandExecutor::new()
+            .with_shell(&ShellConfig { program: Some("/bin/sh".to_string()) })
+            .with_persistent_session(true);
+        assert!(executor.session_context().await.is_none());
+
+        executor.execute(&format!("cd '{}' && export STAGE=test", dir.display())).await.unwrap();
+        let result = executor.execute("echo \"$STAGE in $(pwd)\"").await.unwrap();
+        assert_eq!(result.stdout.trim(), format!("test in {}", dir.display()));
+
+        // Scripts run on their own, but where the session is and with its variables
+        let result = executor.execute("pwd\necho $STAGE").await.unwrap();
+        assert_eq!(result.stdout, format!("{}\ntest\n", dir.display()));
+
+        let context = executor.session_context().await.unwrap();
+        assert_eq!(context.cwd, dir);
+        assert_eq!(context.changes, vec![("STAGE".to_string(), Some("test".to_string()))]);
+
+        // A command that ends the shell gets a fresh session next time
+        assert_eq!(executor.execute("exit 4").await.unwrap().exit_code, Some(4));
+        assert!(executor.execute("test -z \"$STAGE\"").await.unwrap().success);
+    }
 }
@@ -3,7 +3,14 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sled::{Db, IVec};
 use std::path::Path;
+use std::time::Duration;
 use crate::error::CommandGPTError;
+use crate::executor::ExecutionResult;
+use crate::output::{self, OutputStats};
+use crate::usage::ResourceUsage;
+
+/// Bytes of each stream stored per entry; the stats keep the real totals
+const STORED_OUTPUT_BYTES: usize = 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEntry {
@@ -14,6 +21,80 @@ pub struct HistoryEntry {
     pub exit_code: i32,
     pub timestamp: DateTime<Utc>,
     pub duration_ms: u64,
+    #[serde(default)]
+    pub stdout_stats: OutputStats,
+    #[serde(default)]
+    pub stderr_stats: OutputStats,
+    /// Stopped by the user rather than finishing on its own
+    #[serde(default)]
+    pub interrupted: bool,
+    /// The execution limit that stopped it, e.g. "CPU time limit of 30s"
+    #[serde(default)]
+    pub limit_hit: Option<String>,
+    /// The SSH host it ran on, for commands that did not run locally
+    #[serde(default)]
+    pub host: Option<String>,
+    /// CPU time, memory and I/O, for commands recorded since usage was collected
+    #[serde(default)]
+    pub usage: Option<ResourceUsage>,
+}
+
+impl HistoryEntry {
+    pub fn cpu_time(&self) -> Option<Duration> {
+        self.usage.map(|usage| usage.cpu_time())
+    }
+
+    /// The command as listed, prefixed with the host it ran on
+    fn label(&self) -> String {
+        match &self.host {
+            Some(host) => format!("{}: {}", host, self.command),
+            None => self.command.clone(),
+        }
+    }
+}
+
+/// Entries written before output stats were recorded, which were stored with bincode
+#[derive(Deserialize)]
+struct LegacyEntry {
+    id: u64,
+    command: String,
+    stdout: String,
+    stderr: String,
+    exit_code: i32,
+    timestamp: DateTime<Utc>,
+    duration_ms: u64,
+}
+
+impl From<LegacyEntry> for HistoryEntry {
+    fn from(entry: LegacyEntry) -> Self {
+        Self {
+            stdout_stats: OutputStats::of(&entry.stdout),
+            stderr_stats: OutputStats::of(&entry.stderr),
+            id: entry.id,
+            command: entry.command,
+            stdout: entry.stdout,
+            stderr: entry.stderr,
+            exit_code: entry.exit_code,
+            timestamp: entry.timestamp,
+            duration_ms: entry.duration_ms,
+            interrupted: false,
+            limit_hit: None,
+            host: None,
+            usage: None,
+        }
+    }
+}
+
+/// Entries are stored as JSON so new fields can have defaults; older ones are bincode
+fn decode(data: &[u8]) -> Option<HistoryEntry> {
+    serde_json::from_slice(data)
+        .ok()
+        .or_else(|| bincode::deserialize::<LegacyEntry>(data).ok().map(HistoryEntry::from))
+}
+
+fn stored(text: &str, mut stats: OutputStats) -> (String, OutputStats) {
+    let text = output::abridge(text, STORED_OUTPUT_BYTES, &mut stats);
+    (text, stats)
 }
 
 pub struct HistoryManager {
@@ -40,19 +121,56 @@ impl HistoryManager {
         exit_code: i32,
         duration_ms: u64,
     ) -> Result<u64> {
-        let id = self.next_id()?;
-        
-        let entry = HistoryEntry {
-            id,
+        let (stdout, stdout_stats) = stored(stdout, OutputStats::of(stdout));
+        let (stderr, stderr_stats) = stored(stderr, OutputStats::of(stderr));
+        self.insert(HistoryEntry {
+            id: 0,
             command: command.to_string(),
-            stdout: self.truncate_output(stdout, 1024),
-            stderr: self.truncate_output(stderr, 1024),
+            stdout,
+            stderr,
             exit_code,
             timestamp: Utc::now(),
             duration_ms,
-        };
+            stdout_stats,
+            stderr_stats,
+            interrupted: false,
+            limit_hit: None,
+            host: None,
+            usage: None,
+        })
+    }
 
-        let serialized = bincode::serialize(&entry)
+    /// Record a finished command with its real exit code, duration and output totals
+    pub async fn record_execution(&self, command: &str, result: &ExecutionResult) -> Result<u64> {
+        self.record_execution_on(None, command, result).await
+    }
+
+    /// Like `record_execution`, for a command that ran on `host` over SSH when one is given
+    pub async fn record_execution_on(&self, host: Option<&str>, command: &str, result: &ExecutionResult) -> Result<u64> {
+        let (stdout, stdout_stats) = stored(&result.stdout, result.stdout_stats);
+        let (stderr, stderr_stats) = stored(&result.stderr, result.stderr_stats);
+        self.insert(HistoryEntry {
+            id: 0,
+            command: command.to_string(),
+            stdout,
+            stderr,
+            exit_code: result.exit_code.unwrap_or(-1),
+            timestamp: Utc::now(),
+            duration_ms: result.duration.as_millis() as u64,
+            stdout_stats,
+            stderr_stats,
+            interrupted: result.interrupted,
+            limit_hit: result.limit_hit.map(|hit| hit.to_string()),
+            host: host.map(str::to_string),
+            usage: result.usage,
+        })
+    }
+
+    fn insert(&self, mut entry: HistoryEntry) -> Result<u64> {
+        let id = self.next_id()?;
+        entry.id = id;
+
+        let serialized = serde_json::to_vec(&entry)
             .context("Failed to serialize history entry")?;
 
         self.db.insert(id.to_be_bytes(), serialized)
@@ -79,7 +197,7 @@ impl HistoryManager {
         if let Some(data) = self.db.get(id.to_be_bytes())
             .context("Failed to get history entry")? {
             
-            let entry: HistoryEntry = bincode::deserialize(&data)
+            let entry = decode(&data)
                 .context("Failed to deserialize history entry")?;
             
             Ok(Some(entry))
@@ -97,7 +215,7 @@ impl HistoryManager {
                 source: Some(Box::new(e)),
             })?;
             
-            if let Ok(entry) = bincode::deserialize::<HistoryEntry>(&value) {
+            if let Some(entry) = decode(&value) {
                 entries.push(entry);
                 if entries.len() >= count {
                     break;
@@ -149,6 +267,28 @@ impl HistoryManager {
         Ok(removed)
     }
 
+    /// Entries recorded at or after `since`, newest first
+    pub fn entries_since(&self, since: DateTime<Utc>) -> Result<Vec<HistoryEntry>> {
+        let mut entries = Vec::new();
+
+        for item in self.db.iter().rev() {
+            let (_, value) = item.map_err(|e| CommandGPTError::HistoryError {
+                message: "Failed to read database item".to_string(),
+                source: Some(Box::new(e)),
+            })?;
+
+            if let Some(entry) = decode(&value) {
+                // Ids grow with time, so everything after this is older
+                if entry.timestamp < since {
+                    break;
+                }
+                entries.push(entry);
+            }
+        }
+
+        Ok(entries)
+    }
+
     pub fn search_history(&self, query: &str, limit: usize) -> Result<Vec<HistoryEntry>> {
         let mut results = Vec::new();
         let query_lower = query.to_lowercase();
@@ -159,7 +299,7 @@ impl HistoryManager {
                 source: Some(Box::new(e)),
             })?;
             
-            if let Ok(entry) = bincode::deserialize::<HistoryEntry>(&value) {
+            if let Some(entry) = decode(&value) {
                 if entry.command.to_lowercase().contains(&query_lower) {
                     results.push(entry);
                     if results.len() >= limit {
@@ -172,15 +312,6 @@ impl HistoryManager {
         Ok(results)
     }
 
-    fn truncate_output(&self, output: &str, max_len: usize) -> String {
-        if output.len() <= max_len {
-            output.to_string()
-        } else {
-            format!("{}... [truncated: {} bytes total]", 
-                   &output[..max_len], output.len())
-        }
-    }
-
     fn next_id(&self) -> Result<u64> {
         let id = self.counter
             .update_and_fetch(b"current", |old| {
@@ -255,6 +386,18 @@ pub async fn record_command(command: &str, stdout: &str, stderr: &str) -> Result
     Ok(())
 }
 
+pub async fn record_execution(command: &str, result: &ExecutionResult) -> Result<()> {
+    let manager = get_history_manager()?;
+    manager.record_execution(command, result).await?;
+    Ok(())
+}
+
+pub async fn record_remote_execution(host: &str, command: &str, result: &ExecutionResult) -> Result<()> {
+    let manager = get_history_manager()?;
+    manager.record_execution_on(Some(host), command, result).await?;
+    Ok(())
+}
+
 pub async fn get_last_command() -> crate::error::Result<Option<HistoryEntry>> {
     let manager = get_history_manager()?;
     manager.get_last_entry().map_err(|e| CommandGPTError::HistoryError {
@@ -274,17 +417,70 @@ pub async fn show_history(count: usize) -> Result<()> {
     
     println!("📜 Recent Commands:");
     for entry in entries {
-        let status_icon = if entry.exit_code == 0 { "✅" } else { "❌" };
+        let status_icon = if entry.interrupted {
+            "⏹️"
+        } else if entry.limit_hit.is_some() {
+            "⛔"
+        } else if entry.exit_code == 0 {
+            "✅"
+        } else {
+            "❌"
+        };
         println!("  {} [{}] {} - {}", 
                 status_icon,
                 entry.timestamp.format("%m-%d %H:%M"),
                 entry.id,
-                entry.command);
+                entry.label());
     }
     
     Ok(())
 }
 
+/// The `count` entries with the largest `key`, largest first; entries without one are left out
+fn top<K: Ord + Copy>(entries: &[HistoryEntry], count: usize, key: impl Fn(&HistoryEntry) -> Option<K>) -> Vec<(&HistoryEntry, K)> {
+    let mut ranked: Vec<_> = entries.iter().filter_map(|entry| Some((entry, key(entry)?))).collect();
+    ranked.sort_by_key(|entry| std::cmp::Reverse(entry.1));
+    ranked.truncate(count);
+    ranked
+}
+
+/// Totals for the last `days` days and the commands that took longest, used the most CPU
+/// time and needed the most memory
+pub async fn show_stats(days: u32, count: usize) -> Result<()> {
+    let manager = get_history_manager()?;
+    let entries = manager.entries_since(Utc::now() - chrono::Duration::days(days as i64))?;
+
+    if entries.is_empty() {
+        println!("No commands in the last {} days.", days);
+        return Ok(());
+    }
+
+    let failed = entries.iter().filter(|entry| entry.exit_code != 0).count();
+    let wall: u64 = entries.iter().map(|entry| entry.duration_ms).sum();
+    let cpu: Duration = entries.iter().filter_map(HistoryEntry::cpu_time).sum();
+    println!("📊 Last {} days: {} commands, {} failed, {:.1}s running, {:.1}s CPU",
+             days, entries.len(), failed, wall as f64 / 1000.0, cpu.as_secs_f64());
+
+    let print = |title: &str, ranked: Vec<(&HistoryEntry, String)>| {
+        if ranked.is_empty() {
+            return;
+        }
+        println!("{}", title);
+        for (entry, value) in ranked {
+            println!("  {:>10}  [{}] {}", value, entry.timestamp.format("%m-%d %H:%M"), entry.label());
+        }
+    };
+    let seconds = |duration: Duration| format!("{:.2}s", duration.as_secs_f64());
+    print("🐢 Slowest:", top(&entries, count, |entry| Some(entry.duration_ms))
+        .into_iter().map(|(entry, ms)| (entry, seconds(Duration::from_millis(ms)))).collect());
+    print("🔥 Most CPU time:", top(&entries, count, HistoryEntry::cpu_time)
+        .into_iter().map(|(entry, cpu)| (entry, seconds(cpu))).collect());
+    print("🧠 Most memory:", top(&entries, count, |entry| entry.usage?.max_rss())
+        .into_iter().map(|(entry, bytes)| (entry, output::format_bytes(bytes))).collect());
+
+    Ok(())
+}
+
 pub async fn clear_history() -> Result<()> {
     let manager = get_history_manager()?;
     manager.clear().await
@@ -294,3 +490,58 @@ pub async fn search_history(query: &str, limit: Option<usize>) -> Result<Vec<His
     let manager = get_history_manager()?;
     manager.search_history(query, limit.unwrap_or(10))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_legacy_and_abridged_entries() {
+        #[derive(Serialize)]
+        struct Old<'a>(u64, &'a str, &'a str, &'a str, i32, DateTime<Utc>, u64);
+        let old = bincode::serialize(&Old(7, "ls", "a\nb\n", "", 0, Utc::now(), 12)).unwrap();
+        let entry = decode(&old).unwrap();
+        assert_eq!((entry.id, entry.command.as_str()), (7, "ls"));
+        assert_eq!(entry.stdout_stats.lines, 2);
+
+        let big = "x".repeat(5000);
+        let (text, stats) = stored(&big, OutputStats::of(&big));
+        assert!(text.len() < 1100);
+        assert!(stats.truncated);
+        assert_eq!(stats.bytes, 5000);
+    }
+
+    #[test]
+    fn test_entries_since_and_top() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = HistoryManager::new(dir.path().join("history.db")).unwrap();
+        let entry = |command: &str, days_ago: i64, duration_ms: u64, cpu_ms: Option<u64>| HistoryEntry {
+            id: 0,
+            command: command.to_string(),
+            stdout: String::new(),
+            stderr: String::new(),
+            exit_code: 0,
+            timestamp: Utc::now() - chrono::Duration::days(days_ago),
+            duration_ms,
+            stdout_stats: OutputStats::default(),
+            stderr_stats: OutputStats::default(),
+            interrupted: false,
+            limit_hit: None,
+            host: None,
+            usage: cpu_ms.map(|ms| ResourceUsage::cpu(Duration::from_millis(ms), Duration::ZERO)),
+        };
+        manager.insert(entry("old build", 30, 90_000, Some(80_000))).unwrap();
+        manager.insert(entry("make", 2, 4_000, Some(3_500))).unwrap();
+        manager.insert(entry("sleep 5", 1, 5_000, Some(1))).unwrap();
+        manager.insert(entry("ls", 0, 20, None)).unwrap();
+
+        let week = manager.entries_since(Utc::now() - chrono::Duration::days(7)).unwrap();
+        assert_eq!(week.iter().map(|entry| entry.command.as_str()).collect::<Vec<_>>(), ["ls", "sleep 5", "make"]);
+
+        let slowest: Vec<_> = top(&week, 2, |entry| Some(entry.duration_ms)).into_iter().map(|(entry, _)| entry.command.as_str()).collect();
+        assert_eq!(slowest, ["sleep 5", "make"]);
+        let busiest = top(&week, 5, HistoryEntry::cpu_time);
+        assert_eq!(busiest.len(), 2);
+        assert_eq!((busiest[0].0.command.as_str(), busiest[0].1), ("make", Duration::from_millis(3_500)));
+    }
+}
@@ -1,9 +1,13 @@
+use crate::audit::{self, AuditEntry, Decision};
 use crate::config::AppConfig;
+use crate::confirm::{Confirmation, Gatekeeper, Review};
 use crate::context::ContextBuilder;
 use crate::openai::OpenAIClient;
-use crate::safety::{self, SafetyResult};
+use crate::safety;
+use crate::untrusted::{self, Origin, Provenance};
 use crate::executor::CommandExecutor;
 use crate::history;
+use crate::limits::Risk;
 use crate::error::{Result, CommandGPTError};
 use std::io::{self, Write};
 use std::time::Duration;
@@ -150,7 +154,13 @@ impl ShellHook {
             hook_config,
             context_builder: ContextBuilder::new(config),
             openai_client: OpenAIClient::new(config),
-            executor: CommandExecutor::new(),
+            executor: CommandExecutor::new().with_privilege(&config.safety.privilege)
+                .with_environment(&config.environment)
+                .with_capture(&config.output)
+                .with_shell(&config.shell)
+                .with_limits(&config.limits)
+                .with_live_output(true)
+                .with_signal_forwarding(true),
         }
     }
 
@@ -182,12 +192,14 @@ impl ShellHook {
         self.show_exit_hook_activation(&command, &error_context)?;
 
         // Build enhanced prompt with comprehensive error context
-        let enhanced_prompt = self.build_comprehensive_prompt(&command, command_args, &error_context, &error_analysis);
+        let mut provenance = Provenance::default();
+        let enhanced_prompt = self.build_comprehensive_prompt(&command, command_args, &error_context, &error_analysis, &mut provenance);
         
         // Get AI suggestion with timeout
         let timeout_duration = Duration::from_secs(self.hook_config.api_timeout);
+        let request = args.join(" ");
         
-        match tokio::time::timeout(timeout_duration, self.get_ai_suggestion_with_enhanced_context(&enhanced_prompt)).await {
+        match tokio::time::timeout(timeout_duration, self.get_ai_suggestion_with_enhanced_context(&enhanced_prompt, &provenance, &request)).await {
             Ok(Ok(suggestion)) => {
                 self.handle_exit_suggestion(suggestion, &command, &error_context).await
             }
@@ -235,12 +247,14 @@ impl ShellHook {
         }
 
         // Build enhanced prompt with error context
-        let enhanced_prompt = self.build_enhanced_prompt(&command, command_args, &error_context, &error_analysis);
+        let mut provenance = Provenance::default();
+        let enhanced_prompt = self.build_enhanced_prompt(&command, command_args, &error_context, &error_analysis, &mut provenance);
         
         // Get AI suggestion with timeout
         let timeout_duration = Duration::from_secs(self.hook_config.api_timeout);
+        let request = args.join(" ");
         
-        match tokio::time::timeout(timeout_duration, self.get_ai_suggestion_with_enhanced_context(&enhanced_prompt)).await {
+        match tokio::time::timeout(timeout_duration, self.get_ai_suggestion_with_enhanced_context(&enhanced_prompt, &provenance, &request)).await {
             Ok(Ok(suggestion)) => {
                 self.handle_suggestion_with_context(suggestion, &command, &error_context).await
             }
@@ -351,7 +365,7 @@ impl ShellHook {
     }
 
     /// Build comprehensive prompt for all error scenarios
-    fn build_comprehensive_prompt(&self, command: &str, args: &[String], context: &ErrorContext, analysis: &ErrorAnalysis) -> String {
+    fn build_comprehensive_prompt(&self, command: &str, args: &[String], context: &ErrorContext, analysis: &ErrorAnalysis, provenance: &mut Provenance) -> String {
         let mut prompt_parts = vec![
             format!("User executed command: {}", command),
         ];
@@ -368,19 +382,22 @@ impl ShellHook {
         // Add error outputs
         if let Some(stderr) = &context.stderr_output {
             if !stderr.trim().is_empty() {
-                prompt_parts.push(format!("Error output: {}", stderr.trim()));
+                let block = provenance.wrap(&format!("stderr of `{}`", command), Origin::External, stderr.trim());
+                prompt_parts.push(format!("Error output:\n{}", block));
             }
         }
         
         if let Some(stdout) = &context.stdout_output {
             if !stdout.trim().is_empty() {
-                prompt_parts.push(format!("Standard output: {}", stdout.trim()));
+                let block = provenance.wrap(&format!("stdout of `{}`", command), Origin::External, stdout.trim());
+                prompt_parts.push(format!("Standard output:\n{}", block));
             }
         }
         
         // Add error context
         if let Some(error_msg) = &context.error_message {
-            prompt_parts.push(format!("Shell error: {}", error_msg));
+            let block = provenance.wrap("shell error message", Origin::External, error_msg);
+            prompt_parts.push(format!("Shell error:\n{}", block));
         }
         
         // Add error type classification
@@ -476,34 +493,35 @@ impl ShellHook {
             stdout.reset()?;
             
             // Safety validation and execution option
-            let safety_result = safety::validate_command(&suggestion.command, false)?;
+            let safety_result = safety::SafetyChecker::new(&self.config.safety).validate(&suggestion.command, false)?;
+            let mut entry = self.audit_entry(original_command, &suggestion.command, &safety_result);
             
-            let should_execute = match safety_result {
-                SafetyResult::Safe => {
+            let should_execute = match Gatekeeper::new(&self.config).review(&safety_result) {
+                Review::Safe => {
                     if suggestion.auto_execute && !self.hook_config.always_confirm {
                         println!("\n🚀 Auto-executing safe fix...");
+                        entry.decision = Decision::AutoExecuted;
                         true
                     } else {
                         self.get_user_confirmation("Execute this fix? [y/N]: ")?
                     }
                 }
-                SafetyResult::NeedsConfirmation(warning) => {
+                Review::Confirm { warning, confirmation } => {
                     stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
                     println!("\n⚠️  Warning: {}", warning);
                     stdout.reset()?;
-                    self.get_user_confirmation("Are you sure you want to execute this? [y/N]: ")?
+                    self.get_confirmation(&confirmation)?
                 }
-                SafetyResult::Blocked(reason) => {
+                Review::Refuse(message) => {
                     stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
-                    println!("\n🚫 Command blocked: {}", reason);
+                    println!("\n🚫 {}", message);
                     stdout.reset()?;
+                    entry.decision = Decision::Refused;
                     false
                 }
             };
 
-            if should_execute {
-                self.execute_command(&suggestion.command).await?;
-            }
+            self.run_and_audit(should_execute, &suggestion.command, Risk::of(&safety_result), entry).await?;
         }
         
         // Show additional steps if provided
@@ -618,7 +636,7 @@ impl ShellHook {
     }
 
     /// Build enhanced prompt with error context
-    fn build_enhanced_prompt(&self, command: &str, args: &[String], context: &ErrorContext, analysis: &ErrorAnalysis) -> String {
+    fn build_enhanced_prompt(&self, command: &str, args: &[String], context: &ErrorContext, analysis: &ErrorAnalysis, provenance: &mut Provenance) -> String {
         let mut prompt_parts = vec![
             format!("User attempted to run command: {}", command),
         ];
@@ -629,7 +647,8 @@ impl ShellHook {
         
         // Add error context
         if let Some(error_msg) = &context.error_message {
-            prompt_parts.push(format!("Shell error: {}", error_msg));
+            let block = provenance.wrap("shell error message", Origin::External, error_msg);
+            prompt_parts.push(format!("Shell error:\n{}", block));
         }
         
         // Add directory context
@@ -721,11 +740,15 @@ impl ShellHook {
     }
 
     /// Get AI suggestion with enhanced context
-    async fn get_ai_suggestion_with_enhanced_context(&self, enhanced_prompt: &str) -> Result<crate::openai::CommandResponse> {
+    async fn get_ai_suggestion_with_enhanced_context(&self, enhanced_prompt: &str, provenance: &Provenance, request: &str) -> Result<crate::openai::CommandResponse> {
+        let mut system_content = "You are CommandGPT, an AI assistant that helps users with shell commands. Analyze the provided context and suggest the most appropriate command(s).".to_string();
+        if !provenance.is_empty() {
+            system_content.push_str(untrusted::DATA_RULES);
+        }
         let messages = vec![
             crate::openai::ChatMessage {
                 role: "system".to_string(),
-                content: "You are CommandGPT, an AI assistant that helps users with shell commands. Analyze the provided context and suggest the most appropriate command(s).".to_string(),
+                content: system_content,
             },
             crate::openai::ChatMessage {
                 role: "user".to_string(),
@@ -733,14 +756,27 @@ impl ShellHook {
             },
         ];
         
-        self.openai_client.send_chat(&messages).await
+        let mut suggestion = self.openai_client.send_chat(&messages).await
             .map_err(|e| {
                 log::debug!("OpenAI API error: {}", e);
                 crate::error::CommandGPTError::ApiError {
                     message: format!("OpenAI API error: {}", e),
                     source: None,
                 }
-            })
+            })?;
+
+        let checker = safety::SafetyChecker::new(&self.config.safety);
+        if let Some((command, reason)) = checker.drop_unneeded_elevation(&suggestion.command) {
+            suggestion.explanation.push_str(&format!(" (Running without sudo: {})", reason));
+            suggestion.command = command;
+        }
+        let portability = crate::portability::review(&suggestion.command, &self.config.portability);
+        for finding in &portability.findings {
+            suggestion.explanation.push_str(&format!(" ({})", finding.describe()));
+        }
+        suggestion.command = portability.command;
+        provenance.review(request, &mut suggestion);
+        Ok(suggestion)
     }
 
     /// Show command not found with context
@@ -771,19 +807,40 @@ impl ShellHook {
             println!("💡 Suggestion: {}", suggested_command);
             println!("📝 {}", suggestion.explanation);
             
-            print!("Execute this command instead? [y/N]: ");
-            io::stdout().flush()?;
-            
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
-            
-            if input.trim().to_lowercase() == "y" {
-                let result = self.executor.execute(suggested_command).await?;
-                if !result.success {
-                    println!("❌ Command failed: {}", result.stderr);
+            let safety_result = safety::SafetyChecker::new(&self.config.safety).validate(suggested_command, false)?;
+            let mut entry = self.audit_entry(original_command, suggested_command, &safety_result);
+            let should_execute = match Gatekeeper::new(&self.config).review(&safety_result) {
+                Review::Safe => self.get_user_confirmation("Execute this command instead? [y/N]: ")?,
+                Review::Confirm { warning, confirmation } => {
+                    println!("⚠️  Warning: {}", warning);
+                    self.get_confirmation(&confirmation)?
                 }
-            }
-            Ok(())
+                Review::Refuse(message) => {
+                    println!("🚫 {}", message);
+                    entry.decision = Decision::Refused;
+                    false
+                }
+            };
+            
+            let outcome = if should_execute {
+                entry.decision = Decision::Approved;
+                self.executor.execute_reviewed(suggested_command, Risk::of(&safety_result), false).await.map(|result| {
+                    entry.exit_code = result.exit_code;
+                    if result.interrupted {
+                        println!("⏹️  Interrupted by user");
+                    } else if let Some(limit) = result.limit_hit {
+                        println!("⛔ Stopped by the {}", limit);
+                    } else if !result.success && !result.streamed {
+                        println!("❌ Command failed: {}", result.stderr);
+                    } else if !result.success {
+                        println!("❌ Command failed with exit code {:?}", result.exit_code);
+                    }
+                })
+            } else {
+                Ok(())
+            };
+            audit::record(&self.config, entry);
+            Ok(outcome?)
         } else {
             // Standard reactive mode
             self.handle_suggestion(suggestion, original_command).await
@@ -864,20 +921,22 @@ impl ShellHook {
 
         // Set shorter timeout for hook usage
         let timeout_duration = std::time::Duration::from_secs(self.hook_config.api_timeout);
-        let response = tokio::time::timeout(timeout_duration, self.openai_client.send_chat(&payload)).await
+        let response = tokio::time::timeout(timeout_duration, self.openai_client.send_chat(&payload.messages)).await
             .map_err(|_| CommandGPTError::NetworkError {
                 message: "API request timed out".to_string(),
                 source: None,
             })?;
 
-        response.map_err(|e| CommandGPTError::Unknown {
+        let mut suggestion = response.map_err(|e| CommandGPTError::Unknown {
             message: format!("Failed to get response from OpenAI: {}", e),
             source: None,
-        })
+        })?;
+        payload.provenance.review(original_command, &mut suggestion);
+        Ok(suggestion)
     }
 
     /// Handle the AI suggestion
-    async fn handle_suggestion(&self, suggestion: crate::openai::CommandResponse, _original_command: &str) -> Result<()> {
+    async fn handle_suggestion(&self, suggestion: crate::openai::CommandResponse, original_command: &str) -> Result<()> {
         let mut stdout = StandardStream::stdout(ColorChoice::Auto);
         
         // Display suggestion
@@ -896,37 +955,55 @@ impl ShellHook {
         }
 
         // Safety validation
-        let safety_result = safety::validate_command(&suggestion.command, false)?;
+        let safety_result = safety::SafetyChecker::new(&self.config.safety).validate(&suggestion.command, false)?;
+        let mut entry = self.audit_entry(original_command, &suggestion.command, &safety_result);
         
         // Determine if we should execute
-        let should_execute = match safety_result {
-            SafetyResult::Safe => {
+        let should_execute = match Gatekeeper::new(&self.config).review(&safety_result) {
+            Review::Safe => {
                 if suggestion.auto_execute && !self.hook_config.always_confirm {
                     println!("\n🚀 Auto-executing safe command...");
+                    entry.decision = Decision::AutoExecuted;
                     true
                 } else {
                     self.get_user_confirmation("Execute this command? [y/N]: ")?
                 }
             }
-            SafetyResult::NeedsConfirmation(warning) => {
+            Review::Confirm { warning, confirmation } => {
                 stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
                 println!("\n⚠️  Warning: {}", warning);
                 stdout.reset()?;
-                self.get_user_confirmation("Are you sure you want to execute this? [y/N]: ")?
+                self.get_confirmation(&confirmation)?
             }
-            SafetyResult::Blocked(reason) => {
+            Review::Refuse(message) => {
                 stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
-                println!("\n🚫 Command blocked: {}", reason);
+                println!("\n🚫 {}", message);
                 stdout.reset()?;
+                entry.decision = Decision::Refused;
+                audit::record(&self.config, entry);
                 return Ok(());
             }
         };
 
-        if should_execute {
-            self.execute_command(&suggestion.command).await?;
-        }
+        self.run_and_audit(should_execute, &suggestion.command, Risk::of(&safety_result), entry).await
+    }
 
-        Ok(())
+    fn audit_entry(&self, original_command: &str, command: &str, safety_result: &safety::SafetyResult) -> AuditEntry {
+        AuditEntry::new("hook", original_command, command, &self.config.openai_model, safety_result, false)
+    }
+
+    /// Run the suggestion if the user accepted it, then append the outcome to the audit log
+    async fn run_and_audit(&self, should_execute: bool, command: &str, risk: Risk, mut entry: AuditEntry) -> Result<()> {
+        let outcome = if should_execute {
+            if entry.decision != Decision::AutoExecuted {
+                entry.decision = Decision::Approved;
+            }
+            self.execute_command(command, risk).await.map(|exit_code| entry.exit_code = exit_code)
+        } else {
+            Ok(())
+        };
+        audit::record(&self.config, entry);
+        outcome
     }
 
     /// Get user confirmation
@@ -937,31 +1014,53 @@ impl ShellHook {
         let mut input = String::new();
         io::stdin().read_line(&mut input)?;
         
-        Ok(input.trim().to_lowercase() == "y")
+        Ok(Confirmation::YesNo.accepts(&input))
     }
 
-    /// Execute the suggested command
-    async fn execute_command(&self, command: &str) -> Result<()> {
-        match self.executor.execute(command).await {
+    /// Ask for the confirmation tier the safety findings call for
+    fn get_confirmation(&self, confirmation: &Confirmation) -> Result<bool> {
+        let Some(prompt) = confirmation.prompt() else {
+            return self.get_user_confirmation("Are you sure you want to execute this? [y/N]: ");
+        };
+        print!("{}", prompt);
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+
+        let accepted = confirmation.accepts(&input);
+        if !accepted {
+            println!("Confirmation did not match, skipping.");
+        }
+        Ok(accepted)
+    }
+
+    /// Execute the suggested command, returning its exit code
+    async fn execute_command(&self, command: &str, risk: Risk) -> Result<Option<i32>> {
+        match self.executor.execute_reviewed(command, risk, false).await {
             Ok(result) => {
                 // Record in history
-                if let Err(e) = history::record_command(command, &result.stdout, &result.stderr).await {
+                if let Err(e) = history::record_execution(command, &result).await {
                     log::warn!("Failed to record command in history: {}", e);
                 }
                 
-                // Display output
-                if !result.stdout.is_empty() {
+                // Display output, unless it was already shown as it ran
+                if !result.stdout.is_empty() && !result.streamed {
                     println!("{}", result.stdout);
                 }
-                if !result.stderr.is_empty() {
+                if !result.stderr.is_empty() && !result.streamed {
                     eprintln!("{}", result.stderr);
                 }
                 
-                if !result.success {
+                if result.interrupted {
+                    eprintln!("⏹️  Interrupted by user");
+                } else if let Some(limit) = result.limit_hit {
+                    eprintln!("⛔ Stopped by the {}", limit);
+                } else if !result.success {
                     eprintln!("❌ Command failed with exit code {:?}", result.exit_code);
                 }
                 
-                Ok(())
+                Ok(result.exit_code)
             }
             Err(e) => {
                 Err(CommandGPTError::ExecutionError {
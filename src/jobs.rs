@@ -0,0 +1,279 @@
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+use crate::executor::{CommandExecutor, ExecutionResult};
+use crate::limits::Risk;
+use crate::output::{self, Capture, OutputChunk, OutputConfig};
+use crate::process::{self, Target};
+
+/// Output chunks buffered between a job and its collector
+const CHANNEL_SIZE: usize = 64;
+
+/// A job's stdout and stderr interleaved, echoed as it arrives while the job is in the foreground
+struct Transcript {
+    capture: Capture,
+    follow: bool,
+}
+
+/// Where a job is at
+pub enum JobState<'a> {
+    Running,
+    Finished(&'a ExecutionResult),
+    /// The command could not be run at all
+    Failed(&'a str),
+}
+
+/// A command running in the background of the REPL
+pub struct Job {
+    pub id: usize,
+    pub command: String,
+    started: Instant,
+    process_group: watch::Receiver<Option<i32>>,
+    transcript: Arc<Mutex<Transcript>>,
+    killed: Arc<AtomicBool>,
+    task: Option<JoinHandle<Result<ExecutionResult>>>,
+    collector: Option<JoinHandle<()>>,
+    outcome: Option<Result<ExecutionResult, String>>,
+    notified: bool,
+}
+
+impl Job {
+    pub fn state(&self) -> JobState<'_> {
+        match &self.outcome {
+            None => JobState::Running,
+            Some(Ok(result)) => JobState::Finished(result),
+            Some(Err(error)) => JobState::Failed(error),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        match &self.outcome {
+            Some(Ok(result)) => result.duration,
+            _ => self.started.elapsed(),
+        }
+    }
+
+    /// Stopped with `kill` or when the REPL exited
+    pub fn killed(&self) -> bool {
+        self.killed.load(Ordering::SeqCst)
+    }
+
+    /// The output so far, stdout and stderr interleaved, with the middle of long output left out
+    pub fn output(&self) -> String {
+        self.transcript.lock().unwrap().capture.clone().finish().0
+    }
+
+    /// Echo output as it arrives from now on, and return what came before
+    pub fn attach(&self) -> String {
+        let mut transcript = self.transcript.lock().unwrap();
+        transcript.follow = true;
+        transcript.capture.clone().finish().0
+    }
+
+    pub fn detach(&self) {
+        self.transcript.lock().unwrap().follow = false;
+    }
+
+    /// The job's process group, once its command has started
+    pub fn target(&self) -> Option<Target> {
+        (*self.process_group.borrow()).map(Target::Group)
+    }
+
+    /// Wait for the command to start and return its process group; None if it never did
+    pub async fn started(&self) -> Option<Target> {
+        let mut process_group = self.process_group.clone();
+        let pgid = *process_group.wait_for(Option::is_some).await.ok()?;
+        pgid.map(Target::Group)
+    }
+
+    fn is_done(&self) -> bool {
+        self.task.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Wait for the command and the rest of its output
+    async fn settle(&mut self) {
+        let Some(task) = self.task.take() else { return };
+        let outcome = match task.await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(e)) => Err(format!("{:#}", e)),
+            Err(e) => Err(format!("The job stopped unexpectedly: {}", e)),
+        };
+        if let Some(collector) = self.collector.take() {
+            let _ = collector.await;
+        }
+        self.outcome = Some(outcome);
+    }
+}
+
+/// The REPL's background jobs, numbered from 1 in the order they were started
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+    next_id: usize,
+}
+
+impl Jobs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `command` on `executor`, which should be detached from the terminal, and return the job number
+    pub fn start(&mut self, executor: CommandExecutor, command: &str, risk: Risk, config: &OutputConfig) -> usize {
+        let (sender, mut receiver) = mpsc::channel::<OutputChunk>(CHANNEL_SIZE);
+        let (group_sender, process_group) = watch::channel(None);
+        let executor = executor.with_output_channel(sender).with_process_group_sender(group_sender);
+        let transcript = Arc::new(Mutex::new(Transcript { capture: Capture::new(config), follow: false }));
+        let killed = Arc::new(AtomicBool::new(false));
+
+        // Ends once the executor, and with it the sender, is dropped at the end of the task
+        let collector = tokio::spawn({
+            let transcript = transcript.clone();
+            async move {
+                while let Some(chunk) = receiver.recv().await {
+                    let mut transcript = transcript.lock().unwrap();
+                    transcript.capture.push(&chunk.data);
+                    if transcript.follow {
+                        output::echo(chunk.stream, &chunk.data);
+                    }
+                }
+            }
+        });
+        let task = tokio::spawn({
+            let command = command.to_string();
+            let killed = killed.clone();
+            async move {
+                let mut result = executor.execute_reviewed(&command, risk, false).await?;
+                result.interrupted |= killed.load(Ordering::SeqCst);
+                Ok(result)
+            }
+        });
+
+        self.next_id += 1;
+        self.jobs.push(Job {
+            id: self.next_id,
+            command: command.to_string(),
+            started: Instant::now(),
+            process_group,
+            transcript,
+            killed,
+            task: Some(task),
+            collector: Some(collector),
+            outcome: None,
+            notified: false,
+        });
+        self.next_id
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    pub fn running(&self) -> usize {
+        self.jobs.iter().filter(|job| !job.is_done()).count()
+    }
+
+    /// Jobs that finished since the last call; each is returned once, here or by `wait`
+    pub async fn finished(&mut self) -> Vec<&Job> {
+        let mut ids = Vec::new();
+        for job in self.jobs.iter_mut().filter(|job| !job.notified && job.is_done()) {
+            job.settle().await;
+            job.notified = true;
+            ids.push(job.id);
+        }
+        self.jobs.iter().filter(|job| ids.contains(&job.id)).collect()
+    }
+
+    /// Wait for a job to finish. Returns whether it was already reported by `finished`
+    pub async fn wait(&mut self, id: usize) -> Result<(&Job, bool)> {
+        let job = self.jobs.iter_mut().find(|job| job.id == id)
+            .with_context(|| format!("There is no job {}", id))?;
+        job.settle().await;
+        let reported = std::mem::replace(&mut job.notified, true);
+        Ok((job, reported))
+    }
+
+    /// Stop a running job and everything it started: SIGTERM, then SIGKILL after the grace period
+    pub fn kill(&self, id: usize) -> Result<()> {
+        let job = self.get(id).with_context(|| format!("There is no job {}", id))?;
+        if job.is_done() {
+            anyhow::bail!("Job {} has already finished", id);
+        }
+        let target = job.target().with_context(|| format!("Job {} has not started its command yet", id))?;
+        job.killed.store(true, Ordering::SeqCst);
+        target.terminate_later();
+        Ok(())
+    }
+
+    /// Stop every running job and wait for them, killing what is left after the grace period
+    pub async fn stop_all(&mut self) {
+        let mut running = Vec::new();
+        for job in self.jobs.iter().filter(|job| !job.is_done()) {
+            job.killed.store(true, Ordering::SeqCst);
+            if let Some(target) = job.target() {
+                target.signal(libc::SIGTERM);
+                target.signal(libc::SIGCONT);
+                running.push(target);
+            }
+        }
+
+        let settled = tokio::time::timeout(process::GRACE_PERIOD, async {
+            for job in self.jobs.iter_mut() {
+                job.settle().await;
+            }
+        }).await;
+        if settled.is_err() {
+            for target in &running {
+                target.signal(libc::SIGKILL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_job_runs_in_background() {
+        let mut jobs = Jobs::new();
+        let id = jobs.start(CommandExecutor::new(), "echo started; sleep 0.3; echo done >&2", Risk::Safe, &OutputConfig::default());
+        assert_eq!(id, 1);
+        assert!(matches!(jobs.get(id).unwrap().state(), JobState::Running));
+        assert!(jobs.finished().await.is_empty());
+
+        let (job, reported) = jobs.wait(id).await.unwrap();
+        assert!(!reported);
+        assert_eq!(job.output(), "started\ndone\n");
+        match job.state() {
+            JobState::Finished(result) => assert!(result.success),
+            _ => panic!("the job should have finished"),
+        }
+        // Jobs collected with `wait` are not reported again
+        assert!(jobs.finished().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_kill_job() {
+        let mut jobs = Jobs::new();
+        let id = jobs.start(CommandExecutor::new(), "sleep 30 | cat", Risk::Safe, &OutputConfig::default());
+        assert!(jobs.get(id).unwrap().started().await.is_some());
+
+        jobs.kill(id).unwrap();
+        let (job, _) = jobs.wait(id).await.unwrap();
+        assert!(job.killed());
+        match job.state() {
+            JobState::Finished(result) => assert!(!result.success && result.interrupted),
+            _ => panic!("the job should have finished"),
+        }
+        assert!(jobs.kill(id).is_err());
+        assert!(jobs.kill(7).is_err());
+    }
+}
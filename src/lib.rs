@@ -1,15 +1,32 @@
 // Library crate for CommandGPT - enables testing and benchmarking
 
 // Make all modules public for testing
+pub mod audit;
+pub mod check;
 pub mod config;
+pub mod confirm;
 pub mod context; 
+pub mod environment;
 pub mod error;
 pub mod executor;
 pub mod history;
+pub mod jobs;
+pub mod limits;
 pub mod hook;
 pub mod openai;
+pub mod output;
+pub mod portability;
+pub mod process;
+pub mod pty;
+pub mod redact;
+pub mod remote;
 pub mod safety;
+pub mod sandbox;
+pub mod session;
+pub mod shell;
 pub mod telemetry;
+pub mod untrusted;
+pub mod usage;
 
 // Re-export commonly used types for convenience
 pub use error::{CommandGPTError, Result};
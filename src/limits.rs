@@ -0,0 +1,362 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::safety::SafetyResult;
+
+/// Wall-clock limit for commands when no policy sets one
+pub const DEFAULT_TIMEOUT_SECS: u64 = 300;
+
+/// Seconds between SIGXCPU at the CPU limit and SIGKILL, for programs that catch SIGXCPU
+const CPU_GRACE_SECS: u64 = 5;
+
+const MIB: u64 = 1024 * 1024;
+
+/// Resource limits for a command. Unset fields fall back to the less specific policy, and
+/// are unlimited when no policy sets them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// Wall-clock seconds before the command is stopped (`DEFAULT_TIMEOUT_SECS` when unset)
+    pub timeout_secs: Option<u64>,
+    /// CPU seconds each process may use (RLIMIT_CPU)
+    pub cpu_secs: Option<u64>,
+    /// Address space of each process in MiB (RLIMIT_AS). Runtimes that reserve large
+    /// heaps up front, like the JVM or Go, may fail to start under a low limit
+    pub memory_mb: Option<u64>,
+    /// Largest file a process may write, in MiB (RLIMIT_FSIZE)
+    pub file_size_mb: Option<u64>,
+    /// Open files per process (RLIMIT_NOFILE)
+    pub open_files: Option<u64>,
+    /// Processes the user may have, counting those already running elsewhere (RLIMIT_NPROC)
+    pub processes: Option<u64>,
+}
+
+impl Limits {
+    /// Built-in limits for commands approved with y/yes, so a runaway `find /` or a fork
+    /// bomb is stopped without any `[limits]` configuration
+    pub fn confirm_defaults() -> Limits {
+        Limits {
+            timeout_secs: None,
+            cpu_secs: Some(300),
+            memory_mb: Some(16 * 1024),
+            file_size_mb: Some(10 * 1024),
+            open_files: None,
+            processes: Some(4096),
+        }
+    }
+
+    /// Built-in limits for commands that needed a typed confirmation
+    pub fn typed_defaults() -> Limits {
+        Limits {
+            timeout_secs: None,
+            cpu_secs: Some(120),
+            memory_mb: Some(8 * 1024),
+            file_size_mb: Some(4 * 1024),
+            open_files: None,
+            processes: Some(2048),
+        }
+    }
+
+    /// These limits, with unset fields taken from `fallback`
+    fn or(&self, fallback: &Limits) -> Limits {
+        Limits {
+            timeout_secs: self.timeout_secs.or(fallback.timeout_secs),
+            cpu_secs: self.cpu_secs.or(fallback.cpu_secs),
+            memory_mb: self.memory_mb.or(fallback.memory_mb),
+            file_size_mb: self.file_size_mb.or(fallback.file_size_mb),
+            open_files: self.open_files.or(fallback.open_files),
+            processes: self.processes.or(fallback.processes),
+        }
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.map(Duration::from_secs)
+    }
+
+    /// Limits that have to be set on the command's own process, rather than watched from outside
+    pub fn has_rlimits(&self) -> bool {
+        self.cpu_secs.is_some() || self.memory_mb.is_some() || self.file_size_mb.is_some()
+            || self.open_files.is_some() || self.processes.is_some()
+    }
+
+    /// Lower the calling process's resource limits. Runs in the child between fork and exec,
+    /// so it only calls getrlimit and setrlimit, which are async-signal-safe
+    pub fn apply(&self) -> std::io::Result<()> {
+        let cpu = self.cpu_secs.map(|secs| (secs, secs + CPU_GRACE_SECS));
+        let limits = [
+            (libc::RLIMIT_CPU, cpu),
+            (libc::RLIMIT_AS, self.memory_mb.map(|mb| (mb * MIB, mb * MIB))),
+            (libc::RLIMIT_FSIZE, self.file_size_mb.map(|mb| (mb * MIB, mb * MIB))),
+            (libc::RLIMIT_NOFILE, self.open_files.map(|files| (files, files))),
+            (libc::RLIMIT_NPROC, self.processes.map(|processes| (processes, processes))),
+        ];
+
+        for (resource, limit) in limits {
+            let Some((soft, hard)) = limit else { continue };
+            let mut current = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+            if unsafe { libc::getrlimit(resource, &mut current) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            // Limits can only be lowered without privileges
+            let hard = hard.min(current.rlim_max);
+            let new = libc::rlimit { rlim_cur: soft.min(hard), rlim_max: hard };
+            if unsafe { libc::setrlimit(resource, &new) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// The limit a finished command most likely ran into. CPU and file size limits end the
+    /// process with a signal of their own; the others surface as errors in its output
+    pub fn diagnose(&self, status: ExitStatus, stderr: &str) -> Option<LimitHit> {
+        // A shell running the command reports the signal as 128 plus its number
+        let signalled = |signal: i32| status.signal() == Some(signal) || status.code() == Some(128 + signal);
+        let stderr = stderr.to_lowercase();
+        let mentions = |phrases: &[&str]| phrases.iter().any(|phrase| stderr.contains(phrase));
+
+        let found = [
+            (Limit::Cpu, self.cpu_secs, signalled(libc::SIGXCPU)),
+            (Limit::FileSize, self.file_size_mb, signalled(libc::SIGXFSZ) || mentions(&["file too large"])),
+            (Limit::Memory, self.memory_mb, mentions(&["cannot allocate memory", "out of memory", "memory exhausted", "memoryerror"])),
+            (Limit::OpenFiles, self.open_files, mentions(&["too many open files"])),
+            (Limit::Processes, self.processes, mentions(&["fork: resource temporarily unavailable", "cannot fork", "fork failed"])),
+        ];
+        found.into_iter()
+            .find(|(_, value, hit)| value.is_some() && *hit)
+            .and_then(|(limit, value, _)| value.map(|value| LimitHit { limit, value }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Timeout,
+    Cpu,
+    Memory,
+    FileSize,
+    OpenFiles,
+    Processes,
+}
+
+/// A limit a command ran into, with the value it was set to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitHit {
+    pub limit: Limit,
+    pub value: u64,
+}
+
+impl fmt::Display for LimitHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.limit {
+            Limit::Timeout => write!(f, "wall-clock limit of {}s", self.value),
+            Limit::Cpu => write!(f, "CPU time limit of {}s", self.value),
+            Limit::Memory => write!(f, "memory limit of {} MiB", self.value),
+            Limit::FileSize => write!(f, "file size limit of {} MiB", self.value),
+            Limit::OpenFiles => write!(f, "open file limit of {}", self.value),
+            Limit::Processes => write!(f, "process limit of {}", self.value),
+        }
+    }
+}
+
+/// How much review a command needed before it ran, which selects its limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Risk {
+    Safe,
+    /// Approved with y/yes
+    Confirm,
+    /// Approved by typing the program, target or a challenge word
+    Typed,
+}
+
+impl Risk {
+    pub fn of(result: &SafetyResult) -> Self {
+        match result {
+            SafetyResult::Safe => Risk::Safe,
+            SafetyResult::NeedsConfirmation(_) => Risk::Confirm,
+            SafetyResult::NeedsTypedConfirmation { .. } | SafetyResult::Blocked(_) => Risk::Typed,
+        }
+    }
+}
+
+/// Limits for the commands matching `pattern`, a regex searched for in the command
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LimitRule {
+    pub pattern: String,
+    #[serde(flatten)]
+    pub limits: Limits,
+    /// `pattern` compiled on first use, `None` when it is invalid
+    #[serde(skip)]
+    regex: OnceLock<Option<Regex>>,
+}
+
+impl PartialEq for LimitRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern && self.limits == other.limits
+    }
+}
+
+impl LimitRule {
+    fn matches(&self, command: &str) -> bool {
+        let regex = self.regex.get_or_init(|| match Regex::new(&self.pattern) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                log::warn!("Ignoring limits rule with invalid pattern '{}': {}", self.pattern, e);
+                None
+            }
+        });
+        regex.as_ref().is_some_and(|regex| regex.is_match(command))
+    }
+}
+
+/// The execution policy (`[limits]` in config.toml): limits for every command, overridden
+/// by the command's risk tier, overridden by the first rule matching the command. Limits
+/// left unset everywhere fall back to the tier's built-in defaults
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    #[serde(flatten)]
+    pub default: Limits,
+    /// Commands that needed a y/yes confirmation
+    pub confirm: Limits,
+    /// Commands that needed a typed confirmation
+    pub typed: Limits,
+    pub rules: Vec<LimitRule>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            default: Limits::default(),
+            confirm: Limits::confirm_defaults(),
+            typed: Limits::typed_defaults(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub fn policy(&self, command: &str, risk: Risk) -> Limits {
+        let (tier, built_in) = match risk {
+            Risk::Safe => (Limits::default(), Limits::default()),
+            Risk::Confirm => (self.confirm.clone(), Limits::confirm_defaults()),
+            Risk::Typed => (self.typed.clone(), Limits::typed_defaults()),
+        };
+        let rule = self.rules.iter()
+            .find(|rule| rule.matches(command))
+            .map(|rule| rule.limits.clone())
+            .unwrap_or_default();
+        rule.or(&tier).or(&self.default).or(&built_in)
+    }
+}
+
+/// One line for `config show`, e.g. "timeout 300s, CPU 120s; 2 rules"
+pub fn describe(config: &LimitsConfig) -> String {
+    let limits = &config.default;
+    let parts: Vec<String> = [
+        Some(format!("timeout {}s", limits.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS))),
+        limits.cpu_secs.map(|secs| format!("CPU {}s", secs)),
+        limits.memory_mb.map(|mb| format!("memory {} MiB", mb)),
+        limits.file_size_mb.map(|mb| format!("file size {} MiB", mb)),
+        limits.open_files.map(|files| format!("{} open files", files)),
+        limits.processes.map(|processes| format!("{} processes", processes)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    match config.rules.len() {
+        0 => parts.join(", "),
+        1 => format!("{}; 1 rule", parts.join(", ")),
+        rules => format!("{}; {} rules", parts.join(", "), rules),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_precedence() {
+        let config: LimitsConfig = toml::from_str(r#"
+            timeout_secs = 300
+            cpu_secs = 120
+
+            [confirm]
+            timeout_secs = 60
+
+            [[rules]]
+            pattern = "^find /"
+            cpu_secs = 20
+            file_size_mb = 10
+
+            [[rules]]
+            pattern = "(unclosed"
+            cpu_secs = 1
+        "#).unwrap();
+
+        let safe = config.policy("ls -la", Risk::Safe);
+        assert_eq!(safe, Limits { timeout_secs: Some(300), cpu_secs: Some(120), ..Limits::default() });
+        assert!(safe.has_rlimits());
+
+        let find = config.policy("find / -name '*.log'", Risk::Confirm);
+        assert_eq!(find.timeout_secs, Some(60));
+        assert_eq!(find.cpu_secs, Some(20));
+        assert_eq!(find.file_size_mb, Some(10));
+        assert_eq!(config.policy("ls", Risk::Typed).timeout_secs, Some(300));
+        // A tier table that sets some limits keeps the built-in ones for the rest
+        assert_eq!(find.processes, Limits::confirm_defaults().processes);
+        assert_eq!(config.policy("ls", Risk::Confirm).cpu_secs, Some(120));
+        // The rule's regex is compiled once and reused
+        assert!(config.rules[0].regex.get().is_some());
+    }
+
+    #[test]
+    fn test_default_policy() {
+        let config = LimitsConfig::default();
+        assert!(!config.policy("ls", Risk::Safe).has_rlimits());
+
+        let confirm = config.policy("find / -name core", Risk::Confirm);
+        assert_eq!(confirm, Limits::confirm_defaults());
+        assert!(confirm.cpu_secs.is_some() && confirm.memory_mb.is_some());
+        assert!(confirm.file_size_mb.is_some() && confirm.processes.is_some());
+
+        let typed = config.policy(":(){ :|:& };:", Risk::Typed);
+        assert!(typed.processes < confirm.processes);
+        assert!(typed.cpu_secs < confirm.cpu_secs);
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let limits = Limits { cpu_secs: Some(5), open_files: Some(64), ..Limits::default() };
+        let hit = limits.diagnose(ExitStatus::from_raw(libc::SIGXCPU), "").unwrap();
+        assert_eq!(hit, LimitHit { limit: Limit::Cpu, value: 5 });
+        assert_eq!(hit.to_string(), "CPU time limit of 5s");
+
+        // Exit codes of 128 plus the signal come from a shell whose child was killed
+        let status = ExitStatus::from_raw((128 + libc::SIGXCPU) << 8);
+        assert_eq!(limits.diagnose(status, "").map(|hit| hit.limit), Some(Limit::Cpu));
+
+        let status = ExitStatus::from_raw(1 << 8);
+        assert_eq!(limits.diagnose(status, "ls: Too many open files").map(|hit| hit.limit), Some(Limit::OpenFiles));
+        // Limits that were not set are not blamed
+        assert_eq!(limits.diagnose(status, "malloc: Cannot allocate memory"), None);
+    }
+
+    #[test]
+    fn test_apply_in_child() {
+        let limits = Limits { cpu_secs: Some(1), open_files: Some(32), ..Limits::default() };
+        let mut cmd = std::process::Command::new("sh");
+        cmd.args(["-c", "ulimit -n; while :; do :; done"]).stdout(std::process::Stdio::piped());
+        unsafe {
+            std::os::unix::process::CommandExt::pre_exec(&mut cmd, move || limits.apply());
+        }
+        let output = cmd.output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "32");
+        assert_eq!(output.status.signal(), Some(libc::SIGXCPU));
+    }
+}
@@ -1,11 +1,28 @@
+mod audit;
+mod check;
 mod config;
+mod confirm;
 mod repl;
 mod context;
+mod environment;
 mod openai;
+mod output;
+mod portability;
+mod process;
+mod pty;
+mod redact;
+mod remote;
 mod safety;
+mod sandbox;
+mod session;
+mod shell;
 mod executor;
 mod history;
+mod jobs;
+mod limits;
 mod telemetry;
+mod untrusted;
+mod usage;
 mod error;
 mod hook;
 
@@ -38,6 +55,22 @@ struct Cli {
     #[arg(long)]
     no_context: bool,
 
+    /// Run commands in the sandbox (read-only filesystem, no network)
+    #[arg(long)]
+    sandbox: bool,
+
+    /// Print the exact (redacted) payload sent to the model provider
+    #[arg(long, global = true)]
+    show_payload: bool,
+
+    /// Show CPU time, peak memory, block I/O and context switches after each command
+    #[arg(long)]
+    usage: bool,
+
+    /// Generate the command for this SSH host and run it there (an alias from your SSH config)
+    #[arg(long, value_name = "SSH_ALIAS")]
+    host: Option<String>,
+
     /// One-shot mode: provide command as argument
     #[arg(value_name = "REQUEST")]
     request: Option<String>,
@@ -56,6 +89,15 @@ enum Commands {
         #[arg(short, long, default_value = "10")]
         count: usize,
     },
+    /// Show the slowest commands and those that used the most CPU time and memory
+    Stats {
+        /// How many days back to look
+        #[arg(short, long, default_value = "7")]
+        days: u32,
+        /// Number of commands in each list
+        #[arg(short, long, default_value = "5")]
+        count: usize,
+    },
     /// Clear command history
     Clear,
     /// Hook mode - process unknown command (internal use)
@@ -103,6 +145,31 @@ enum Commands {
         #[arg(long)]
         error_type: Option<String>,
     },
+    /// Check commands against the safety rules without calling the model
+    ///
+    /// Exits with 0 when every command is safe, 12 when any needs confirmation
+    /// and 13 when any is blocked.
+    Check {
+        /// Commands to check; read from --file or stdin when none are given
+        commands: Vec<String>,
+        /// Read commands from a file ("-" for stdin)
+        #[arg(short, long, value_name = "PATH")]
+        file: Option<std::path::PathBuf>,
+        /// How to split the input into commands
+        #[arg(long, value_enum, default_value = "lines")]
+        input: check::InputFormat,
+        /// Output format
+        #[arg(long, value_enum, default_value = "table")]
+        output: check::OutputFormat,
+        /// Use the [safety] settings from this TOML file instead of config.toml
+        #[arg(long, value_name = "PATH")]
+        policy: Option<std::path::PathBuf>,
+    },
+    /// Inspect the audit log of suggested and executed commands
+    Audit {
+        #[command(subcommand)]
+        action: AuditAction,
+    },
     /// Shell hook management
     ShellHook {
         #[command(subcommand)]
@@ -120,6 +187,20 @@ enum ConfigAction {
     Show,
 }
 
+#[derive(Subcommand, Debug)]
+enum AuditAction {
+    /// Check the hash chain; exits with 1 if the log was modified
+    Verify,
+    /// Print every record
+    Export {
+        #[arg(long, value_enum, default_value = "jsonl")]
+        format: audit::ExportFormat,
+        /// Write to a file instead of stdout
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<std::path::PathBuf>,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 enum HookAction {
     /// Install shell hook
@@ -151,13 +232,15 @@ async fn main() -> Result<()> {
     }
 
     // Load configuration with enhanced error handling
-    let config = match config::AppConfig::load() {
+    let mut config = match config::AppConfig::load() {
         Ok(config) => config,
         Err(e) => {
             eprintln!("❌ Configuration error: {}", e);
             std::process::exit(1);
         }
     };
+    config.show_payload = cli.show_payload;
+    config.output.show_usage |= cli.usage;
 
     // Initialize history manager
     if let Err(e) = history::init_history(&config.history_path).await {
@@ -174,6 +257,12 @@ async fn main() -> Result<()> {
                 source: None,
             })
         }
+        Some(Commands::Stats { days, count }) => {
+            history::show_stats(*days, *count).await.map_err(|e| CommandGPTError::HistoryError {
+                message: format!("Failed to show statistics: {}", e),
+                source: None,
+            })
+        }
         Some(Commands::Clear) => {
             history::clear_history().await.map_err(|e| CommandGPTError::HistoryError {
                 message: format!("Failed to clear history: {}", e),
@@ -215,6 +304,12 @@ async fn main() -> Result<()> {
                 error_type: error_type.clone(),
             }).await
         }
+        Some(Commands::Check { commands, file, input, output, policy }) => {
+            handle_check_command(&config, commands, file.as_deref(), *input, *output, policy.as_deref(), cli.force)
+        }
+        Some(Commands::Audit { action }) => {
+            handle_audit_command(action, &config)
+        }
         Some(Commands::ShellHook { action }) => {
             handle_shell_hook_command(action, &config).await
         }
@@ -263,6 +358,10 @@ fn setup_error_handlers() {
 
     // Handle Ctrl+C gracefully
     ctrlc::set_handler(move || {
+        // While a command runs, Ctrl+C is forwarded to it instead
+        if process::is_running() {
+            return;
+        }
         println!("\n👋 Goodbye!");
         std::process::exit(130); // 128 + SIGINT
     }).expect("Error setting Ctrl+C handler");
@@ -296,6 +395,98 @@ async fn handle_config_command(action: &ConfigAction, config: &config::AppConfig
     Ok(())
 }
 
+fn handle_check_command(
+    config: &config::AppConfig,
+    commands: &[String],
+    file: Option<&std::path::Path>,
+    input: check::InputFormat,
+    output: check::OutputFormat,
+    policy: Option<&std::path::Path>,
+    force: bool,
+) -> Result<()> {
+    use std::io::Read;
+
+    let safety_config = match policy {
+        Some(path) => check::load_policy(path)?,
+        None => config.safety.clone(),
+    };
+    let checker = safety::SafetyChecker::new(&safety_config);
+
+    if !commands.is_empty() && file.is_some() {
+        return Err(CommandGPTError::InputError {
+            message: "Pass commands as arguments or with --file, not both".to_string(),
+            source: None,
+        });
+    }
+
+    let outcomes = if !commands.is_empty() {
+        let items = commands.iter()
+            .enumerate()
+            .map(|(index, command)| check::CheckItem {
+                source: format!("arg {}", index + 1),
+                command: command.clone(),
+            })
+            .collect();
+        check::check_items(&checker, items, force)?
+    } else {
+        let mut text = String::new();
+        let read = match file {
+            Some(path) if path != std::path::Path::new("-") => {
+                std::fs::read_to_string(path).map(|content| text = content)
+            }
+            _ => std::io::stdin().read_to_string(&mut text).map(|_| ()),
+        };
+        read.map_err(|e| CommandGPTError::InputError {
+            message: format!("Failed to read commands: {}", e),
+            source: Some(Box::new(e)),
+        })?;
+        check::check_text(&checker, &text, input, force)?
+    };
+
+    match output {
+        check::OutputFormat::Table => println!("{}", check::render_table(&outcomes)),
+        check::OutputFormat::Json => println!("{}", check::render_json(&outcomes)?),
+    }
+
+    let code = check::exit_code(&outcomes);
+    if code != 0 {
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+fn handle_audit_command(action: &AuditAction, config: &config::AppConfig) -> Result<()> {
+    let log = audit::AuditLog::new(config)?;
+    match action {
+        AuditAction::Verify => {
+            let verification = log.verify()?;
+            if verification.is_intact() {
+                println!("✅ Audit log intact: {} records", verification.records);
+                return Ok(());
+            }
+            println!("🚫 Audit log failed verification ({} records):", verification.records);
+            for problem in &verification.problems {
+                println!("  - {}", problem);
+            }
+            std::process::exit(1);
+        }
+        AuditAction::Export { format, output } => {
+            let exported = log.export(*format)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(path, exported).map_err(|e| CommandGPTError::OutputError {
+                        message: format!("Failed to write {}: {}", path.display(), e),
+                        source: Some(Box::new(e)),
+                    })?;
+                    println!("✅ Audit log exported to {}", path.display());
+                }
+                None => print!("{}", exported),
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn handle_oneshot(
     config: &config::AppConfig,
     request: &str,
@@ -312,9 +503,30 @@ async fn handle_oneshot(
     }
 
     let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+    // Commands for a remote host are written for it and sent there over SSH
+    let host = match &cli.host {
+        Some(_) if cli.sandbox => {
+            return Err(CommandGPTError::InputError {
+                message: "--sandbox only covers local commands and cannot be combined with --host".to_string(),
+                source: None,
+            });
+        }
+        Some(alias) => {
+            println!("🔌 Connecting to {}...", alias);
+            let host = remote::RemoteHost::connect(alias).await.map_err(|e| CommandGPTError::NetworkError {
+                message: format!("{:#}", e),
+                source: None,
+            })?;
+            println!("🔌 {}: {}", host.alias(), host.facts.summary());
+            Some(host)
+        }
+        None => None,
+    };
     
     // Build context and send to OpenAI with enhanced error handling
-    let context_builder = context::ContextBuilder::new(config);
+    let mut context_builder = context::ContextBuilder::new(config);
+    context_builder.set_remote(host.as_ref().map(|host| host.facts.clone()));
     let payload = context_builder.build_payload(request, None).await
         .map_err(|e| CommandGPTError::Unknown {
             message: format!("Failed to build request payload: {}", e),
@@ -322,10 +534,32 @@ async fn handle_oneshot(
         })?;
     
     let openai_client = openai::OpenAIClient::new(config);
-    let response = openai_client.send_chat(&payload).await?;
+    let mut response = openai_client.send_chat(&payload.messages).await?;
     
     // Safety check with enhanced error handling
-    let safety_result = safety::validate_command(&response.command, cli.force)?;
+    let checker = match &host {
+        Some(host) => safety::SafetyChecker::new(&config.safety).for_host(&host.facts.alias, &host.facts.home, &host.facts.cwd),
+        None => safety::SafetyChecker::new(&config.safety),
+    };
+    // Whether sudo is needed depends on the files where the command runs, so only local commands are checked
+    if host.is_none() {
+        if let Some((command, reason)) = checker.drop_unneeded_elevation(&response.command) {
+            response.explanation.push_str(&format!(" (Running without sudo: {})", reason));
+            response.command = command;
+        }
+    }
+    let portability_config = match &host {
+        Some(host) => host.portability(&config.portability),
+        None => config.portability.clone(),
+    };
+    let portability = portability::review(&response.command, &portability_config);
+    for finding in &portability.findings {
+        response.explanation.push_str(&format!(" ({})", finding.describe()));
+    }
+    response.command = portability.command;
+    payload.provenance.review(request, &mut response);
+    let safety_result = checker.validate(&response.command, cli.force)?;
+    let risk = limits::Risk::of(&safety_result);
     
     // Display command with explanation
     if let Err(e) = write_colored_output(&mut stdout, &response) {
@@ -336,16 +570,30 @@ async fn handle_oneshot(
     }
 
     // Handle execution based on safety and auto_execute flag
-    let should_execute = match safety_result {
-        safety::SafetyResult::Safe => {
+    let sandbox_available = sandbox::is_supported() && host.is_none();
+    let choice_prompt = if sandbox_available {
+        "Execute this command? [y/N/s=sandbox]: "
+    } else {
+        "Execute this command? [y/N]: "
+    };
+    let mut entry = audit::AuditEntry::new(
+        "oneshot", request, &response.command, &config.openai_model, &safety_result, cli.force,
+    );
+    if let Some(host) = &host {
+        entry.cwd = format!("{}:{}", host.alias(), host.facts.cwd);
+    }
+    let mut auto_executed = false;
+    let choice = match confirm::Gatekeeper::new(config).review(&safety_result) {
+        confirm::Review::Safe => {
             if response.auto_execute && !cli.always_confirm {
                 println!("\n🚀 Auto-executing...");
-                true
+                auto_executed = true;
+                sandbox::RunChoice::Execute
             } else {
-                get_user_confirmation("Execute this command? [y/N]: ")?
+                get_run_choice(choice_prompt)?
             }
         }
-        safety::SafetyResult::NeedsConfirmation(warning) => {
+        confirm::Review::Confirm { warning, confirmation } => {
             if let Err(e) = stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red))) {
                 log::warn!("Failed to set terminal color: {}", e);
             }
@@ -354,25 +602,67 @@ async fn handle_oneshot(
             }
             let _ = stdout.reset();
             
-            get_user_confirmation("Are you sure you want to execute this? [y/N]: ")?
+            if confirmation != confirm::Confirmation::YesNo {
+                // Typed tiers have no sandbox shortcut, the user has to type the answer
+                if get_typed_confirmation(&confirmation)? {
+                    sandbox::RunChoice::Execute
+                } else {
+                    println!("Confirmation did not match, skipping.");
+                    sandbox::RunChoice::Skip
+                }
+            } else if response.auto_execute && config.sandbox.auto_for_uncertain && sandbox_available {
+                // The model thought this was harmless but the checker disagrees: inspect first
+                sandbox::RunChoice::Sandbox
+            } else if sandbox_available {
+                get_run_choice("Are you sure you want to execute this? [y/N/s=sandbox]: ")?
+            } else {
+                get_run_choice("Are you sure you want to execute this? [y/N]: ")?
+            }
         }
-        safety::SafetyResult::Blocked(reason) => {
+        confirm::Review::Refuse(message) => {
             if let Err(e) = stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true)) {
                 log::warn!("Failed to set terminal color: {}", e);
             }
-            if let Err(e) = writeln!(&mut stdout, "\n🚫 Command blocked: {}", reason) {
+            if let Err(e) = writeln!(&mut stdout, "\n🚫 {}", message) {
                 log::warn!("Failed to write blocked message: {}", e);
             }
             let _ = stdout.reset();
+            entry.decision = audit::Decision::Refused;
+            audit::record(config, entry);
             return Ok(());
         }
     };
 
-    if should_execute {
-        execute_command_safely(&response.command).await?;
-    }
+    let outcome = match choice {
+        sandbox::RunChoice::Execute if cli.sandbox => {
+            entry.decision = audit::Decision::Sandboxed;
+            execute_command_sandboxed(config, &response.command, &mut entry).await
+        }
+        sandbox::RunChoice::Execute => {
+            entry.decision = if auto_executed { audit::Decision::AutoExecuted } else { audit::Decision::Approved };
+            execute_command_safely(config, &response.command, response.interactive, risk, host.as_ref(), &mut entry).await
+        }
+        sandbox::RunChoice::Sandbox => {
+            entry.decision = audit::Decision::Sandboxed;
+            match execute_command_sandboxed(config, &response.command, &mut entry).await {
+                Ok(()) if !cli.sandbox => match get_user_confirmation("\nRun it for real now? [y/N]: ") {
+                    Ok(true) => {
+                        entry.decision = audit::Decision::SandboxedThenApproved;
+                        execute_command_safely(config, &response.command, response.interactive, risk, host.as_ref(), &mut entry).await
+                    }
+                    other => other.map(|_| ()),
+                },
+                other => other,
+            }
+        }
+        sandbox::RunChoice::Skip => {
+            entry.decision = audit::Decision::Declined;
+            Ok(())
+        }
+    };
 
-    Ok(())
+    audit::record(config, entry);
+    outcome
 }
 
 fn write_colored_output(
@@ -411,25 +701,139 @@ fn get_user_confirmation(prompt: &str) -> Result<bool> {
         source: Some(Box::new(e)),
     })?;
     
-    Ok(input.trim().to_lowercase() == "y")
+    Ok(confirm::Confirmation::YesNo.accepts(&input))
+}
+
+/// Ask for one of the typed confirmation tiers; anything but the expected text declines
+fn get_typed_confirmation(confirmation: &confirm::Confirmation) -> Result<bool> {
+    use std::io::{self, Write};
+
+    print!("{}", confirmation.prompt().unwrap_or_default());
+    io::stdout().flush().map_err(|e| CommandGPTError::OutputError {
+        message: format!("Failed to flush stdout: {}", e),
+        source: Some(Box::new(e)),
+    })?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).map_err(|e| CommandGPTError::InputError {
+        message: format!("Failed to read user input: {}", e),
+        source: Some(Box::new(e)),
+    })?;
+
+    Ok(confirmation.accepts(&input))
+}
+
+fn get_run_choice(prompt: &str) -> Result<sandbox::RunChoice> {
+    use std::io::{self, Write};
+    
+    print!("{}", prompt);
+    io::stdout().flush().map_err(|e| CommandGPTError::OutputError {
+        message: format!("Failed to flush stdout: {}", e),
+        source: Some(Box::new(e)),
+    })?;
+    
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).map_err(|e| CommandGPTError::InputError {
+        message: format!("Failed to read user input: {}", e),
+        source: Some(Box::new(e)),
+    })?;
+    
+    Ok(sandbox::RunChoice::parse(&input))
+}
+
+async fn execute_command_sandboxed(config: &config::AppConfig, command: &str, entry: &mut audit::AuditEntry) -> Result<()> {
+    let policy = sandbox::SandboxPolicy::from_config(&config.sandbox)?;
+    println!("\n🧪 Sandbox run: {}", policy.describe());
+
+    let executor = executor::CommandExecutor::new().with_environment(&config.environment)
+        .with_capture(&config.output)
+        .with_shell(&config.shell)
+        .with_limits(&config.limits)
+        .with_live_output(true)
+        .with_signal_forwarding(true);
+    let result = executor.execute_sandboxed(command, &policy).await
+        .map_err(|e| CommandGPTError::ExecutionError {
+            message: format!("Failed to run command '{}' in sandbox: {}", command, e),
+            source: None,
+        })?;
+    entry.exit_code = result.exit_code;
+
+    if !result.streamed {
+        if !result.stdout.is_empty() {
+            println!("{}", result.stdout);
+        }
+        if !result.stderr.is_empty() {
+            eprintln!("{}", result.stderr);
+        }
+    }
+    println!("🧪 Sandbox run finished with exit code {:?} (writes outside the allowed paths were denied)",
+             result.exit_code);
+
+    Ok(())
 }
 
-async fn execute_command_safely(command: &str) -> Result<()> {
-    let executor = executor::CommandExecutor::new();
-    match executor.execute(command).await {
-        Ok(result) => {
+async fn execute_command_safely(
+    config: &config::AppConfig,
+    command: &str,
+    interactive: bool,
+    risk: limits::Risk,
+    host: Option<&remote::RemoteHost>,
+    entry: &mut audit::AuditEntry,
+) -> Result<()> {
+    let executor = executor::CommandExecutor::new().with_privilege(&config.safety.privilege)
+        .with_environment(&config.environment)
+        .with_capture(&config.output)
+        .with_shell(&config.shell)
+        .with_limits(&config.limits)
+        .with_live_output(true)
+        .with_signal_forwarding(true);
+    let execution = match host {
+        Some(host) => {
+            let terminal = interactive || host.needs_terminal(command);
+            executor.execute_reviewed(&host.command(command, executor.shell(), terminal), risk, terminal).await
+        }
+        None => executor.execute_reviewed(command, risk, interactive).await,
+    };
+    match execution {
+        Ok(mut result) => {
+            entry.exit_code = result.exit_code;
+            // What the local ssh client used says nothing about the command on the host
+            if host.is_some() {
+                result.usage = None;
+            }
+
             // Save to history
-            if let Err(e) = history::record_command(command, &result.stdout, &result.stderr).await {
+            let recorded = match host {
+                Some(host) => history::record_remote_execution(host.alias(), command, &result).await,
+                None => history::record_execution(command, &result).await,
+            };
+            if let Err(e) = recorded {
                 log::warn!("Failed to record command in history: {}", e);
             }
             
-            if !result.stdout.is_empty() {
-                println!("{}", result.stdout);
+            // Output was already shown as it ran
+            if !result.streamed {
+                if !result.stdout.is_empty() {
+                    println!("{}", result.stdout);
+                }
+                if !result.stderr.is_empty() {
+                    eprintln!("{}", result.stderr);
+                }
             }
-            if !result.stderr.is_empty() {
-                eprintln!("{}", result.stderr);
+            if let (true, Some(usage)) = (config.output.show_usage, result.usage) {
+                println!("📊 {}", usage);
             }
             
+            if result.interrupted {
+                println!("⏹️  Interrupted by user");
+                return Ok(());
+            }
+            if let Some(limit) = result.limit_hit {
+                return Err(CommandGPTError::ExecutionError {
+                    message: format!("Command '{}' was stopped by the {}", command, limit),
+                    source: None,
+                });
+            }
             if !result.success {
                 return Err(CommandGPTError::ExecutionError {
                     message: format!("Command '{}' failed with exit code {:?}: {}", 
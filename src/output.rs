@@ -0,0 +1,453 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::Write;
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+
+const READ_SIZE: usize = 8192;
+
+/// How much of each stream is kept and what is shown after a command (`[output]` in
+/// config.toml). Output beyond the two buffers is counted but not stored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    /// Bytes kept from the start of the output
+    pub head_bytes: usize,
+    /// Bytes kept from the end of the output
+    pub tail_bytes: usize,
+    /// Print CPU time, peak memory, block I/O and context switches after each command
+    pub show_usage: bool,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            head_bytes: 256 * 1024,
+            tail_bytes: 256 * 1024,
+            show_usage: false,
+        }
+    }
+}
+
+/// What a stream produced in total, as opposed to what was kept of it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutputStats {
+    pub bytes: u64,
+    pub lines: u64,
+    /// Part of the output is missing from the captured text
+    pub truncated: bool,
+    /// The output was not text, so only a placeholder was kept
+    pub binary: bool,
+}
+
+impl OutputStats {
+    /// Stats for text that was kept whole
+    pub fn of(text: &str) -> Self {
+        Self {
+            bytes: text.len() as u64,
+            lines: count_lines(text.as_bytes(), text.as_bytes().last().copied()),
+            truncated: false,
+            binary: false,
+        }
+    }
+
+    /// A note for readers of the captured text when it is not the whole output
+    pub fn label(&self) -> Option<String> {
+        if self.binary {
+            Some(format!("binary output, {}", format_bytes(self.bytes)))
+        } else if self.truncated {
+            Some(format!("truncated, {} lines and {} in total", self.lines, format_bytes(self.bytes)))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// A piece of command output, in the order it was read
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputChunk {
+    pub stream: Stream,
+    pub data: Vec<u8>,
+}
+
+/// Where output goes while the command runs, besides the captured copy
+#[derive(Debug, Clone, Default)]
+pub struct Tee {
+    /// Write output to this process's stdout and stderr as it arrives
+    pub echo: bool,
+    /// Send every chunk to a library user; a full channel slows the command down rather than dropping output
+    pub sender: Option<mpsc::Sender<OutputChunk>>,
+}
+
+/// Captured output: the start in a head buffer, the most recent bytes in a tail ring
+#[derive(Debug, Clone)]
+pub struct Capture {
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    head_limit: usize,
+    tail_limit: usize,
+    bytes: u64,
+    newlines: u64,
+    last: Option<u8>,
+    nul: bool,
+}
+
+impl Capture {
+    pub fn new(config: &OutputConfig) -> Self {
+        Self {
+            head: Vec::new(),
+            tail: VecDeque::new(),
+            head_limit: config.head_bytes,
+            tail_limit: config.tail_bytes,
+            bytes: 0,
+            newlines: 0,
+            last: None,
+            nul: false,
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        self.bytes += data.len() as u64;
+        self.newlines += data.iter().filter(|&&byte| byte == b'\n').count() as u64;
+        self.last = data.last().copied();
+        self.nul |= data.contains(&0);
+
+        let room = self.head_limit.saturating_sub(self.head.len()).min(data.len());
+        self.head.extend_from_slice(&data[..room]);
+        let rest = &data[room..];
+        if rest.len() >= self.tail_limit {
+            self.tail.clear();
+            self.tail.extend(&rest[rest.len() - self.tail_limit..]);
+        } else {
+            self.tail.extend(rest);
+            let excess = self.tail.len().saturating_sub(self.tail_limit);
+            self.tail.drain(..excess);
+        }
+    }
+
+    pub fn finish(self) -> (String, OutputStats) {
+        self.render(|bytes| String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    /// Turn the kept bytes into text with `decode`, marking where output was left out
+    pub fn render(self, decode: impl Fn(&[u8]) -> String) -> (String, OutputStats) {
+        let kept = (self.head.len() + self.tail.len()) as u64;
+        let stats = OutputStats {
+            bytes: self.bytes,
+            lines: self.newlines + u64::from(self.last.is_some_and(|byte| byte != b'\n')),
+            truncated: kept < self.bytes,
+            binary: self.nul || invalid_utf8(&self.head),
+        };
+
+        if stats.binary {
+            return (format!("[{}]", stats.label().unwrap_or_default()), stats);
+        }
+        let mut text = decode(trim_partial_end(&self.head));
+        if stats.truncated {
+            text.push_str(&format!("\n… [{} omitted] …\n", format_bytes(self.bytes - kept)));
+        }
+        let tail: Vec<u8> = self.tail.into_iter().collect();
+        text.push_str(&decode(trim_partial_start(&tail)));
+        (text, stats)
+    }
+}
+
+/// Cut `text` down to about `max_bytes`, keeping its start and end, and update `stats` to match
+pub fn abridge(text: &str, max_bytes: usize, stats: &mut OutputStats) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    stats.truncated = true;
+    let head = floor_char_boundary(text, max_bytes / 2);
+    let tail = ceil_char_boundary(text, text.len() - max_bytes / 2);
+    format!("{}\n… [{} omitted] …\n{}", &text[..head], format_bytes((tail - head) as u64), &text[tail..])
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn count_lines(data: &[u8], last: Option<u8>) -> u64 {
+    data.iter().filter(|&&byte| byte == b'\n').count() as u64 + u64::from(last.is_some_and(|byte| byte != b'\n'))
+}
+
+/// Bytes that are not UTF-8, ignoring a sequence cut off at the end of the buffer
+fn invalid_utf8(bytes: &[u8]) -> bool {
+    match std::str::from_utf8(bytes) {
+        Ok(_) => false,
+        Err(error) => error.error_len().is_some(),
+    }
+}
+
+/// Drop a character cut in half by the end of the head buffer
+fn trim_partial_end(bytes: &[u8]) -> &[u8] {
+    match std::str::from_utf8(bytes) {
+        Err(error) if error.error_len().is_none() => &bytes[..error.valid_up_to()],
+        _ => bytes,
+    }
+}
+
+/// Drop continuation bytes left at the start of the tail ring
+fn trim_partial_start(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().take(3).take_while(|&&byte| byte & 0xC0 == 0x80).count();
+    &bytes[start..]
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+/// Read both streams until they close, passing each chunk on as soon as it arrives and
+/// keeping a bounded copy of each
+pub async fn pump<O, E>(mut stdout: O, mut stderr: E, tee: &Tee, config: &OutputConfig) -> Result<(Capture, Capture)>
+where
+    O: AsyncReadExt + Unpin,
+    E: AsyncReadExt + Unpin,
+{
+    let (stdout, stderr, _) = pump_until(&mut stdout, &mut stderr, tee, config, None).await?;
+    Ok((stdout, stderr))
+}
+
+/// Like `pump`, but a stream also ends where `sentinel` appears in it, for readers that
+/// outlive a single command. The sentinel is not passed on. Also returns whether both
+/// streams reached the sentinel rather than closing
+pub async fn pump_until<O, E>(
+    stdout: &mut O,
+    stderr: &mut E,
+    tee: &Tee,
+    config: &OutputConfig,
+    sentinel: Option<&[u8]>,
+) -> Result<(Capture, Capture, bool)>
+where
+    O: AsyncReadExt + Unpin,
+    E: AsyncReadExt + Unpin,
+{
+    let (mut out, mut err) = (Source::new(config), Source::new(config));
+    let (mut out_buffer, mut err_buffer) = ([0u8; READ_SIZE], [0u8; READ_SIZE]);
+    let mut sender = tee.sender.clone();
+
+    while out.open || err.open {
+        let (stream, read) = tokio::select! {
+            read = stdout.read(&mut out_buffer), if out.open => (Stream::Stdout, read),
+            read = stderr.read(&mut err_buffer), if err.open => (Stream::Stderr, read),
+        };
+        let read = read.context("Failed to read from stream")?;
+        let (data, source) = match stream {
+            Stream::Stdout => (&out_buffer[..read], &mut out),
+            Stream::Stderr => (&err_buffer[..read], &mut err),
+        };
+        let data = source.take(data, sentinel);
+        if data.is_empty() {
+            continue;
+        }
+
+        source.capture.push(&data);
+        if tee.echo {
+            echo(stream, &data);
+        }
+        if let Some(channel) = &sender {
+            // A receiver that went away only stops the streaming, not the command
+            if channel.send(OutputChunk { stream, data }).await.is_err() {
+                sender = None;
+            }
+        }
+    }
+
+    let finished = out.finished && err.finished;
+    Ok((out.capture, err.capture, finished))
+}
+
+/// One stream being pumped, and the start of a sentinel that may continue in the next read
+struct Source {
+    capture: Capture,
+    held: Vec<u8>,
+    open: bool,
+    finished: bool,
+}
+
+impl Source {
+    fn new(config: &OutputConfig) -> Self {
+        Self { capture: Capture::new(config), held: Vec::new(), open: true, finished: false }
+    }
+
+    /// The part of a read that is command output; an empty read closes the stream
+    fn take(&mut self, data: &[u8], sentinel: Option<&[u8]>) -> Vec<u8> {
+        if data.is_empty() {
+            self.open = false;
+            return std::mem::take(&mut self.held);
+        }
+        let Some(sentinel) = sentinel else {
+            return data.to_vec();
+        };
+
+        self.held.extend_from_slice(data);
+        if let Some(position) = self.held.windows(sentinel.len()).position(|window| window == sentinel) {
+            self.held.truncate(position);
+            self.open = false;
+            self.finished = true;
+            return std::mem::take(&mut self.held);
+        }
+        let partial = (1..sentinel.len()).rev()
+            .find(|&length| self.held.ends_with(&sentinel[..length]))
+            .unwrap_or(0);
+        let rest = self.held.split_off(self.held.len() - partial);
+        std::mem::replace(&mut self.held, rest)
+    }
+}
+
+/// Write a chunk to this process's stdout or stderr
+pub fn echo(stream: Stream, data: &[u8]) {
+    let written = match stream {
+        Stream::Stdout => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(data).and_then(|_| stdout.flush())
+        }
+        Stream::Stderr => {
+            let mut stderr = std::io::stderr().lock();
+            stderr.write_all(data).and_then(|_| stderr.flush())
+        }
+    };
+    if let Err(e) = written {
+        log::debug!("Failed to echo command output: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(head_bytes: usize, tail_bytes: usize) -> OutputConfig {
+        OutputConfig { head_bytes, tail_bytes, ..OutputConfig::default() }
+    }
+
+    #[test]
+    fn test_capture_head_and_tail() {
+        let mut capture = Capture::new(&config(6, 4));
+        for line in ["first\n", "second\n", "third\n", "last"] {
+            capture.push(line.as_bytes());
+        }
+        let (text, stats) = capture.finish();
+        assert_eq!(text, "first\n\n… [13 B omitted] …\nlast");
+        assert_eq!(stats, OutputStats { bytes: 23, lines: 4, truncated: true, binary: false });
+        assert_eq!(stats.label().unwrap(), "truncated, 4 lines and 23 B in total");
+
+        let mut capture = Capture::new(&config(6, 4));
+        capture.push(b"short\n");
+        assert_eq!(capture.finish(), ("short\n".to_string(), OutputStats::of("short\n")));
+
+        // Characters cut by either buffer edge are dropped rather than mangled
+        let mut capture = Capture::new(&config(2, 4));
+        capture.push("aé——é".as_bytes());
+        assert_eq!(capture.finish().0, "a\n… [5 B omitted] …\né");
+    }
+
+    #[test]
+    fn test_binary_output() {
+        let mut capture = Capture::new(&OutputConfig::default());
+        capture.push(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0]);
+        let (text, stats) = capture.finish();
+        assert!(stats.binary);
+        assert_eq!(text, "[binary output, 10 B]");
+    }
+
+    #[test]
+    fn test_abridge() {
+        let mut stats = OutputStats::of("0123456789");
+        assert_eq!(abridge("0123456789", 4, &mut stats), "01\n… [6 B omitted] …\n89");
+        assert!(stats.truncated);
+        assert_eq!(stats.bytes, 10);
+        assert_eq!(format_bytes(3 * 1024 * 1024 / 2), "1.5 MiB");
+    }
+
+    #[tokio::test]
+    async fn test_pump_streams_in_order() {
+        let (sender, mut receiver) = mpsc::channel(16);
+        let tee = Tee { echo: false, sender: Some(sender) };
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", "echo one; sleep 0.1; echo two >&2; sleep 0.1; echo three"])
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let (stdout, stderr) = (child.stdout.take().unwrap(), child.stderr.take().unwrap());
+
+        let (out, err) = pump(stdout, stderr, &tee, &config(4, 0)).await.unwrap();
+        child.wait().await.unwrap();
+        drop(tee);
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            chunks.push((chunk.stream, String::from_utf8(chunk.data).unwrap()));
+        }
+        assert_eq!(chunks, vec![
+            (Stream::Stdout, "one\n".to_string()),
+            (Stream::Stderr, "two\n".to_string()),
+            (Stream::Stdout, "three\n".to_string()),
+        ]);
+        assert_eq!(out.finish().0, "one\n\n… [6 B omitted] …\n");
+        assert_eq!(err.finish().0, "two\n");
+    }
+
+    #[tokio::test]
+    async fn test_pump_until_sentinel() {
+        let sentinel = b"\x1e123\x1e";
+        let (mut out_writer, mut out_reader) = tokio::io::duplex(64);
+        let (mut err_writer, mut err_reader) = tokio::io::duplex(64);
+        let writer = tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            // The sentinel may be split across reads
+            for piece in [&b"done\x1e1"[..], b"23\x1e", b"left for later"] {
+                out_writer.write_all(piece).await.unwrap();
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            err_writer.write_all(b"\x1e12 warning\n\x1e123\x1e").await.unwrap();
+            (out_writer, err_writer)
+        });
+
+        let (out, err, finished) = pump_until(&mut out_reader, &mut err_reader, &Tee::default(), &config(64, 64), Some(sentinel))
+            .await
+            .unwrap();
+        assert!(finished);
+        assert_eq!(out.finish().0, "done");
+        assert_eq!(err.finish().0, "\x1e12 warning\n");
+
+        // Streams that close before the sentinel keep everything
+        let (out_writer, err_writer) = writer.await.unwrap();
+        drop((out_writer, err_writer));
+        let (out, _, finished) = pump_until(&mut out_reader, &mut err_reader, &Tee::default(), &config(64, 64), Some(sentinel))
+            .await
+            .unwrap();
+        assert!(!finished);
+        assert_eq!(out.finish().0, "left for later");
+    }
+}
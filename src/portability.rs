@@ -0,0 +1,824 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use crate::safety::parse::{is_assignment, program_name, wrapper_option_takes_value};
+
+/// Tools whose flags differ between GNU, BSD and BusyBox
+const TOOLS: &[&str] = &["sed", "stat", "date", "find", "xargs", "ls", "du", "grep", "base64", "head", "cp", "mv"];
+
+/// Wrappers whose argument is the command that actually runs
+const WRAPPERS: &[&str] = &["sudo", "doas", "env", "command", "exec", "nohup", "time", "nice"];
+
+/// Which implementation of the core utilities is installed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    Gnu,
+    Bsd,
+    BusyBox,
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Variant::Gnu => "GNU",
+            Variant::Bsd => "BSD",
+            Variant::BusyBox => "BusyBox",
+        })
+    }
+}
+
+/// Settings for portability checks (`[portability]` in config.toml)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortabilityConfig {
+    pub enabled: bool,
+    /// Rewrite flags for the installed variant; when false, only warn
+    pub rewrite: bool,
+    /// Assume this variant for every tool instead of detecting it
+    pub variant: Option<Variant>,
+}
+
+impl Default for PortabilityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rewrite: true,
+            variant: None,
+        }
+    }
+}
+
+/// The detected variant of each tool
+#[derive(Debug, Clone)]
+pub struct Toolset {
+    default: Variant,
+    tools: HashMap<String, Variant>,
+}
+
+impl Toolset {
+    pub fn uniform(variant: Variant) -> Self {
+        Self {
+            default: variant,
+            tools: HashMap::new(),
+        }
+    }
+
+    /// The configured variant, or what is installed (detected once per process)
+    pub fn for_config(config: &PortabilityConfig) -> Self {
+        match config.variant {
+            Some(variant) => Self::uniform(variant),
+            None => Self::detect().clone(),
+        }
+    }
+
+    pub fn detect() -> &'static Toolset {
+        static DETECTED: OnceLock<Toolset> = OnceLock::new();
+        DETECTED.get_or_init(|| {
+            let default = if cfg!(target_os = "linux") { Variant::Gnu } else { Variant::Bsd };
+            let tools = TOOLS.iter()
+                .filter_map(|tool| detect_tool(tool).map(|variant| (tool.to_string(), variant)))
+                .collect();
+            Toolset { default, tools }
+        })
+    }
+
+    pub fn variant(&self, tool: &str) -> Variant {
+        self.tools.get(tool).copied().unwrap_or(self.default)
+    }
+
+    /// e.g. `GNU` or `GNU (BSD sed)`
+    pub fn summary(&self) -> String {
+        let mut counts: HashMap<Variant, usize> = HashMap::new();
+        for tool in TOOLS {
+            *counts.entry(self.variant(tool)).or_default() += 1;
+        }
+        let main = counts.iter()
+            .max_by_key(|(variant, count)| (**count, **variant == self.default))
+            .map(|(variant, _)| *variant)
+            .unwrap_or(self.default);
+
+        let exceptions: Vec<String> = TOOLS.iter()
+            .filter(|tool| self.variant(tool) != main)
+            .map(|tool| format!("{} {}", self.variant(tool), tool))
+            .collect();
+        if exceptions.is_empty() {
+            main.to_string()
+        } else {
+            format!("{} ({})", main, exceptions.join(", "))
+        }
+    }
+}
+
+fn detect_tool(tool: &str) -> Option<Variant> {
+    let path = find_in_path(tool)?;
+    if path.canonicalize().ok()?.file_name().is_some_and(|name| name == "busybox") {
+        return Some(Variant::BusyBox);
+    }
+
+    // GNU tools print a version banner; BSD tools reject --version
+    let output = Command::new(&path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+    let banner = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    Some(variant_from_banner(&banner, output.status.success()))
+}
+
+/// macOS grep answers --version with "grep (BSD grep, GNU compatible)", so BSD wins over GNU
+fn variant_from_banner(banner: &str, success: bool) -> Variant {
+    if banner.contains("BusyBox") {
+        Variant::BusyBox
+    } else if success && !banner.contains("BSD") && (banner.contains("GNU") || banner.contains("uutils")) {
+        Variant::Gnu
+    } else {
+        Variant::Bsd
+    }
+}
+
+fn find_in_path(tool: &str) -> Option<PathBuf> {
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(tool))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// A flag that does not work with the installed variant
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub tool: String,
+    pub variant: Variant,
+    pub message: String,
+    /// Whether the command was rewritten to fix it
+    pub fixed: bool,
+}
+
+impl Finding {
+    pub fn describe(&self) -> String {
+        let prefix = if self.fixed { "Adjusted for" } else { "May not work with" };
+        format!("{} {} {}: {}", prefix, self.variant, self.tool, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// The command, rewritten if any finding was fixed
+    pub command: String,
+    pub findings: Vec<Finding>,
+}
+
+/// Check a command against the configured or detected variants
+pub fn review(command: &str, config: &PortabilityConfig) -> Report {
+    if !config.enabled {
+        return Report { command: command.to_string(), findings: Vec::new() };
+    }
+    check(command, &Toolset::for_config(config), config.rewrite)
+}
+
+pub fn check(command: &str, toolset: &Toolset, rewrite: bool) -> Report {
+    let mut findings = Vec::new();
+    let mut edits = Vec::new();
+
+    for words in simple_commands(command) {
+        check_words(&words, toolset, &mut |tool, variant, message, fix: Vec<Edit>| {
+            let fixed = rewrite && !fix.is_empty();
+            if fixed {
+                edits.extend(fix);
+            }
+            findings.push(Finding { tool: tool.to_string(), variant, message, fixed });
+        });
+    }
+
+    Report { command: apply_edits(command, edits), findings }
+}
+
+/// A replacement of `start..end` in the original command text
+#[derive(Debug, Clone, PartialEq)]
+struct Edit {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+impl Edit {
+    fn replace(word: &Word, text: impl Into<String>) -> Self {
+        Self { start: word.start, end: word.end, text: text.into() }
+    }
+
+    fn insert_after(word: &Word, text: &str) -> Self {
+        Self { start: word.end, end: word.end, text: format!(" {}", text) }
+    }
+
+    /// Remove a word and the whitespace before it
+    fn remove(previous: &Word, word: &Word) -> Self {
+        Self { start: previous.end, end: word.end, text: String::new() }
+    }
+}
+
+fn apply_edits(command: &str, mut edits: Vec<Edit>) -> String {
+    edits.sort_by_key(|edit| std::cmp::Reverse((edit.start, edit.end)));
+    let mut result = command.to_string();
+    let mut limit = usize::MAX;
+    for edit in edits {
+        // Overlapping edits would corrupt the command; keep the first one
+        if edit.end > limit {
+            continue;
+        }
+        result.replace_range(edit.start..edit.end, &edit.text);
+        limit = edit.start;
+    }
+    result
+}
+
+/// A shell word with its position in the command text
+#[derive(Debug, Clone, PartialEq)]
+struct Word {
+    start: usize,
+    end: usize,
+    /// The word with quotes and escapes removed
+    value: String,
+    /// Whether any part of the word was quoted (so `''` is an empty argument, not nothing)
+    quoted: bool,
+}
+
+impl Word {
+    fn is(&self, value: &str) -> bool {
+        self.value == value
+    }
+}
+
+/// Split a command line into simple commands, keeping word positions so rewrites can
+/// edit the original text. Redirections and their targets are left out
+fn simple_commands(command: &str) -> Vec<Vec<Word>> {
+    let mut commands = Vec::new();
+    let mut words: Vec<Word> = Vec::new();
+    let mut current: Option<Word> = None;
+    let mut skip_next_word = false;
+    let mut chars = command.char_indices().peekable();
+    let (mut in_single, mut in_double, mut in_backtick) = (false, false, false);
+    let mut subshell_depth = 0;
+
+    fn finish(current: &mut Option<Word>, words: &mut Vec<Word>, skip: &mut bool, end: usize) {
+        if let Some(mut word) = current.take() {
+            word.end = end;
+            if *skip {
+                *skip = false;
+            } else {
+                words.push(word);
+            }
+        }
+    }
+
+    while let Some((index, c)) = chars.next() {
+        let quoted = in_single || in_double || in_backtick || subshell_depth > 0;
+        if quoted {
+            let word = current.get_or_insert_with(|| Word { start: index, end: index, value: String::new(), quoted: true });
+            match c {
+                '\'' if in_single => in_single = false,
+                '"' if in_double => in_double = false,
+                '`' if in_backtick => {
+                    in_backtick = false;
+                    word.value.push(c);
+                }
+                '\\' if in_double => {
+                    if let Some((_, next)) = chars.next() {
+                        word.value.push(next);
+                    }
+                }
+                '(' if subshell_depth > 0 && !in_single && !in_double => {
+                    subshell_depth += 1;
+                    word.value.push(c);
+                }
+                ')' if subshell_depth > 0 && !in_single && !in_double => {
+                    subshell_depth -= 1;
+                    word.value.push(c);
+                }
+                _ => word.value.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            ' ' | '\t' => finish(&mut current, &mut words, &mut skip_next_word, index),
+            '#' if current.is_none() => {
+                // Comment to the end of the line
+                while chars.peek().is_some_and(|(_, c)| *c != '\n') {
+                    chars.next();
+                }
+            }
+            '|' | ';' | '\n' | '(' | ')' | '&' if !(c == '&' && chars.peek().is_some_and(|(_, c)| *c == '>')) => {
+                finish(&mut current, &mut words, &mut skip_next_word, index);
+                if !words.is_empty() {
+                    commands.push(std::mem::take(&mut words));
+                }
+            }
+            '<' | '>' | '&' => {
+                // An fd number glued to the operator belongs to the redirection
+                if current.as_ref().is_some_and(|word| !word.quoted && word.value.chars().all(|c| c.is_ascii_digit())) {
+                    current = None;
+                } else {
+                    finish(&mut current, &mut words, &mut skip_next_word, index);
+                }
+                while chars.peek().is_some_and(|(_, c)| matches!(c, '<' | '>' | '&' | '|')) {
+                    chars.next();
+                }
+                skip_next_word = true;
+            }
+            _ => {
+                let word = current.get_or_insert_with(|| Word { start: index, end: index, value: String::new(), quoted: false });
+                match c {
+                    '\'' => {
+                        in_single = true;
+                        word.quoted = true;
+                    }
+                    '"' => {
+                        in_double = true;
+                        word.quoted = true;
+                    }
+                    '`' => {
+                        in_backtick = true;
+                        word.value.push(c);
+                    }
+                    '\\' => {
+                        if let Some((_, next)) = chars.next() {
+                            word.value.push(next);
+                        }
+                    }
+                    '$' if chars.peek().is_some_and(|(_, c)| *c == '(') => {
+                        chars.next();
+                        subshell_depth = 1;
+                        word.value.push_str("$(");
+                    }
+                    _ => word.value.push(c),
+                }
+            }
+        }
+    }
+    finish(&mut current, &mut words, &mut skip_next_word, command.len());
+    if !words.is_empty() {
+        commands.push(words);
+    }
+    commands
+}
+
+type Reporter<'a> = dyn FnMut(&str, Variant, String, Vec<Edit>) + 'a;
+
+/// Check the program of a simple command, following wrappers, `xargs` and `find -exec`
+fn check_words(words: &[Word], toolset: &Toolset, report: &mut Reporter) {
+    let mut index = 0;
+    while index < words.len() && is_assignment(&words[index].value) {
+        index += 1;
+    }
+
+    while index < words.len() {
+        let name = program_name(&words[index].value).to_string();
+        if WRAPPERS.contains(&name.as_str()) {
+            index += 1;
+            while index < words.len() && (words[index].value.starts_with('-') || is_assignment(&words[index].value)) {
+                index += if wrapper_option_takes_value(&name, &words[index].value) { 2 } else { 1 };
+            }
+            continue;
+        }
+
+        let program = &words[index];
+        let args = &words[index + 1..];
+        if TOOLS.contains(&name.as_str()) {
+            let variant = toolset.variant(&name);
+            let mut found = |message: String, fix: Vec<Edit>| report(&name, variant, message, fix);
+            match name.as_str() {
+                "sed" => sed(variant, args, &mut found),
+                "stat" => stat(variant, args, &mut found),
+                "date" => date(variant, args, &mut found),
+                "find" => find(variant, program, args, &mut found),
+                "xargs" => xargs(variant, program, args, &mut found),
+                "ls" => ls(variant, args, &mut found),
+                "du" => du(variant, args, &mut found),
+                "grep" => grep(variant, args, &mut found),
+                "base64" => base64(variant, args, &mut found),
+                "head" => head(variant, args, &mut found),
+                "cp" | "mv" => target_directory(variant, args, &mut found),
+                _ => {}
+            }
+        }
+
+        match name.as_str() {
+            // The command xargs runs follows its options
+            "xargs" => {
+                index += 1;
+                while index < words.len() && words[index].value.starts_with('-') {
+                    let takes_value = matches!(words[index].value.as_str(), "-I" | "-n" | "-P" | "-L" | "-s" | "-d" | "-E" | "-a");
+                    index += if takes_value { 2 } else { 1 };
+                }
+            }
+            // Commands run by find -exec end at `;` or `+`
+            "find" => {
+                let mut rest = args;
+                while let Some(start) = rest.iter().position(|w| matches!(w.value.as_str(), "-exec" | "-execdir" | "-ok" | "-okdir")) {
+                    let inner = &rest[start + 1..];
+                    let end = inner.iter().position(|w| w.is(";") || w.is("+")).unwrap_or(inner.len());
+                    check_words(&inner[..end], toolset, report);
+                    rest = &inner[end.min(inner.len())..];
+                }
+                return;
+            }
+            _ => return,
+        }
+    }
+}
+
+type Found<'a> = dyn FnMut(String, Vec<Edit>) + 'a;
+
+/// Whether the word is a cluster of short flags containing `flag`, e.g. `-laG`
+fn has_short_flag(word: &Word, flag: char) -> bool {
+    let value = &word.value;
+    value.len() > 1 && value.starts_with('-') && !value.starts_with("--") && value[1..].chars().all(|c| c.is_ascii_alphabetic()) && value.contains(flag)
+}
+
+fn sed(variant: Variant, args: &[Word], found: &mut Found) {
+    for (index, word) in args.iter().enumerate() {
+        let next = args.get(index + 1);
+        if word.is("-i") {
+            let empty_suffix = next.is_some_and(|next| next.value.is_empty() && next.quoted);
+            match variant {
+                Variant::Bsd if !empty_suffix => found(
+                    "BSD sed takes a backup suffix after -i, use -i '' to edit in place".to_string(),
+                    vec![Edit::insert_after(word, "''")],
+                ),
+                Variant::Gnu | Variant::BusyBox if empty_suffix => found(
+                    format!("{} sed reads the '' after -i as the script", variant),
+                    vec![Edit::remove(word, next.unwrap())],
+                ),
+                _ => {}
+            }
+        } else if word.is("--in-place") && variant == Variant::Bsd {
+            found("BSD sed has no --in-place, use -i ''".to_string(), vec![Edit::replace(word, "-i ''")]);
+        } else if (word.is("-r") || word.is("--regexp-extended")) && variant == Variant::Bsd {
+            found("BSD sed uses -E for extended regular expressions".to_string(), vec![Edit::replace(word, "-E")]);
+        }
+    }
+}
+
+/// BSD `stat -f` format sequences and their GNU `stat -c` equivalents
+const STAT_FORMATS: &[(&str, &str)] = &[
+    ("%z", "%s"), ("%m", "%Y"), ("%a", "%X"), ("%c", "%Z"), ("%B", "%W"), ("%N", "%n"),
+    ("%Su", "%U"), ("%Sg", "%G"), ("%u", "%u"), ("%g", "%g"), ("%Lp", "%a"), ("%Sp", "%A"),
+    ("%Sm", "%y"), ("%HT", "%F"), ("%i", "%i"), ("%l", "%h"), ("%d", "%d"), ("%%", "%%"),
+];
+
+/// Translate a stat format string between BSD and GNU sequences, `None` if any is unknown
+fn translate_stat_format(format: &str, to: Variant) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = format;
+    while let Some(position) = rest.find('%') {
+        result.push_str(&rest[..position]);
+        rest = &rest[position..];
+        let (from, replacement) = STAT_FORMATS.iter()
+            .map(|(bsd, gnu)| if to == Variant::Bsd { (*gnu, *bsd) } else { (*bsd, *gnu) })
+            .filter(|(from, _)| rest.starts_with(from))
+            .max_by_key(|(from, _)| from.len())?;
+        result.push_str(replacement);
+        rest = &rest[from.len()..];
+    }
+    result.push_str(rest);
+    Some(result)
+}
+
+fn stat(variant: Variant, args: &[Word], found: &mut Found) {
+    for (index, word) in args.iter().enumerate() {
+        let Some(format) = args.get(index + 1).filter(|next| next.value.contains('%')) else {
+            continue;
+        };
+        let (from_flag, to_flag, to) = match variant {
+            Variant::Gnu | Variant::BusyBox if word.is("-f") => ("-f", "-c", variant),
+            Variant::Bsd if word.is("-c") || word.is("--format") => (word.value.as_str(), "-f", Variant::Bsd),
+            _ => continue,
+        };
+        let message = format!("{} stat uses {} for the output format (and different % sequences)", variant, to_flag);
+        match translate_stat_format(&format.value, to) {
+            Some(translated) => found(
+                format!("{}, not {}", message, from_flag),
+                vec![Edit::replace(word, to_flag), Edit::replace(format, shell_words::quote(&translated))],
+            ),
+            None => found(message, Vec::new()),
+        }
+    }
+}
+
+/// `-v-1d` style adjustments as GNU `-d` relative items, e.g. `-1 day`
+fn relative_date(adjustment: &str) -> Option<String> {
+    let sign = adjustment.chars().next().filter(|c| matches!(c, '+' | '-'))?;
+    let rest = &adjustment[1..];
+    let unit = rest.chars().last().filter(char::is_ascii_alphabetic)?;
+    let amount: u32 = rest[..rest.len() - 1].parse().ok()?;
+    let unit = match unit {
+        'y' => "year",
+        'm' => "month",
+        'w' => "week",
+        'd' => "day",
+        'H' => "hour",
+        'M' => "minute",
+        'S' => "second",
+        _ => return None,
+    };
+    Some(format!("{}{} {}{}", sign, amount, unit, if amount == 1 { "" } else { "s" }))
+}
+
+fn date(variant: Variant, args: &[Word], found: &mut Found) {
+    match variant {
+        Variant::Gnu | Variant::BusyBox => {
+            // -v-1d or -v -1d
+            let mut adjustments = Vec::new();
+            let mut index = 0;
+            while index < args.len() {
+                let word = &args[index];
+                if word.is("-v") {
+                    if let Some(value) = args.get(index + 1) {
+                        adjustments.push((index, value, value.value.clone()));
+                    }
+                    index += 2;
+                    continue;
+                }
+                if let Some(value) = word.value.strip_prefix("-v").filter(|v| !v.is_empty()) {
+                    adjustments.push((index, word, value.to_string()));
+                }
+                if word.is("-r") {
+                    if let Some(seconds) = args.get(index + 1).filter(|next| next.value.chars().all(|c| c.is_ascii_digit())) {
+                        found(
+                            format!("{} date -r takes a file, use -d @SECONDS for a timestamp", variant),
+                            vec![Edit::replace(word, "-d"), Edit::replace(seconds, format!("@{}", seconds.value))],
+                        );
+                    }
+                }
+                index += 1;
+            }
+
+            if adjustments.is_empty() {
+                return;
+            }
+            let message = format!("{} date has no -v, use -d with a relative date", variant);
+            let relative: Option<Vec<String>> = adjustments.iter().map(|(_, _, value)| relative_date(value)).collect();
+            match relative.filter(|_| variant == Variant::Gnu) {
+                Some(relative) => {
+                    let (first, last, _) = adjustments[0];
+                    let mut edits = vec![Edit {
+                        start: args[first].start,
+                        end: last.end,
+                        text: format!("-d {}", shell_words::quote(&relative.join(" "))),
+                    }];
+                    // Later adjustments are folded into the first -d, along with the space before them
+                    for (index, last, _) in &adjustments[1..] {
+                        edits.push(Edit::remove(&args[index - 1], last));
+                    }
+                    found(message, edits);
+                }
+                None => found(message, Vec::new()),
+            }
+        }
+        Variant::Bsd => {
+            for (index, word) in args.iter().enumerate() {
+                if !word.is("-d") && !word.value.starts_with("--date") {
+                    continue;
+                }
+                match args.get(index + 1).and_then(|next| next.value.strip_prefix('@').map(|seconds| (next, seconds))) {
+                    Some((next, seconds)) if word.is("-d") && seconds.chars().all(|c| c.is_ascii_digit()) => found(
+                        "BSD date takes a timestamp with -r SECONDS".to_string(),
+                        vec![Edit::replace(word, "-r"), Edit::replace(next, seconds)],
+                    ),
+                    _ => found("BSD date has no -d, use -v adjustments or -j -f to parse a date".to_string(), Vec::new()),
+                }
+            }
+        }
+    }
+}
+
+fn find(variant: Variant, program: &Word, args: &[Word], found: &mut Found) {
+    if variant == Variant::Bsd {
+        let options = ["-H", "-L", "-P", "-E", "-X", "-d", "-s", "-x"];
+        let first = args.iter().find(|word| !options.contains(&word.value.as_str()));
+        if first.is_none_or(|word| word.value.starts_with('-') || word.is("(") || word.is("!")) {
+            let after = args.iter().take_while(|word| options.contains(&word.value.as_str())).last().unwrap_or(program);
+            found("BSD find needs a starting path".to_string(), vec![Edit::insert_after(after, ".")]);
+        }
+    }
+
+    // Stop at -exec, whose arguments belong to another command
+    let own = args.iter().take_while(|w| !matches!(w.value.as_str(), "-exec" | "-execdir" | "-ok" | "-okdir"));
+    for word in own {
+        match word.value.as_str() {
+            "-printf" | "-fprintf" if variant != Variant::Gnu => found(
+                format!("{} find has no {}, use -exec stat or -print", variant, word.value),
+                Vec::new(),
+            ),
+            "-regextype" if variant == Variant::Bsd => {
+                found("BSD find has no -regextype, use find -E".to_string(), Vec::new())
+            }
+            _ => {}
+        }
+    }
+}
+
+fn xargs(variant: Variant, program: &Word, args: &[Word], found: &mut Found) {
+    if variant != Variant::Bsd {
+        return;
+    }
+    let mut previous = program;
+    for word in args.iter().take_while(|word| word.value.starts_with('-')) {
+        if word.is("-r") || word.is("--no-run-if-empty") {
+            found(
+                "BSD xargs never runs the command for empty input, -r is not needed".to_string(),
+                vec![Edit::remove(previous, word)],
+            );
+        } else if word.is("-d") || word.value.starts_with("--delimiter") {
+            found("BSD xargs has no -d, use tr '\\n' '\\0' | xargs -0".to_string(), Vec::new());
+        }
+        previous = word;
+    }
+}
+
+fn ls(variant: Variant, args: &[Word], found: &mut Found) {
+    for word in args {
+        match variant {
+            Variant::Bsd if word.is("--color") || word.value.starts_with("--color=") => {
+                found("BSD ls uses -G for colors".to_string(), vec![Edit::replace(word, "-G")]);
+            }
+            Variant::Bsd if word.value.starts_with("--") => {
+                let short = match word.value.as_str() {
+                    "--all" => Some("-a"),
+                    "--almost-all" => Some("-A"),
+                    "--human-readable" => Some("-h"),
+                    "--reverse" => Some("-r"),
+                    "--recursive" => Some("-R"),
+                    "--classify" => Some("-F"),
+                    _ => None,
+                };
+                let message = format!("BSD ls has no long options like {}", word.value);
+                found(message, short.map(|short| vec![Edit::replace(word, short)]).unwrap_or_default());
+            }
+            Variant::Gnu | Variant::BusyBox if has_short_flag(word, 'G') => {
+                let rest = word.value.replace('G', "");
+                let replacement = if rest == "-" { "--color=auto".to_string() } else { format!("{} --color=auto", rest) };
+                found(
+                    format!("{} ls -G hides groups, colors are --color=auto", variant),
+                    vec![Edit::replace(word, replacement)],
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+fn du(variant: Variant, args: &[Word], found: &mut Found) {
+    if variant != Variant::Bsd {
+        return;
+    }
+    for word in args {
+        if let Some(depth) = word.value.strip_prefix("--max-depth=") {
+            found("BSD du uses -d for the depth".to_string(), vec![Edit::replace(word, format!("-d {}", depth))]);
+        } else if word.is("--max-depth") {
+            found("BSD du uses -d for the depth".to_string(), vec![Edit::replace(word, "-d")]);
+        } else if word.is("-b") || word.is("--apparent-size") || word.is("--bytes") {
+            found(format!("BSD du has no {}", word.value), Vec::new());
+        }
+    }
+}
+
+fn grep(variant: Variant, args: &[Word], found: &mut Found) {
+    if variant == Variant::Gnu {
+        return;
+    }
+    if args.iter().any(|word| has_short_flag(word, 'P') || word.is("--perl-regexp")) {
+        found(format!("{} grep has no Perl regular expressions (-P), use -E", variant), Vec::new());
+    }
+}
+
+fn base64(variant: Variant, args: &[Word], found: &mut Found) {
+    for (index, word) in args.iter().enumerate() {
+        match variant {
+            Variant::Gnu | Variant::BusyBox if word.is("-D") => {
+                found(format!("{} base64 decodes with -d", variant), vec![Edit::replace(word, "-d")]);
+            }
+            Variant::Gnu | Variant::BusyBox if word.is("-b") && args.get(index + 1).is_some() => {
+                found(format!("{} base64 sets the line width with -w", variant), vec![Edit::replace(word, "-w")]);
+            }
+            Variant::Bsd if word.is("-w") && args.get(index + 1).is_some() => {
+                found("BSD base64 sets the line width with -b".to_string(), vec![Edit::replace(word, "-b")]);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn head(variant: Variant, args: &[Word], found: &mut Found) {
+    if variant != Variant::Bsd {
+        return;
+    }
+    let negative = args.windows(2).any(|pair| pair[0].is("-n") && pair[1].value.starts_with('-'))
+        || args.iter().any(|word| word.value.starts_with("-n-"));
+    if negative {
+        found("BSD head has no negative counts, use sed '$d' or ghead".to_string(), Vec::new());
+    }
+}
+
+fn target_directory(variant: Variant, args: &[Word], found: &mut Found) {
+    if variant == Variant::Bsd && args.iter().any(|word| word.is("-t") || word.value.starts_with("--target-directory")) {
+        found("BSD cp and mv have no -t, put the directory last".to_string(), Vec::new());
+    }
+}
+
+/// Human-readable name of the operating system for the model's environment context
+pub fn os_name() -> &'static str {
+    match std::env::consts::OS {
+        "macos" => "macOS",
+        "linux" => "Linux",
+        "freebsd" => "FreeBSD",
+        "openbsd" => "OpenBSD",
+        "netbsd" => "NetBSD",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(command: &str, variant: Variant) -> String {
+        check(command, &Toolset::uniform(variant), true).command
+    }
+
+    #[test]
+    fn test_sed_in_place() {
+        assert_eq!(rewrite("sed -i '' 's/a/b/' notes.txt", Variant::Gnu), "sed -i 's/a/b/' notes.txt");
+        assert_eq!(rewrite("sed -i 's/a/b/' notes.txt", Variant::Bsd), "sed -i '' 's/a/b/' notes.txt");
+        assert_eq!(rewrite("sed -i.bak 's/a/b/' notes.txt", Variant::Bsd), "sed -i.bak 's/a/b/' notes.txt");
+        assert_eq!(rewrite("sed -r 's/(a)+/b/' x", Variant::Bsd), "sed -E 's/(a)+/b/' x");
+        // Inside pipelines, xargs and find -exec
+        assert_eq!(
+            rewrite("grep -l foo *.md | xargs sed -i '' -e 's/foo/bar/'", Variant::Gnu),
+            "grep -l foo *.md | xargs sed -i -e 's/foo/bar/'"
+        );
+        assert_eq!(
+            rewrite("find . -name '*.txt' -exec sed -i 's/a/b/' {} +", Variant::Bsd),
+            "find . -name '*.txt' -exec sed -i '' 's/a/b/' {} +"
+        );
+        // Warn-only mode leaves the command alone
+        let report = check("sed -i '' s/a/b/ f", &Toolset::uniform(Variant::Gnu), false);
+        assert_eq!(report.command, "sed -i '' s/a/b/ f");
+        assert!(!report.findings[0].fixed);
+        assert!(report.findings[0].describe().starts_with("May not work with GNU sed"));
+    }
+
+    #[test]
+    fn test_stat_and_date() {
+        assert_eq!(rewrite("stat -f '%z %N' big.iso", Variant::Gnu), "stat -c '%s %n' big.iso");
+        assert_eq!(rewrite("stat -c %s big.iso", Variant::Bsd), "stat -f '%z' big.iso");
+        assert_eq!(rewrite("stat -f '%Q' x", Variant::Gnu), "stat -f '%Q' x");
+        assert_eq!(rewrite("date -v-1d +%F", Variant::Gnu), "date -d '-1 day' +%F");
+        assert_eq!(rewrite("date -v -2H -v+30M +%R", Variant::Gnu), "date -d '-2 hours +30 minutes' +%R");
+        assert_eq!(rewrite("date -r 1700000000", Variant::Gnu), "date -d @1700000000");
+        assert_eq!(rewrite("date -d @1700000000", Variant::Bsd), "date -r 1700000000");
+        let report = check("date -d yesterday", &Toolset::uniform(Variant::Bsd), true);
+        assert_eq!(report.findings.len(), 1);
+        assert!(!report.findings[0].fixed);
+    }
+
+    #[test]
+    fn test_find_ls_and_friends() {
+        assert_eq!(rewrite("find -name '*.log' -delete", Variant::Bsd), "find . -name '*.log' -delete");
+        assert_eq!(rewrite("find -name '*.log'", Variant::Gnu), "find -name '*.log'");
+        assert_eq!(rewrite("ls -laG", Variant::Gnu), "ls -la --color=auto");
+        assert_eq!(rewrite("ls --color=auto -la", Variant::Bsd), "ls -G -la");
+        assert_eq!(rewrite("du -h --max-depth=1 . 2>/dev/null | sort -h", Variant::Bsd), "du -h -d 1 . 2>/dev/null | sort -h");
+        assert_eq!(rewrite("git ls-files | xargs -r wc -l", Variant::Bsd), "git ls-files | xargs wc -l");
+        assert_eq!(rewrite("echo aGk= | base64 -D", Variant::Gnu), "echo aGk= | base64 -d");
+
+        let report = check("grep -P '\\d+' log.txt && find . -printf '%s\\n'", &Toolset::uniform(Variant::BusyBox), true);
+        let tools: Vec<_> = report.findings.iter().map(|f| f.tool.as_str()).collect();
+        assert_eq!(tools, vec!["grep", "find"]);
+
+        // Portable commands and quoted look-alikes are untouched
+        let portable = "ls -la && echo 'sed -i' > notes.txt && sort -u names.txt";
+        assert!(check(portable, &Toolset::uniform(Variant::Bsd), true).findings.is_empty());
+    }
+
+    #[test]
+    fn test_toolset_summary() {
+        let mut toolset = Toolset::uniform(Variant::Gnu);
+        assert_eq!(toolset.summary(), "GNU");
+        toolset.tools.insert("sed".to_string(), Variant::Bsd);
+        assert_eq!(toolset.summary(), "GNU (BSD sed)");
+    }
+
+    #[test]
+    fn test_variant_from_banner() {
+        assert_eq!(variant_from_banner("grep (GNU grep) 3.11", true), Variant::Gnu);
+        assert_eq!(variant_from_banner("grep (BSD grep, GNU compatible) 2.6.0-FreeBSD", true), Variant::Bsd);
+        assert_eq!(variant_from_banner("sed: illegal option -- -", false), Variant::Bsd);
+        assert_eq!(variant_from_banner("BusyBox v1.36.1 multi-call binary", false), Variant::BusyBox);
+    }
+}
@@ -0,0 +1,264 @@
+use std::future::Future;
+use std::io;
+use std::process::ExitStatus;
+use std::os::unix::process::ExitStatusExt;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::process::Child;
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::usage::ResourceUsage;
+
+/// How long a command gets to exit after SIGTERM before it is killed
+pub const GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+/// The foreground command: its process group when positive, its pid when negative
+/// (commands that share our group to read a password from the terminal), 0 when idle
+static FOREGROUND: AtomicI32 = AtomicI32::new(0);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static LISTENER: OnceLock<()> = OnceLock::new();
+
+/// Who gets the signals meant for a running command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// The command leads its own process group, so pipelines and scripts are signalled as a whole
+    Group(i32),
+    /// The command shares our process group and already receives keyboard signals from the terminal
+    Shared(i32),
+}
+
+impl Target {
+    fn encode(self) -> i32 {
+        match self {
+            Target::Group(pgid) => pgid,
+            Target::Shared(pid) => -pid,
+        }
+    }
+
+    fn decode(value: i32) -> Option<Self> {
+        match value {
+            0 => None,
+            pgid if pgid > 0 => Some(Target::Group(pgid)),
+            pid => Some(Target::Shared(-pid)),
+        }
+    }
+
+    pub fn signal(self, signal: i32) {
+        match self {
+            Target::Group(pgid) => unsafe { libc::kill(-pgid, signal) },
+            Target::Shared(pid) => unsafe { libc::kill(pid, signal) },
+        };
+    }
+
+    fn alive(self) -> bool {
+        match self {
+            Target::Group(pgid) => unsafe { libc::kill(-pgid, 0) == 0 },
+            Target::Shared(pid) => unsafe { libc::kill(pid, 0) == 0 },
+        }
+    }
+
+    /// SIGTERM now, SIGKILL for whatever is left after the grace period
+    pub fn terminate_later(self) {
+        self.signal(libc::SIGTERM);
+        self.signal(libc::SIGCONT);
+        tokio::spawn(async move {
+            tokio::time::sleep(GRACE_PERIOD).await;
+            if self.alive() {
+                self.signal(libc::SIGKILL);
+            }
+        });
+    }
+}
+
+/// Marks a command as the one receiving forwarded signals until dropped
+pub struct Foreground {
+    previous: i32,
+}
+
+impl Foreground {
+    pub fn enter(target: Target) -> Self {
+        listen();
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        Self { previous: FOREGROUND.swap(target.encode(), Ordering::SeqCst) }
+    }
+
+    /// A forwarded Ctrl+C or SIGTERM reached the command
+    pub fn interrupted(&self) -> bool {
+        INTERRUPTED.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Foreground {
+    fn drop(&mut self) {
+        FOREGROUND.store(self.previous, Ordering::SeqCst);
+    }
+}
+
+/// A command is running in the foreground, so Ctrl+C belongs to it rather than to us
+pub fn is_running() -> bool {
+    FOREGROUND.load(Ordering::SeqCst) != 0
+}
+
+/// Stop a command and everything it started: SIGTERM, then SIGKILL after the grace period
+pub async fn terminate(child: &mut Child, target: Target, grace: Duration) -> io::Result<ExitStatus> {
+    stop(&mut Box::pin(child.wait()), target, grace).await
+}
+
+/// `terminate` for a command whose exit `exit` waits for, e.g. `reap`
+pub async fn stop<T>(exit: &mut (impl Future<Output = T> + Unpin), target: Target, grace: Duration) -> T {
+    target.signal(libc::SIGTERM);
+    target.signal(libc::SIGCONT);
+    if let Ok(status) = tokio::time::timeout(grace, &mut *exit).await {
+        // The shell may exit first while the rest of its group ignores SIGTERM
+        if target.alive() {
+            target.signal(libc::SIGKILL);
+        }
+        return status;
+    }
+    target.signal(libc::SIGKILL);
+    exit.await
+}
+
+/// Wait for the child `pid` with `wait4`, which also reports the resources used by it and
+/// the descendants it waited for. The child is reaped here, so its `Child` must not be
+/// waited for, but has to be kept until this returns so tokio does not reap it first
+pub async fn reap(pid: i32) -> io::Result<(ExitStatus, ResourceUsage)> {
+    tokio::task::spawn_blocking(move || {
+        let mut status = 0;
+        let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+        loop {
+            if unsafe { libc::wait4(pid, &mut status, 0, &mut rusage) } == pid {
+                return Ok((ExitStatus::from_raw(status), ResourceUsage::from_rusage(&rusage)));
+            }
+            let error = io::Error::last_os_error();
+            if error.kind() != io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+    }).await?
+}
+
+/// Forward SIGINT, SIGTERM and SIGTSTP to the foreground command for the rest of the
+/// process's life. While nothing runs, SIGTERM exits, SIGTSTP suspends and SIGINT is left
+/// to whatever else handles it
+fn listen() {
+    LISTENER.get_or_init(|| {
+        let kinds = [SignalKind::interrupt(), SignalKind::terminate(), SignalKind::from_raw(libc::SIGTSTP)];
+        for kind in kinds {
+            let Ok(mut signals) = signal(kind) else {
+                log::warn!("Failed to listen for signal {}", kind.as_raw_value());
+                continue;
+            };
+            tokio::spawn(async move {
+                while signals.recv().await.is_some() {
+                    handle(kind.as_raw_value());
+                }
+            });
+        }
+    });
+}
+
+fn handle(signal: i32) {
+    let foreground = Target::decode(FOREGROUND.load(Ordering::SeqCst));
+    match (signal, foreground) {
+        // A second Ctrl+C stops a command that ignored the first
+        (libc::SIGINT, Some(target)) => {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                target.terminate_later();
+            } else if let Target::Group(_) = target {
+                target.signal(libc::SIGINT);
+            }
+        }
+        (libc::SIGTERM, Some(target)) => {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+            target.terminate_later();
+        }
+        (libc::SIGTERM, None) => std::process::exit(128 + libc::SIGTERM),
+        (libc::SIGTSTP, target) => {
+            if let Some(target @ Target::Group(_)) = target {
+                target.signal(libc::SIGTSTP);
+            }
+            // Suspend like a normal foreground job and resume the command with us on `fg`
+            unsafe { libc::raise(libc::SIGSTOP) };
+            if let Some(target) = target {
+                target.signal(libc::SIGCONT);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+    use tokio::process::Command;
+
+    #[tokio::test]
+    async fn test_terminate_escalates_to_sigkill() {
+        let mut child = Command::new("sh")
+            .args(["-c", "trap '' TERM; sleep 30 & wait"])
+            .stdout(Stdio::null())
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let target = Target::Group(child.id().unwrap() as i32);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let started = std::time::Instant::now();
+        let status = terminate(&mut child, target, Duration::from_millis(300)).await.unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_reap_reports_usage() {
+        let child = Command::new("sh")
+            .args(["-c", "i=0; while [ $i -lt 20000 ]; do i=$((i + 1)); done; sh -c 'exit 3'"])
+            .spawn()
+            .unwrap();
+        let (status, usage) = reap(child.id().unwrap() as i32).await.unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert!(usage.cpu_time() > Duration::ZERO);
+        assert!(usage.max_rss().unwrap() > 0);
+        drop(child);
+
+        // A command that outlives the grace period is killed and still reaped
+        let child = Command::new("sh").args(["-c", "trap '' TERM; sleep 30"]).process_group(0).spawn().unwrap();
+        let target = Target::Group(child.id().unwrap() as i32);
+        let mut exit = Box::pin(reap(child.id().unwrap() as i32));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let (status, _) = stop(&mut exit, target, Duration::from_millis(300)).await.unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+    }
+
+    #[tokio::test]
+    async fn test_ctrl_c_is_forwarded_to_the_group() {
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 30; echo survived"])
+            .stdout(Stdio::null())
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let foreground = Foreground::enter(Target::Group(child.id().unwrap() as i32));
+        assert!(is_running());
+        // Give the shell time to start `sleep`; a signal during its fork is lost to the shell
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        unsafe { libc::raise(libc::SIGINT) };
+        let status = tokio::time::timeout(Duration::from_secs(5), child.wait()).await.unwrap().unwrap();
+        assert!(foreground.interrupted());
+        assert!(!status.success());
+
+        drop(foreground);
+        assert!(!is_running());
+    }
+
+    #[test]
+    fn test_target_encoding() {
+        assert_eq!(Target::decode(Target::Group(42).encode()), Some(Target::Group(42)));
+        assert_eq!(Target::decode(Target::Shared(42).encode()), Some(Target::Shared(42)));
+        assert_eq!(Target::decode(0), None);
+    }
+}
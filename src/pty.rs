@@ -0,0 +1,316 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::fs::File;
+use std::io::{IsTerminal, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::process::Command as TokioCommand;
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::output::{Capture, OutputConfig, OutputStats};
+use crate::process;
+use crate::safety::parse::{program_name, split_segments};
+use crate::usage::ResourceUsage;
+
+/// How often the forwarding threads check whether the command has finished
+const POLL_INTERVAL_MS: i32 = 100;
+
+/// Programs that draw on or read keys from the terminal
+const TERMINAL_PROGRAMS: &[&str] = &[
+    "top", "htop", "btop", "atop", "less", "more", "most", "man", "vi", "vim", "nvim", "view",
+    "nano", "emacs", "pico", "micro", "joe", "mc", "ranger", "nnn", "fzf", "tig", "lazygit",
+    "tmux", "screen", "watch", "ssh", "mosh", "telnet", "ftp", "sftp", "passwd", "visudo",
+    "vipw", "ncdu", "iftop", "nethogs", "su", "read",
+];
+
+/// Interpreters and shells that start a REPL when given nothing to run
+const REPLS: &[&str] = &[
+    "python", "python3", "ipython", "node", "irb", "ghci", "lua", "R", "bash", "zsh", "sh", "fish",
+];
+
+/// Database clients that prompt for queries unless given one
+const DATABASE_CLIENTS: &[&str] = &["psql", "mysql", "sqlite3", "redis-cli", "mongosh"];
+
+/// Options that give a REPL something to run instead of prompting
+const SCRIPT_OPTIONS: &[&str] = &["-c", "-e", "-f", "-m", "--command", "--execute", "--file", "--eval"];
+
+/// Whether stdin and stdout are the user's terminal, so a pseudo-terminal can be passed through
+pub fn is_available() -> bool {
+    std::io::stdin().is_terminal() && std::io::stdout().is_terminal()
+}
+
+/// Whether the command needs a terminal: editors, pagers, full-screen tools, REPLs and prompts
+pub fn needs_terminal(command: &str) -> bool {
+    let segments = split_segments(command);
+    segments.iter().enumerate().any(|(index, segment)| {
+        let Some((program, args)) = segment.program_and_args() else {
+            // `sudo -i` and `sudo -s` run nothing but a login shell
+            return segment.tokens.first().is_some_and(|token| matches!(program_name(token), "sudo" | "doas"));
+        };
+        let has = |flags: &[&str]| args.iter().any(|arg| flags.contains(&arg.as_str()));
+        let output_piped = segments.get(index + 1).is_some_and(|next| next.piped);
+        let positional = args.iter().filter(|arg| !arg.starts_with('-')).count();
+        let input_given = segment.piped || segment.redirects.iter().any(|r| r.is_input() || r.op.starts_with("<<"));
+
+        match program {
+            // Remote commands run with -T do not get a terminal on the other end either
+            "ssh" if has(&["-T"]) => false,
+            _ if TERMINAL_PROGRAMS.contains(&program) => !output_piped,
+            _ if REPLS.contains(&program) => !has(SCRIPT_OPTIONS) && positional == 0 && !input_given,
+            // Positional arguments of database clients are connection settings
+            _ if DATABASE_CLIENTS.contains(&program) => {
+                let limit = match program {
+                    "sqlite3" => 1,
+                    "redis-cli" => 0,
+                    _ => usize::MAX,
+                };
+                !has(SCRIPT_OPTIONS) && !input_given && positional <= limit
+            }
+            "git" => match args.first().map(String::as_str) {
+                Some("add" | "checkout" | "reset" | "stash" | "restore") => has(&["-p", "--patch", "-i", "--interactive"]),
+                Some("rebase") => has(&["-i", "--interactive"]),
+                Some("commit") => !has(&["-m", "--message", "-F", "--file", "-C", "--no-edit", "--fixup"])
+                    && !args.iter().any(|arg| arg.starts_with("-m") || arg.starts_with("--message=") || arg.starts_with("-am")),
+                _ => false,
+            },
+            "crontab" => has(&["-e"]),
+            "docker" | "podman" | "kubectl" => {
+                matches!(args.first().map(String::as_str), Some("run" | "exec" | "attach"))
+                    && (has(&["-it", "-ti"]) || (has(&["-i", "--interactive"]) && has(&["-t", "--tty"])))
+            }
+            "apt" | "apt-get" | "dnf" | "yum" => {
+                matches!(args.first().map(String::as_str), Some("install" | "remove" | "upgrade" | "purge"))
+                    && !has(&["-y", "--yes", "--assume-yes"])
+                    && !input_given
+            }
+            _ => false,
+        }
+    })
+}
+
+/// What a command run in a pseudo-terminal left behind
+#[derive(Debug)]
+pub struct Session {
+    pub status: ExitStatus,
+    /// Terminal output with escape sequences removed, bounded like piped output
+    pub transcript: String,
+    pub stats: OutputStats,
+    pub usage: ResourceUsage,
+}
+
+/// Run a command attached to a new pseudo-terminal that is passed through to the user's
+/// terminal: keystrokes are forwarded, window-size changes are copied over and the output
+/// is shown as it arrives while a transcript is kept
+pub async fn run(mut cmd: TokioCommand, config: &OutputConfig) -> Result<Session> {
+    let (master, slave) = open_pty()?;
+    cmd.stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+    // The command leads its own session with the pseudo-terminal as controlling terminal,
+    // so job control, Ctrl+C and password prompts behave as in a normal shell
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 || libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY as _, 0) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let child = cmd.spawn().context("Failed to spawn command in a pseudo-terminal")?;
+    let pid = child.id().context("Command exited before it could be tracked")? as i32;
+    // Drop our copies of the terminal's child side so reads end when the command closes it
+    drop(cmd);
+
+    let raw_mode = RawMode::enable(libc::STDIN_FILENO);
+    let finished = Arc::new(AtomicBool::new(false));
+
+    let output = {
+        let reader = File::from(master.try_clone()?);
+        let finished = Arc::clone(&finished);
+        let capture = Capture::new(config);
+        std::thread::spawn(move || forward_output(reader, capture, &finished))
+    };
+    let input = {
+        let writer = File::from(master.try_clone()?);
+        let finished = Arc::clone(&finished);
+        std::thread::spawn(move || forward_input(writer, &finished))
+    };
+
+    let master_fd = master.as_raw_fd();
+    copy_window_size(libc::STDOUT_FILENO, master_fd);
+    let resize = tokio::spawn(async move {
+        if let Ok(mut window_changes) = signal(SignalKind::window_change()) {
+            while window_changes.recv().await.is_some() {
+                copy_window_size(libc::STDOUT_FILENO, master_fd);
+            }
+        }
+    });
+
+    let exit = process::reap(pid).await;
+    drop(child);
+    finished.store(true, Ordering::SeqCst);
+    resize.abort();
+    let _ = input.join();
+    let capture = output.join().unwrap_or_else(|_| Capture::new(config));
+    drop(raw_mode);
+    drop(master);
+
+    let (transcript, stats) = capture.render(clean_transcript);
+    let (status, usage) = exit.context("Failed to wait for command")?;
+    Ok(Session { status, transcript, stats, usage })
+}
+
+fn open_pty() -> Result<(OwnedFd, OwnedFd)> {
+    let (mut master, mut slave) = (-1, -1);
+    let size = window_size(libc::STDOUT_FILENO);
+    let size_ptr = size.as_ref().map_or(std::ptr::null(), |size| size as *const libc::winsize);
+    let result = unsafe { libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null(), size_ptr) };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to open a pseudo-terminal");
+    }
+    Ok(unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) })
+}
+
+fn window_size(fd: RawFd) -> Option<libc::winsize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    (unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0).then_some(size)
+}
+
+fn copy_window_size(from: RawFd, to: RawFd) {
+    if let Some(size) = window_size(from) {
+        unsafe { libc::ioctl(to, libc::TIOCSWINSZ, &size) };
+    }
+}
+
+/// Wait until the descriptor is readable, giving up after the poll interval
+fn readable(fd: RawFd) -> bool {
+    let mut poll_fd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+    unsafe { libc::poll(&mut poll_fd, 1, POLL_INTERVAL_MS) > 0 }
+}
+
+/// Copy the command's output to the terminal, keeping a transcript of it
+fn forward_output(mut reader: File, mut transcript: Capture, finished: &AtomicBool) -> Capture {
+    let mut stdout = std::io::stdout();
+    let mut buffer = [0u8; 4096];
+    loop {
+        if !readable(reader.as_raw_fd()) {
+            // Background processes may keep the terminal open after the command exits
+            if finished.load(Ordering::SeqCst) {
+                break;
+            }
+            continue;
+        }
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => {
+                let _ = stdout.write_all(&buffer[..count]);
+                let _ = stdout.flush();
+                transcript.push(&buffer[..count]);
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            // EIO once every process has closed the terminal
+            Err(_) => break,
+        }
+    }
+    transcript
+}
+
+/// Copy keystrokes to the command until it finishes; reads go straight to the descriptor so
+/// nothing typed after the command ends is swallowed
+fn forward_input(mut writer: File, finished: &AtomicBool) {
+    let mut buffer = [0u8; 1024];
+    while !finished.load(Ordering::SeqCst) {
+        if !readable(libc::STDIN_FILENO) {
+            continue;
+        }
+        let count = unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr().cast(), buffer.len()) };
+        if count <= 0 || writer.write_all(&buffer[..count as usize]).is_err() {
+            break;
+        }
+    }
+}
+
+/// Puts the user's terminal in raw mode so keystrokes reach the command unprocessed,
+/// restoring the original settings when dropped
+struct RawMode {
+    fd: RawFd,
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable(fd: RawFd) -> Option<Self> {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return None;
+        }
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        (unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } == 0).then_some(Self { fd, original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Terminal output as plain text: escape sequences removed and carriage-return redraws collapsed
+fn clean_transcript(raw: &[u8]) -> String {
+    static ESCAPES: OnceLock<Regex> = OnceLock::new();
+    let escapes = ESCAPES.get_or_init(|| {
+        Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(\x07|\x1b\\)|\x1b[()][0-9A-Za-z]|\x1b[=>78DEHMNOc]")
+            .expect("valid escape pattern")
+    });
+
+    let text = String::from_utf8_lossy(raw);
+    let text = escapes.replace_all(&text, "");
+    text.split('\n')
+        .map(|line| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            line.rsplit('\r').next().unwrap_or(line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_terminal() {
+        for command in [
+            "vim notes.txt", "top", "less /var/log/syslog", "cat big.log | less", "python3",
+            "git add -p", "git commit", "git rebase -i HEAD~3", "sudo -u postgres psql", "psql mydb", "sudo -i",
+            "docker exec -it web sh", "crontab -e", "apt install jq", "ssh prod-1",
+        ] {
+            assert!(needs_terminal(command), "{} should need a terminal", command);
+        }
+        for command in [
+            "ls -la", "python3 script.py", "python3 -c 'print(1)'", "git commit -m 'fix'",
+            "git status", "psql -c 'select 1'", "redis-cli GET session", "top -b -n 1 | head", "docker exec web ls",
+            "apt install -y jq", "echo y | apt install jq", "sqlite3 app.db 'select 1'", "bash < setup.sh",
+            "ssh -T prod-1 uptime",
+        ] {
+            assert!(!needs_terminal(command), "{} should not need a terminal", command);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_in_pseudo_terminal() {
+        let mut cmd = TokioCommand::new("/bin/sh");
+        cmd.arg("-c").arg("test -t 0 && test -t 1 && echo on a tty; exit 3");
+        let session = run(cmd, &OutputConfig::default()).await.unwrap();
+        assert_eq!(session.status.code(), Some(3));
+        assert_eq!(session.transcript.trim(), "on a tty");
+    }
+
+    #[test]
+    fn test_clean_transcript() {
+        let raw = b"\x1b[?1049h\x1b[1;32mok\x1b[0m\r\nDownloading 10%\rDownloading 100%\r\n\x1b]0;title\x07done";
+        assert_eq!(clean_transcript(raw), "ok\nDownloading 100%\ndone");
+    }
+}
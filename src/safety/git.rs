@@ -0,0 +1,508 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::parse::split_segments;
+use super::paths::resolve;
+
+/// Configuration for the git-aware analyzer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GitConfig {
+    /// Branch names (with `*` wildcards) whose history must not be rewritten casually
+    pub protected_branches: Vec<String>,
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            protected_branches: vec![
+                "main".to_string(),
+                "master".to_string(),
+                "production".to_string(),
+                "release/*".to_string(),
+            ],
+        }
+    }
+}
+
+impl GitConfig {
+    pub fn is_protected(&self, branch: &str) -> bool {
+        self.protected_branches.iter().any(|pattern| match pattern.split_once('*') {
+            Some((prefix, suffix)) => {
+                branch.len() >= prefix.len() + suffix.len()
+                    && branch.starts_with(prefix)
+                    && branch.ends_with(suffix)
+            }
+            None => pattern == branch,
+        })
+    }
+}
+
+/// A git operation that can lose work, with a concrete description of what would be lost
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitRisk {
+    pub summary: String,
+    /// Set when the operation rewrites or deletes a protected branch
+    pub protected_branch: Option<String>,
+}
+
+/// The repository a git command runs against
+struct Repo {
+    dir: PathBuf,
+}
+
+#[derive(Debug, Default)]
+struct StatusCounts {
+    /// Tracked files with staged or unstaged changes
+    modified: usize,
+    staged: usize,
+}
+
+impl Repo {
+    fn open(dir: &Path) -> Option<Self> {
+        let repo = Self { dir: dir.to_path_buf() };
+        (repo.git(&["rev-parse", "--is-inside-work-tree"])? == "true").then_some(repo)
+    }
+
+    fn git(&self, args: &[&str]) -> Option<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.dir)
+            .args(args)
+            .output()
+            .ok()?;
+        output.status.success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+    }
+
+    fn count(&self, args: &[&str]) -> usize {
+        self.git(args)
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0)
+    }
+
+    fn current_branch(&self) -> Option<String> {
+        self.git(&["rev-parse", "--abbrev-ref", "HEAD"])
+            .filter(|branch| branch != "HEAD")
+    }
+
+    fn status(&self, pathspec: &[&str]) -> StatusCounts {
+        let mut args = vec!["status", "--porcelain", "--"];
+        args.extend_from_slice(pathspec);
+
+        let mut counts = StatusCounts::default();
+        for line in self.git(&args).unwrap_or_default().lines() {
+            if line.starts_with("??") || line.len() < 2 {
+                continue;
+            }
+            counts.modified += 1;
+            if !line.starts_with(' ') {
+                counts.staged += 1;
+            }
+        }
+        counts
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+fn describe_status(status: &StatusCounts) -> String {
+    match status.staged {
+        0 => plural(status.modified, "modified file"),
+        staged => format!("{} ({} staged)", plural(status.modified, "modified file"), staged),
+    }
+}
+
+fn operands(args: &[String]) -> Vec<&str> {
+    args.iter()
+        .map(String::as_str)
+        .filter(|arg| !arg.starts_with('-'))
+        .collect()
+}
+
+fn has_short_flag(args: &[String], flag: char) -> bool {
+    args.iter().any(|arg| arg.starts_with('-') && !arg.starts_with("--") && arg.contains(flag))
+}
+
+/// Subcommands `analyze` checks against the repository
+const CHECKED_SUBCOMMANDS: &[&str] = &["reset", "clean", "checkout", "restore", "switch", "stash", "branch", "push"];
+
+/// `git` subcommands in the command that could lose work, for when the repository cannot be
+/// opened because the command runs on another machine
+pub fn unchecked(command: &str) -> Vec<String> {
+    let mut subcommands = Vec::new();
+    for segment in split_segments(command) {
+        let Some(("git", args)) = segment.program_and_args() else {
+            continue;
+        };
+        let mut index = 0;
+        while index < args.len() && args[index].starts_with('-') {
+            if matches!(args[index].as_str(), "-C" | "-c" | "--git-dir" | "--work-tree" | "--namespace") {
+                index += 1;
+            }
+            index += 1;
+        }
+        if let Some(subcommand) = args.get(index).filter(|sub| CHECKED_SUBCOMMANDS.contains(&sub.as_str())) {
+            subcommands.push(format!("git {}", subcommand));
+        }
+    }
+    subcommands
+}
+
+/// Inspect every git command in the line for operations that would lose work
+pub fn analyze(command: &str, cwd: &Path, config: &GitConfig) -> Vec<GitRisk> {
+    let mut cwd = cwd.to_path_buf();
+    let mut risks = Vec::new();
+
+    for segment in split_segments(command) {
+        let Some((program, args)) = segment.program_and_args() else {
+            continue;
+        };
+        if program == "cd" {
+            if let Some(dir) = args.first() {
+                cwd = resolve(dir, &cwd);
+            }
+            continue;
+        }
+        if program != "git" {
+            continue;
+        }
+
+        // Skip global options, honouring -C
+        let mut dir = cwd.clone();
+        let mut index = 0;
+        while index < args.len() && args[index].starts_with('-') {
+            match args[index].as_str() {
+                "-C" => {
+                    if let Some(path) = args.get(index + 1) {
+                        dir = resolve(path, &dir);
+                    }
+                    index += 1;
+                }
+                "-c" | "--git-dir" | "--work-tree" | "--namespace" => index += 1,
+                _ => {}
+            }
+            index += 1;
+        }
+        let Some(subcommand) = args.get(index) else {
+            continue;
+        };
+        let sub_args = &args[index + 1..];
+
+        let Some(repo) = Repo::open(&dir) else {
+            continue;
+        };
+
+        let risk = match subcommand.as_str() {
+            "reset" => reset_risk(&repo, sub_args),
+            "clean" => clean_risk(&repo, sub_args),
+            "checkout" => checkout_risk(&repo, sub_args),
+            "restore" => restore_risk(&repo, sub_args),
+            "switch" => switch_risk(&repo, sub_args),
+            "stash" => stash_risk(&repo, sub_args),
+            "branch" => branch_risk(&repo, sub_args, config),
+            "push" => push_risk(&repo, sub_args, config),
+            _ => None,
+        };
+        risks.extend(risk);
+    }
+
+    risks
+}
+
+fn reset_risk(repo: &Repo, args: &[String]) -> Option<GitRisk> {
+    if !args.iter().any(|arg| arg == "--hard") {
+        return None;
+    }
+
+    let status = repo.status(&[]);
+    let mut summary = format!("git reset --hard will discard {}", describe_status(&status));
+
+    if let Some(target) = operands(args).first().filter(|target| **target != "HEAD") {
+        let range = format!("{}..HEAD", target);
+        let moved = repo.count(&["rev-list", "--count", &range]);
+        if moved > 0 {
+            let unpushed = repo.count(&["rev-list", "--count", &range, "--not", "--remotes"]);
+            summary.push_str(&format!(" and move HEAD back past {}", plural(moved, "commit")));
+            if unpushed > 0 {
+                summary.push_str(&format!(" ({} not pushed anywhere)", unpushed));
+            }
+        }
+    }
+
+    if let Some(branch) = repo.current_branch() {
+        summary.push_str(&format!(" on branch '{}'", branch));
+    }
+
+    Some(GitRisk { summary, protected_branch: None })
+}
+
+fn clean_risk(repo: &Repo, args: &[String]) -> Option<GitRisk> {
+    let forced = args.iter().any(|arg| arg == "--force") || has_short_flag(args, 'f');
+    if !forced {
+        return None;
+    }
+
+    // Ask git for the exact list with a dry run of the same flags
+    let dry_run: Vec<String> = args.iter()
+        .filter(|arg| *arg != "--force")
+        .map(|arg| {
+            if arg.starts_with('-') && !arg.starts_with("--") {
+                arg.replace('f', "")
+            } else {
+                arg.clone()
+            }
+        })
+        .filter(|arg| arg != "-")
+        .collect();
+    let mut git_args = vec!["clean", "-n"];
+    git_args.extend(dry_run.iter().map(String::as_str));
+
+    let removed = repo.git(&git_args)
+        .map(|output| output.lines().filter(|line| line.starts_with("Would remove")).count())
+        .unwrap_or(0);
+
+    let ignored = has_short_flag(args, 'x') || has_short_flag(args, 'X');
+    Some(GitRisk {
+        summary: format!(
+            "git clean will permanently delete {}{}",
+            plural(removed, "untracked file or directory"),
+            if ignored { " including ignored files" } else { "" }
+        ),
+        protected_branch: None,
+    })
+}
+
+fn discard_risk(repo: &Repo, pathspec: &[&str], verb: &str) -> Option<GitRisk> {
+    let status = repo.status(pathspec);
+    Some(GitRisk {
+        summary: format!("git {} will discard {}", verb, describe_status(&status)),
+        protected_branch: None,
+    })
+}
+
+fn checkout_risk(repo: &Repo, args: &[String]) -> Option<GitRisk> {
+    if let Some(separator) = args.iter().position(|arg| arg == "--") {
+        let pathspec: Vec<&str> = args[separator + 1..].iter().map(String::as_str).collect();
+        return discard_risk(repo, &pathspec, "checkout");
+    }
+    if args.iter().any(|arg| arg == "--force") || has_short_flag(args, 'f') {
+        return discard_risk(repo, &[], "checkout --force");
+    }
+    match operands(args).as_slice() {
+        ["."] => discard_risk(repo, &["."], "checkout"),
+        _ => None,
+    }
+}
+
+fn restore_risk(repo: &Repo, args: &[String]) -> Option<GitRisk> {
+    let staged_only = args.iter().any(|arg| arg == "--staged" || arg == "-S")
+        && !args.iter().any(|arg| arg == "--worktree" || arg == "-W");
+    if staged_only {
+        return None;
+    }
+
+    let mut pathspec = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-s" || arg == "--source" {
+            iter.next();
+        } else if !arg.starts_with('-') {
+            pathspec.push(arg.as_str());
+        }
+    }
+    discard_risk(repo, &pathspec, "restore")
+}
+
+fn switch_risk(repo: &Repo, args: &[String]) -> Option<GitRisk> {
+    let discards = args.iter().any(|arg| arg == "--discard-changes" || arg == "--force")
+        || has_short_flag(args, 'f');
+    discards.then(|| discard_risk(repo, &[], "switch --discard-changes")).flatten()
+}
+
+fn stash_risk(repo: &Repo, args: &[String]) -> Option<GitRisk> {
+    match args.first().map(String::as_str) {
+        Some("clear") => {
+            let stashes = repo.git(&["stash", "list"]).unwrap_or_default().lines().count();
+            Some(GitRisk {
+                summary: format!("git stash clear will permanently drop {}", plural(stashes, "stash")),
+                protected_branch: None,
+            })
+        }
+        Some("drop") => Some(GitRisk {
+            summary: format!(
+                "git stash drop will permanently drop {}",
+                args.get(1).map(String::as_str).unwrap_or("the latest stash")
+            ),
+            protected_branch: None,
+        }),
+        _ => None,
+    }
+}
+
+fn branch_risk(repo: &Repo, args: &[String], config: &GitConfig) -> Option<GitRisk> {
+    let forced = args.iter().any(|arg| arg == "-D")
+        || (args.iter().any(|arg| arg == "-d" || arg == "--delete")
+            && args.iter().any(|arg| arg == "-f" || arg == "--force"));
+    if !forced {
+        return None;
+    }
+
+    let branches = operands(args);
+    let mut parts = Vec::new();
+    let mut protected_branch = None;
+    for branch in &branches {
+        let unmerged = repo.count(&["rev-list", "--count", branch, "--not", "HEAD", "--remotes"]);
+        parts.push(format!("'{}' ({} not merged into HEAD or pushed)", branch, plural(unmerged, "commit")));
+        if config.is_protected(branch) {
+            protected_branch.get_or_insert_with(|| branch.to_string());
+        }
+    }
+
+    Some(GitRisk {
+        summary: format!("git branch -D will delete {}", parts.join(", ")),
+        protected_branch,
+    })
+}
+
+fn push_risk(repo: &Repo, args: &[String], config: &GitConfig) -> Option<GitRisk> {
+    let operands = operands(args);
+    let refspecs = operands.get(1..).unwrap_or_default();
+
+    let forced = args.iter().any(|arg| {
+        matches!(arg.as_str(), "--force" | "--mirror" | "--delete")
+            || arg.starts_with("--force-with-lease")
+    }) || has_short_flag(args, 'f')
+        || has_short_flag(args, 'd')
+        || refspecs.iter().any(|refspec| refspec.starts_with('+') || refspec.starts_with(':'));
+    if !forced {
+        return None;
+    }
+
+    let deleting = args.iter().any(|arg| arg == "--delete") || has_short_flag(args, 'd')
+        || refspecs.iter().any(|refspec| refspec.starts_with(':'));
+    let remote = operands.first().copied().unwrap_or("origin");
+    let branch = refspecs.first()
+        .map(|refspec| {
+            let refspec = refspec.trim_start_matches('+');
+            refspec.rsplit(':').next().unwrap_or(refspec).to_string()
+        })
+        .or_else(|| repo.current_branch())
+        .unwrap_or_else(|| "HEAD".to_string());
+
+    let summary = if deleting {
+        format!("git push will delete remote branch {}/{}", remote, branch)
+    } else {
+        // Commits on the remote branch (as of the last fetch) that the push would drop
+        let range = format!("HEAD..{}/{}", remote, branch);
+        let dropped = repo.count(&["rev-list", "--count", &range]);
+        format!(
+            "git push --force will overwrite {}/{}, dropping {} not in your branch (as of the last fetch)",
+            remote, branch, plural(dropped, "commit")
+        )
+    };
+
+    Some(GitRisk {
+        summary,
+        protected_branch: config.is_protected(&branch).then_some(branch),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    fn repo_with_changes() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path();
+        git(path, &["init", "-q", "-b", "main"]);
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(path.join(name), "original").unwrap();
+        }
+        git(path, &["add", "."]);
+        git(path, &["commit", "-q", "-m", "initial"]);
+
+        fs::write(path.join("a.txt"), "changed").unwrap();
+        fs::write(path.join("b.txt"), "changed").unwrap();
+        git(path, &["add", "b.txt"]);
+        fs::write(path.join("untracked.txt"), "new").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_unchecked_subcommands() {
+        assert_eq!(unchecked("cd /srv/app && git -C repo reset --hard && git status"), vec!["git reset"]);
+        assert_eq!(unchecked("sudo git clean -fd; git log"), vec!["git clean"]);
+        assert!(unchecked("git status && ls").is_empty());
+    }
+
+    #[test]
+    fn test_reset_hard_counts_changes() {
+        let dir = repo_with_changes();
+        let risks = analyze("git reset --hard", dir.path(), &GitConfig::default());
+        assert_eq!(risks.len(), 1);
+        assert_eq!(
+            risks[0].summary,
+            "git reset --hard will discard 2 modified files (1 staged) on branch 'main'"
+        );
+
+        // Soft resets are not risky
+        assert!(analyze("git reset HEAD b.txt", dir.path(), &GitConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn test_clean_and_checkout() {
+        let dir = repo_with_changes();
+        let config = GitConfig::default();
+
+        let risks = analyze("git clean -fdx", dir.path(), &config);
+        assert_eq!(risks[0].summary, "git clean will permanently delete 1 untracked file or directory including ignored files");
+
+        let risks = analyze("git checkout -- .", dir.path(), &config);
+        assert!(risks[0].summary.contains("discard 2 modified files"));
+
+        // Switching branches is not risky, and neither is anything outside a repository
+        assert!(analyze("git checkout -b feature", dir.path(), &config).is_empty());
+        assert!(analyze("git clean -fd", Path::new("/"), &config).is_empty());
+    }
+
+    #[test]
+    fn test_branch_and_push() {
+        let dir = repo_with_changes();
+        let config = GitConfig::default();
+        git(dir.path(), &["branch", "feature"]);
+
+        let risks = analyze("git branch -D feature", dir.path(), &config);
+        assert_eq!(risks[0].summary, "git branch -D will delete 'feature' (0 commits not merged into HEAD or pushed)");
+        assert_eq!(risks[0].protected_branch, None);
+
+        let risks = analyze("git push --force origin main", dir.path(), &config);
+        assert_eq!(risks[0].protected_branch.as_deref(), Some("main"));
+        assert!(analyze("git push origin main", dir.path(), &config).is_empty());
+    }
+
+    #[test]
+    fn test_protected_branch_patterns() {
+        let config = GitConfig::default();
+        assert!(config.is_protected("main"));
+        assert!(config.is_protected("release/1.2"));
+        assert!(!config.is_protected("feature/release"));
+    }
+}
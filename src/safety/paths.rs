@@ -0,0 +1,546 @@
+use std::path::{Component, Path, PathBuf};
+
+use super::parse::{split_segments, Segment};
+
+/// Shells whose `-c` argument is itself a command line
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh"];
+
+/// xargs options whose value is a separate word
+const XARGS_VALUE_FLAGS: &[&str] = &[
+    "-a", "-d", "-E", "-I", "-L", "-n", "-P", "-s",
+    "--arg-file", "--delimiter", "--max-args", "--max-chars", "--max-lines", "--max-procs",
+];
+
+/// Stands in for the operands `xargs` reads from stdin
+const STDIN_OPERAND: &str = "<paths from stdin>";
+
+/// How a command touches a path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathOperation {
+    Write,
+    Delete,
+    Move,
+    Permissions,
+    Redirect,
+}
+
+impl PathOperation {
+    pub fn verb(self) -> &'static str {
+        match self {
+            PathOperation::Write => "write to",
+            PathOperation::Delete => "delete",
+            PathOperation::Move => "move",
+            PathOperation::Permissions => "change permissions of",
+            PathOperation::Redirect => "redirect output to",
+        }
+    }
+
+    /// Operations that also affect everything below the target directory
+    fn affects_descendants(self) -> bool {
+        matches!(self, PathOperation::Delete | PathOperation::Move | PathOperation::Permissions)
+    }
+}
+
+/// A path a command would modify, resolved against the execution directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathTarget {
+    pub operation: PathOperation,
+    pub program: String,
+    /// The path as written in the command
+    pub path: String,
+    pub resolved: PathBuf,
+    /// The path is only known when the command runs: read by `xargs`, or built from
+    /// variables or command substitution
+    pub dynamic: bool,
+}
+
+impl PathTarget {
+    /// Whether this operation would modify the protected entry. Entries starting with
+    /// `~/`, `$HOME/` or `/` cover that path and everything below it, bare names such
+    /// as `.git` match a path component anywhere. A dynamic target may be any path
+    pub fn touches(&self, entry: &str, cwd: &Path) -> bool {
+        if self.dynamic {
+            return true;
+        }
+        if !entry.contains('/') {
+            return self.resolved.components().any(|c| c.as_os_str() == entry);
+        }
+
+        let protected = resolve(entry, cwd);
+        self.resolved.starts_with(&protected)
+            || (self.operation.affects_descendants() && protected.starts_with(&self.resolved))
+    }
+
+    /// The path as written with `~` and `$HOME` expanded to `home` and `.`/`..` folded,
+    /// without looking at any filesystem
+    pub fn lexical_path(&self, home: &Path) -> PathBuf {
+        lexical(&self.path, home)
+    }
+
+    /// Like `touches`, judged from the command text alone for a command that runs on another
+    /// machine whose home directory is `home`. `None` when the answer depends on the working
+    /// directory there
+    pub fn names(&self, entry: &str, home: &Path) -> Option<bool> {
+        if self.dynamic {
+            return Some(true);
+        }
+        let path = self.lexical_path(home);
+        if !entry.contains('/') {
+            return Some(path.components().any(|c| c.as_os_str() == entry));
+        }
+        if !path.is_absolute() {
+            return None;
+        }
+
+        let protected = lexical(entry, home);
+        Some(path.starts_with(&protected) || (self.operation.affects_descendants() && protected.starts_with(&path)))
+    }
+}
+
+/// Find every path the command would write, delete, move, chmod or redirect into
+pub fn find_targets(command: &str, cwd: &Path) -> Vec<PathTarget> {
+    let mut cwd = cwd.to_path_buf();
+    let mut targets = Vec::new();
+
+    for segment in split_segments(command) {
+        for redirect in segment.redirects.iter().filter(|r| r.is_output()) {
+            targets.push(target(PathOperation::Redirect, "redirect", &redirect.target, &cwd));
+        }
+
+        let Some((program, args)) = segment.program_and_args() else {
+            continue;
+        };
+
+        // Later statements run in the directory a `cd` moved to
+        if program == "cd" {
+            if let Some(dir) = args.first() {
+                cwd = resolve(dir, &cwd);
+            }
+            continue;
+        }
+
+        targets.extend(command_targets(program, args, &cwd));
+    }
+
+    targets
+}
+
+/// Paths one program would modify, following `sh -c`, `xargs` and `find -exec` into the
+/// command they run
+fn command_targets(program: &str, args: &[String], cwd: &Path) -> Vec<PathTarget> {
+    if SHELLS.contains(&program) {
+        return shell_script(args).map(|script| find_targets(script, cwd)).unwrap_or_default();
+    }
+    match program {
+        "xargs" => xargs_targets(args, cwd),
+        "find" => find_targets_of_find(args, cwd),
+        _ => operations(program, args).into_iter()
+            .map(|(operation, path)| target(operation, program, &path, cwd))
+            .collect(),
+    }
+}
+
+/// Targets of a command given as words, skipping wrappers such as `sudo`
+fn nested_targets(tokens: Vec<String>, cwd: &Path) -> Vec<PathTarget> {
+    let segment = Segment { tokens, redirects: Vec::new(), piped: false };
+    match segment.program_and_args() {
+        Some((program, args)) => command_targets(program, args, cwd),
+        None => Vec::new(),
+    }
+}
+
+/// The script passed to a shell with `-c` (also combined, as in `-ec`)
+fn shell_script(args: &[String]) -> Option<&str> {
+    let mut command_flag = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            continue;
+        } else if arg == "-o" || arg == "+o" {
+            iter.next();
+        } else if arg.starts_with("--") {
+            continue;
+        } else if arg.starts_with('-') || arg.starts_with('+') {
+            command_flag |= arg.contains('c');
+        } else {
+            return command_flag.then_some(arg.as_str());
+        }
+    }
+    None
+}
+
+/// `xargs <program>` runs the program on paths read from stdin
+fn xargs_targets(args: &[String], cwd: &Path) -> Vec<PathTarget> {
+    let mut replace = None;
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        if arg == "--" {
+            index += 1;
+            break;
+        }
+        if !arg.starts_with('-') {
+            break;
+        }
+        if arg == "-I" {
+            replace = args.get(index + 1).cloned();
+        } else if let Some(value) = arg.strip_prefix("--replace=").or_else(|| arg.strip_prefix("-I")) {
+            replace = Some(value.to_string());
+        } else if arg == "-i" || arg == "--replace" {
+            replace = Some("{}".to_string());
+        }
+        index += if XARGS_VALUE_FLAGS.contains(&arg.as_str()) { 2 } else { 1 };
+    }
+
+    let mut tokens: Vec<String> = args.get(index..).unwrap_or_default().to_vec();
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+    match replace.filter(|replace| !replace.is_empty()) {
+        Some(replace) => {
+            for token in tokens.iter_mut().skip(1) {
+                *token = token.replace(&replace, STDIN_OPERAND);
+            }
+        }
+        None => tokens.push(STDIN_OPERAND.to_string()),
+    }
+    nested_targets(tokens, cwd)
+}
+
+/// `find` deletes its starting points with `-delete` and runs `-exec`/`-execdir`/`-ok`
+/// commands on them
+fn find_targets_of_find(args: &[String], cwd: &Path) -> Vec<PathTarget> {
+    // Starting points come before the first expression
+    let mut roots: Vec<&str> = args.iter()
+        .take_while(|arg| !arg.starts_with('-') && *arg != "(" && *arg != "!")
+        .map(String::as_str)
+        .collect();
+    if roots.is_empty() {
+        roots.push(".");
+    }
+
+    let mut targets = Vec::new();
+    if args.iter().any(|arg| arg == "-delete") {
+        targets.extend(roots.iter().map(|root| target(PathOperation::Delete, "find", root, cwd)));
+    }
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if !matches!(arg.as_str(), "-exec" | "-execdir" | "-ok" | "-okdir") {
+            continue;
+        }
+        let command: Vec<&String> = iter.by_ref().take_while(|word| *word != ";" && *word != "+").collect();
+        for root in &roots {
+            let tokens = command.iter().map(|word| word.replace("{}", root)).collect();
+            targets.extend(nested_targets(tokens, cwd));
+        }
+    }
+    targets
+}
+
+fn target(operation: PathOperation, program: &str, path: &str, cwd: &Path) -> PathTarget {
+    let dynamic = is_dynamic(path);
+    PathTarget {
+        operation,
+        program: program.to_string(),
+        path: path.to_string(),
+        resolved: if dynamic { PathBuf::from(path) } else { resolve(path, cwd) },
+        dynamic,
+    }
+}
+
+/// Whether the path depends on stdin, variables other than `$HOME`, or command substitution
+fn is_dynamic(path: &str) -> bool {
+    let path = path.trim_matches(|c| c == '\'' || c == '"');
+    path.contains(STDIN_OPERAND) || expand_home(path).to_string_lossy().contains(['$', '`'])
+}
+
+/// Non-flag arguments, skipping the values of the given flags
+fn operands<'a>(args: &'a [String], value_flags: &[&str]) -> Vec<&'a str> {
+    let mut result = Vec::new();
+    let mut iter = args.iter();
+    let mut options_done = false;
+    while let Some(arg) = iter.next() {
+        if options_done {
+            result.push(arg.as_str());
+        } else if arg == "--" {
+            options_done = true;
+        } else if value_flags.contains(&arg.as_str()) {
+            iter.next();
+        } else if !arg.starts_with('-') || arg == "-" {
+            result.push(arg.as_str());
+        }
+    }
+    result
+}
+
+/// The value of a flag given as `-o value`, `--output value` or `--output=value`
+fn flag_values<'a>(args: &'a [String], flags: &[&str]) -> Vec<&'a str> {
+    let mut values = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if flags.contains(&arg.as_str()) {
+            if let Some(value) = iter.next() {
+                values.push(value.as_str());
+            }
+        } else if let Some((flag, value)) = arg.split_once('=') {
+            if flags.contains(&flag) {
+                values.push(value);
+            }
+        }
+    }
+    values
+}
+
+fn operations(program: &str, args: &[String]) -> Vec<(PathOperation, String)> {
+    use PathOperation::*;
+
+    let each = |operation: PathOperation, paths: Vec<&str>| -> Vec<(PathOperation, String)> {
+        paths.into_iter()
+            .filter(|path| !path.is_empty() && *path != "-")
+            .map(|path| (operation, path.to_string()))
+            .collect()
+    };
+    let has_flag = |flags: &[&str]| args.iter().any(|arg| flags.contains(&arg.as_str()));
+
+    match program {
+        "rm" | "rmdir" | "unlink" | "shred" | "srm" | "trash" => each(Delete, operands(args, &[])),
+        "chmod" | "chown" | "chgrp" | "chattr" | "chflags" => {
+            // The first operand is the mode or owner unless --reference is used
+            let mut paths = operands(args, &[]);
+            if !args.iter().any(|arg| arg.starts_with("--reference")) && !paths.is_empty() {
+                paths.remove(0);
+            }
+            each(Permissions, paths)
+        }
+        "setfacl" => each(Permissions, operands(args, &["-m", "-x", "-M", "-X"])),
+        "mv" => {
+            let mut result = each(Move, operands(args, &["-t", "-S"]));
+            result.extend(each(Write, flag_values(args, &["-t", "--target-directory"])));
+            result
+        }
+        "cp" | "install" | "ln" => {
+            let paths = operands(args, &["-t", "-S", "-m", "-o", "-g"]);
+            let mut result = each(Write, flag_values(args, &["-t", "--target-directory"]));
+            if result.is_empty() && paths.len() > 1 {
+                result.extend(each(Write, paths.last().copied().into_iter().collect()));
+            }
+            result
+        }
+        "rsync" => {
+            let paths = operands(args, &["-e", "--rsh"]);
+            let destination: Vec<&str> = paths.last()
+                .filter(|path| paths.len() > 1 && !path.contains(':'))
+                .copied()
+                .into_iter()
+                .collect();
+            let deletes = args.iter().any(|arg| arg.starts_with("--delete") || arg == "--remove-source-files");
+            each(if deletes { Delete } else { Write }, destination)
+        }
+        "dd" => each(Write, args.iter().filter_map(|arg| arg.strip_prefix("of=")).collect()),
+        "tee" | "touch" | "mkdir" => each(Write, operands(args, &["-m", "-d", "-r", "-t"])),
+        "truncate" => each(Write, operands(args, &["-s", "-r", "--size", "--reference"])),
+        "sed" | "perl" if args.iter().any(|arg| {
+            arg == "--in-place" || arg.starts_with("--in-place=")
+                || (arg.starts_with('-') && !arg.starts_with("--") && arg.contains('i'))
+        }) => {
+            let mut paths = operands(args, &["-e", "-f", "--expression", "--file"]);
+            // Without -e the first operand is the script
+            if !has_flag(&["-e", "-f", "--expression", "--file"]) && !paths.is_empty() {
+                paths.remove(0);
+            }
+            each(Write, paths)
+        }
+        "curl" => each(Write, flag_values(args, &["-o", "--output"])),
+        "wget" => {
+            let mut result = each(Write, flag_values(args, &["-O", "--output-document"]));
+            result.extend(each(Write, flag_values(args, &["-P", "--directory-prefix"])));
+            result
+        }
+        "tar" => each(Write, flag_values(args, &["-C", "--directory"])),
+        "unzip" => each(Write, flag_values(args, &["-d"])),
+        _ => Vec::new(),
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    let home = dirs_next::home_dir();
+    for prefix in ["~/", "$HOME/", "${HOME}/"] {
+        if let (Some(rest), Some(home)) = (path.strip_prefix(prefix), home.as_ref()) {
+            return home.join(rest);
+        }
+    }
+    match (path, home) {
+        ("~" | "$HOME" | "${HOME}", Some(home)) => home,
+        _ => PathBuf::from(path),
+    }
+}
+
+fn lexical(path: &str, home: &Path) -> PathBuf {
+    let path = path.trim_matches(|c| c == '\'' || c == '"');
+    let expanded = match ["~/", "$HOME/", "${HOME}/"].iter().find_map(|prefix| path.strip_prefix(prefix)) {
+        Some(rest) => home.join(rest),
+        None if matches!(path, "~" | "$HOME" | "${HOME}") => home.to_path_buf(),
+        None => PathBuf::from(path),
+    };
+
+    let mut folded = PathBuf::new();
+    for component in expanded.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                folded.pop();
+            }
+            other => folded.push(other.as_os_str()),
+        }
+    }
+    folded
+}
+
+/// Resolve `~`, `$HOME`, `.`, `..` and symlinks relative to `cwd`. Components that do
+/// not exist yet (or globs) are appended to the canonical form of the deepest existing parent
+pub fn resolve(path: &str, cwd: &Path) -> PathBuf {
+    let path = path.trim_matches(|c| c == '\'' || c == '"');
+    let expanded = expand_home(path);
+    let absolute = if expanded.is_absolute() {
+        expanded
+    } else {
+        cwd.join(expanded)
+    };
+
+    let mut existing = absolute.as_path();
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            let mut resolved = canonical;
+            for component in rest.iter().rev() {
+                match component {
+                    Component::ParentDir => {
+                        resolved.pop();
+                    }
+                    Component::CurDir => {}
+                    other => resolved.push(other.as_os_str()),
+                }
+            }
+            return resolved;
+        }
+        match (existing.parent(), existing.components().next_back()) {
+            (Some(parent), Some(component)) => {
+                rest.push(component);
+                existing = parent;
+            }
+            _ => return absolute,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn ops(command: &str, cwd: &Path) -> Vec<(PathOperation, String)> {
+        find_targets(command, cwd).into_iter().map(|t| (t.operation, t.path)).collect()
+    }
+
+    #[test]
+    fn test_find_targets() {
+        let cwd = Path::new("/tmp");
+        assert_eq!(ops("rm -rf build dist", cwd), vec![
+            (PathOperation::Delete, "build".to_string()),
+            (PathOperation::Delete, "dist".to_string()),
+        ]);
+        assert_eq!(ops("chmod -R 700 ~/.ssh", cwd), vec![(PathOperation::Permissions, "~/.ssh".to_string())]);
+        assert_eq!(ops("echo key >> ~/.ssh/authorized_keys 2>&1", cwd), vec![
+            (PathOperation::Redirect, "~/.ssh/authorized_keys".to_string()),
+        ]);
+        assert_eq!(ops("sed -i 's/a/b/' /etc/hosts", cwd), vec![(PathOperation::Write, "/etc/hosts".to_string())]);
+        assert_eq!(ops("cp a.txt b.txt dest/", cwd), vec![(PathOperation::Write, "dest/".to_string())]);
+        assert_eq!(ops("find . -name '*.tmp' -delete", cwd), vec![(PathOperation::Delete, ".".to_string())]);
+        assert!(ops("cat /etc/hosts | grep localhost", cwd).is_empty());
+    }
+
+    #[test]
+    fn test_nested_commands() {
+        let cwd = Path::new("/tmp");
+        assert_eq!(ops("bash -c \"echo x > ~/.ssh/authorized_keys\"", cwd), vec![
+            (PathOperation::Redirect, "~/.ssh/authorized_keys".to_string()),
+        ]);
+        assert_eq!(ops("find ~/.ssh -type f -exec shred {} +", cwd), vec![
+            (PathOperation::Delete, "~/.ssh".to_string()),
+        ]);
+        assert_eq!(ops("find . -name '*.bak' -exec sudo chmod 600 {} \\;", cwd), vec![
+            (PathOperation::Permissions, ".".to_string()),
+        ]);
+        assert!(ops("find ~/.ssh -name '*.pub' -exec cat {} +", cwd).is_empty());
+
+        let xargs = find_targets("echo ~/.ssh/id_rsa | xargs rm", cwd);
+        assert_eq!(xargs.len(), 1);
+        assert_eq!(xargs[0].operation, PathOperation::Delete);
+        assert!(xargs[0].dynamic && xargs[0].touches("~/.ssh", cwd));
+        assert!(find_targets("ls | xargs -I{} cp {} ~/.ssh/", cwd).iter().any(|t| t.touches("~/.ssh", cwd)));
+        assert!(find_targets("ls | xargs wc -l", cwd).is_empty());
+    }
+
+    #[test]
+    fn test_dynamic_targets() {
+        let cwd = Path::new("/tmp");
+        let dynamic = |command: &str| find_targets(command, cwd).iter().any(|t| t.dynamic);
+        assert!(dynamic("rm -rf \"$TARGET\""));
+        assert!(dynamic("touch $(cat list.txt)"));
+        assert!(dynamic("find . -exec sh -c 'rm \"$1\"' _ {} \\;"));
+        assert!(!find_targets("rm -rf $HOME/tmp", cwd)[0].dynamic);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("real")).unwrap();
+        std::os::unix::fs::symlink(root.join("real"), root.join("link")).unwrap();
+
+        assert_eq!(resolve("link/file.txt", &root), root.join("real/file.txt"));
+        assert_eq!(resolve("real/../real/./new/x", &root), root.join("real/new/x"));
+        assert_eq!(resolve("/definitely/missing/../path", &root), PathBuf::from("/definitely/path"));
+        if let Some(home) = dirs_next::home_dir().and_then(|h| h.canonicalize().ok()) {
+            assert_eq!(resolve("$HOME/.ssh", &root), home.join(".ssh"));
+        }
+    }
+
+    #[test]
+    fn test_cd_changes_resolution() {
+        let targets = find_targets("cd /tmp && rm notes.txt", Path::new("/"));
+        let tmp = Path::new("/tmp").canonicalize().unwrap();
+        assert_eq!(targets[0].resolved, tmp.join("notes.txt"));
+    }
+
+    #[test]
+    fn test_touches() {
+        let cwd = Path::new("/");
+        let home = dirs_next::home_dir().unwrap();
+
+        let write = &find_targets("touch ~/.ssh/config", cwd)[0];
+        assert!(write.touches("~/.ssh", cwd));
+        assert!(!write.touches("~/.gnupg", cwd));
+
+        // Deleting a parent directory deletes the protected path too
+        let delete = &find_targets(&format!("rm -r {}", home.display()), cwd)[0];
+        assert!(delete.touches("~/.ssh", cwd));
+
+        let git = &find_targets("rm -rf project/.git/hooks", cwd)[0];
+        assert!(git.touches(".git", cwd));
+    }
+
+    #[test]
+    fn test_names_without_filesystem() {
+        let cwd = Path::new("/");
+        let home = Path::new("/home/deploy");
+
+        let names = |command: &str, entry: &str| find_targets(command, cwd)[0].names(entry, home);
+        assert_eq!(names("echo x >> ~/.ssh/authorized_keys", "~/.ssh"), Some(true));
+        assert_eq!(names("rm -rf /home/deploy/../deploy", "~/.ssh"), Some(true));
+        assert_eq!(names("rm -rf /srv/app/.git", ".git"), Some(true));
+        assert_eq!(names("touch /tmp/x", "~/.ssh"), Some(false));
+        // Relative paths depend on the remote working directory
+        assert_eq!(names("rm -rf .ssh", "~/.ssh"), None);
+    }
+}
@@ -0,0 +1,324 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use super::parse::{is_assignment, program_name, split_segments, wrapper_option_takes_value, Segment};
+use super::paths::resolve;
+
+/// Programs that run a command as another user
+const ELEVATION_TOOLS: &[&str] = &["sudo", "doas", "pkexec", "su"];
+
+/// Tools that manage per-user installs and misbehave (or refuse to run) as root
+const USER_TOOLS: &[&str] = &["brew", "cargo", "rustup"];
+
+/// Commands that only read the files they are given
+const READ_TOOLS: &[&str] = &[
+    "ls", "cat", "head", "tail", "less", "more", "wc", "stat", "file", "grep", "egrep",
+    "fgrep", "diff", "md5sum", "shasum", "sha256sum", "readlink", "realpath",
+];
+
+/// Settings for privilege escalation (`[safety.privilege]` in config.toml)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivilegeConfig {
+    /// When false, any command using sudo, doas, pkexec or su is blocked, even with --force
+    pub allow_elevation: bool,
+    /// Password helper for `sudo -A`; without one, sudo asks on the terminal
+    pub askpass: Option<String>,
+}
+
+impl Default for PrivilegeConfig {
+    fn default() -> Self {
+        Self {
+            allow_elevation: true,
+            askpass: None,
+        }
+    }
+}
+
+/// The part of a command that runs with another user's privileges
+#[derive(Debug, Clone, PartialEq)]
+pub struct Elevation {
+    pub tool: String,
+    /// `root` unless `-u`/`--user` (or su's user argument) says otherwise
+    pub user: String,
+    /// Program and arguments run elevated; empty for an interactive shell
+    pub argv: Vec<String>,
+    /// Output redirections on the segment, which the calling shell performs unprivileged
+    pub redirects: Vec<String>,
+    /// Why the elevated part works just as well without root
+    pub unneeded: Option<String>,
+}
+
+impl Elevation {
+    /// The exact elevated command line
+    pub fn command(&self) -> String {
+        shell_words::join(&self.argv)
+    }
+
+    pub fn describe(&self) -> String {
+        let mut description = if self.argv.is_empty() {
+            format!("{} opens an interactive shell as {}", self.tool, self.user)
+        } else {
+            format!("{} runs '{}' as {}", self.tool, self.command(), self.user)
+        };
+        for target in &self.redirects {
+            description.push_str(&format!(
+                " (the redirection to '{}' is done by your shell, not as {})",
+                target, self.user
+            ));
+        }
+        description
+    }
+}
+
+/// Whether the word runs its arguments as another user
+pub fn is_elevation_tool(program: &str) -> bool {
+    ELEVATION_TOOLS.contains(&program_name(program))
+}
+
+/// Find every sudo, doas, pkexec and su invocation in a command line
+pub fn analyze(command: &str, cwd: &Path) -> Vec<Elevation> {
+    let mut cwd = cwd.to_path_buf();
+    let mut elevations = Vec::new();
+
+    for segment in split_segments(command) {
+        if let Some(("cd", args)) = segment.program_and_args() {
+            if let Some(dir) = args.first() {
+                cwd = resolve(dir, &cwd);
+            }
+            continue;
+        }
+        elevations.extend(elevation(&segment, &cwd));
+    }
+
+    elevations
+}
+
+/// A single simple command without its sudo or doas prefix when running it as root
+/// is unnecessary, with the reason
+pub fn drop_unneeded(command: &str, cwd: &Path) -> Option<(String, String)> {
+    let segments = split_segments(command);
+    let [segment] = segments.as_slice() else {
+        return None;
+    };
+    if !segment.redirects.is_empty() || segment.tokens.first().is_some_and(|t| is_assignment(t)) {
+        return None;
+    }
+    let elevation = elevation(segment, cwd)?;
+    let reason = elevation.unneeded.clone()?;
+    Some((elevation.command(), reason))
+}
+
+fn elevation(segment: &Segment, cwd: &Path) -> Option<Elevation> {
+    let start = segment.tokens.iter().position(|token| !is_assignment(token))?;
+    let tool = program_name(&segment.tokens[start]);
+    if !is_elevation_tool(tool) {
+        return None;
+    }
+    let args = &segment.tokens[start + 1..];
+
+    let (user, argv) = if tool == "su" {
+        su_arguments(args)
+    } else {
+        wrapper_arguments(tool, args)
+    };
+
+    let unneeded = match (tool, argv.split_first()) {
+        ("sudo" | "doas", Some((program, args))) if user == "root" => unneeded(program_name(program), args, cwd),
+        _ => None,
+    };
+
+    Some(Elevation {
+        tool: tool.to_string(),
+        user,
+        argv,
+        redirects: segment.redirects.iter()
+            .filter(|redirect| redirect.is_output())
+            .map(|redirect| redirect.target.clone())
+            .collect(),
+        unneeded,
+    })
+}
+
+/// Target user and command of `sudo`, `doas` and `pkexec`
+fn wrapper_arguments(tool: &str, args: &[String]) -> (String, Vec<String>) {
+    let mut user = "root".to_string();
+    let mut index = 0;
+    while index < args.len() {
+        let arg = &args[index];
+        if arg == "--" {
+            index += 1;
+            break;
+        }
+        if !arg.starts_with('-') && !is_assignment(arg) {
+            break;
+        }
+        if wrapper_option_takes_value(tool, arg) {
+            if matches!(arg.as_str(), "-u" | "--user") {
+                if let Some(value) = args.get(index + 1) {
+                    user = value.clone();
+                }
+            }
+            index += 2;
+            continue;
+        }
+        if let Some(value) = arg.strip_prefix("--user=").or_else(|| arg.strip_prefix("-u").filter(|v| !v.is_empty())) {
+            user = value.to_string();
+        }
+        index += 1;
+    }
+    (user, args[index.min(args.len())..].to_vec())
+}
+
+/// Target user and `-c` command of `su`
+fn su_arguments(args: &[String]) -> (String, Vec<String>) {
+    let mut user = "root".to_string();
+    let mut command = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-c" | "--command" => {
+                if let Some(value) = iter.next() {
+                    command = shell_words::split(value).unwrap_or_else(|_| vec![value.clone()]);
+                }
+            }
+            "-s" | "--shell" | "-g" | "--group" => {
+                iter.next();
+            }
+            _ if arg.starts_with("--command=") => {
+                let value = &arg["--command=".len()..];
+                command = shell_words::split(value).unwrap_or_else(|_| vec![value.to_string()]);
+            }
+            _ if arg.starts_with('-') => {}
+            _ => user = arg.clone(),
+        }
+    }
+    (user, command)
+}
+
+fn unneeded(program: &str, args: &[String], cwd: &Path) -> Option<String> {
+    if USER_TOOLS.contains(&program) {
+        return Some(format!("'{}' manages per-user installs and should not run as root", program));
+    }
+    if !READ_TOOLS.contains(&program) {
+        return None;
+    }
+
+    // Directories may hold entries only root can read, and the output would silently miss them
+    let paths = read_paths(program, args)?;
+    let all_readable = paths.iter()
+        .map(|path| resolve(path, cwd))
+        .all(|path| readable(&path) && !path.is_dir());
+    all_readable.then(|| format!("'{}' only reads files you can already read", program))
+}
+
+/// The files a read-only command would open, or `None` when that cannot be told statically
+fn read_paths(program: &str, args: &[String]) -> Option<Vec<String>> {
+    let grep = program.ends_with("grep");
+    let value_options: &[&str] = match program {
+        "head" | "tail" => &["-n", "-c"],
+        _ if grep => &["-e", "-f", "-m", "-A", "-B", "-C", "--regexp", "--file"],
+        _ => &[],
+    };
+
+    let mut paths = Vec::new();
+    let mut pattern_given = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg.chars().any(|c| matches!(c, '*' | '?' | '[' | '$' | '`')) {
+            return None;
+        }
+        if is_recursive_flag(arg) {
+            return None;
+        }
+        if value_options.contains(&arg.as_str()) {
+            pattern_given |= matches!(arg.as_str(), "-e" | "-f" | "--regexp" | "--file");
+            iter.next();
+            continue;
+        }
+        if arg.starts_with('-') && arg.len() > 1 {
+            continue;
+        }
+        if grep && !pattern_given {
+            pattern_given = true;
+            continue;
+        }
+        paths.push(arg.clone());
+    }
+
+    match (paths.is_empty(), program) {
+        (true, "ls") => Some(vec![".".to_string()]),
+        // Everything else would read from the terminal
+        (true, _) => None,
+        (false, _) => Some(paths),
+    }
+}
+
+/// `-r`, `-R` (also combined, as in `-lR`) or `--recursive`
+fn is_recursive_flag(arg: &str) -> bool {
+    arg == "--recursive" || (arg.starts_with('-') && !arg.starts_with("--") && arg.contains(['r', 'R']))
+}
+
+/// Whether the current user can read the file, or list and enter the directory
+fn readable(path: &Path) -> bool {
+    let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mode = if path.is_dir() { libc::R_OK | libc::X_OK } else { libc::R_OK };
+    path.exists() && unsafe { libc::access(c_path.as_ptr(), mode) } == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_elevated_part() {
+        let cwd = Path::new("/");
+        let elevations = analyze("apt update && sudo -u postgres psql -c 'select 1' | sudo -E tee /etc/motd", cwd);
+        assert_eq!(elevations.len(), 2);
+        assert_eq!(elevations[0].user, "postgres");
+        assert_eq!(elevations[0].command(), "psql -c 'select 1'");
+        assert_eq!(elevations[1].describe(), "sudo runs 'tee /etc/motd' as root");
+
+        let elevations = analyze("sudo echo 1 > /proc/sys/vm/drop_caches; su - admin -c 'systemctl restart nginx'; su", cwd);
+        assert!(elevations[0].describe().contains("redirection to '/proc/sys/vm/drop_caches' is done by your shell"));
+        assert_eq!(elevations[1].user, "admin");
+        assert_eq!(elevations[1].argv, vec!["systemctl", "restart", "nginx"]);
+        assert_eq!(elevations[2].describe(), "su opens an interactive shell as root");
+
+        assert!(analyze("ls -la && echo sudo", cwd).is_empty());
+    }
+
+    #[test]
+    fn test_unneeded_elevation() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hi").unwrap();
+
+        let (command, reason) = drop_unneeded("sudo cat notes.txt", dir.path()).unwrap();
+        assert_eq!(command, "cat notes.txt");
+        assert!(reason.contains("only reads"));
+        assert_eq!(drop_unneeded("sudo grep -n hi notes.txt", dir.path()).unwrap().0, "grep -n hi notes.txt");
+        assert!(drop_unneeded("sudo brew install jq", dir.path()).unwrap().1.contains("per-user"));
+
+        // Files that may not be readable, other users, globs, pipelines and writes keep sudo
+        assert!(drop_unneeded("sudo cat missing.txt", dir.path()).is_none());
+        assert!(drop_unneeded("sudo -u www-data cat notes.txt", dir.path()).is_none());
+        assert!(drop_unneeded("sudo cat *.txt", dir.path()).is_none());
+        assert!(drop_unneeded("sudo cat notes.txt | head", dir.path()).is_none());
+        assert!(drop_unneeded("sudo cp notes.txt /etc/", dir.path()).is_none());
+        assert!(drop_unneeded("sudo cat", dir.path()).is_none());
+
+        // Recursion and directories can reach entries that are not readable
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        assert!(drop_unneeded("sudo ls", dir.path()).is_none());
+        assert!(drop_unneeded("sudo ls -R sub", dir.path()).is_none());
+        assert!(drop_unneeded("sudo diff -r sub sub", dir.path()).is_none());
+        assert!(drop_unneeded("sudo grep -r hi notes.txt", dir.path()).is_none());
+        assert!(drop_unneeded("sudo grep --recursive hi notes.txt", dir.path()).is_none());
+        assert!(drop_unneeded("sudo ls -lR notes.txt", dir.path()).is_none());
+    }
+}
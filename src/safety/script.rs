@@ -0,0 +1,334 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+use super::parse::split_segments;
+use super::{Challenge, SafetyResult};
+
+/// Programs that run a heredoc body as code rather than read it as data
+const HEREDOC_RUNNERS: &[&str] = &[
+    "sh", "bash", "zsh", "dash", "ksh", "fish", "ssh",
+    "python", "perl", "ruby", "node", "php", "lua", "osascript",
+];
+
+/// A single statement of a multi-line script
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    /// 1-based line number where the statement starts
+    pub line: usize,
+    /// The statement with compound-command syntax (`if`, `do`, `fi`, function headers) removed
+    pub text: String,
+}
+
+/// The verdict for one statement of a script
+#[derive(Debug)]
+pub struct LineVerdict {
+    pub line: usize,
+    pub text: String,
+    pub result: SafetyResult,
+}
+
+/// Per-statement safety review of a multi-line script
+#[derive(Debug)]
+pub struct ScriptReport {
+    pub lines: Vec<LineVerdict>,
+}
+
+impl ScriptReport {
+    /// The overall verdict: blocked if any line is blocked, otherwise confirmation if any line needs it
+    pub fn overall(&self) -> SafetyResult {
+        let flagged = self.lines.iter()
+            .filter(|verdict| verdict.result != SafetyResult::Safe)
+            .count();
+
+        if let Some(blocked) = self.lines.iter().find(|v| matches!(v.result, SafetyResult::Blocked(_))) {
+            SafetyResult::Blocked(format!(
+                "Line {} of the script is blocked\n{}",
+                blocked.line,
+                self.render_table()
+            ))
+        } else if flagged > 0 {
+            let reason = format!(
+                "{} of {} script statements need confirmation\n{}",
+                flagged,
+                self.lines.len(),
+                self.render_table()
+            );
+            // The strictest confirmation any line asks for applies to the whole script
+            let challenge = self.lines.iter()
+                .filter_map(|v| match &v.result {
+                    SafetyResult::NeedsTypedConfirmation { challenge, .. } => Some(challenge),
+                    _ => None,
+                })
+                .fold(None, |strongest: Option<&Challenge>, challenge| match strongest {
+                    Some(current) if current.strength() >= challenge.strength() => Some(current),
+                    _ => Some(challenge),
+                });
+            match challenge {
+                Some(challenge) => SafetyResult::NeedsTypedConfirmation { reason, challenge: challenge.clone() },
+                None => SafetyResult::NeedsConfirmation(reason),
+            }
+        } else {
+            SafetyResult::Safe
+        }
+    }
+
+    /// Render a per-line risk table for the confirmation prompt
+    pub fn render_table(&self) -> String {
+        let mut table = String::from("   Line  Risk     Statement\n");
+        for verdict in &self.lines {
+            let (risk, reason) = match &verdict.result {
+                SafetyResult::Safe => ("ok", None),
+                SafetyResult::NeedsConfirmation(reason) => ("CONFIRM", Some(reason)),
+                SafetyResult::NeedsTypedConfirmation { reason, .. } => ("TYPE", Some(reason)),
+                SafetyResult::Blocked(reason) => ("BLOCKED", Some(reason)),
+            };
+            table.push_str(&format!("   {:>4}  {:<7}  {}\n", verdict.line, risk, verdict.text));
+            if let Some(reason) = reason {
+                let reason = reason.lines().next().unwrap_or_default();
+                table.push_str(&format!("{:>17}↳ {}\n", "", reason));
+            }
+        }
+        table.trim_end().to_string()
+    }
+}
+
+fn heredoc_regex() -> &'static Regex {
+    static HEREDOC: OnceLock<Regex> = OnceLock::new();
+    HEREDOC.get_or_init(|| Regex::new(r#"<<-?\s*(?:'([^']+)'|"([^"]+)"|\\?([A-Za-z_][A-Za-z0-9_]*))"#).unwrap())
+}
+
+/// Whether the heredoc starting at `start` is run as code, by the command it is attached to
+/// (`bash <<EOF`, `python3 <<EOF`) or by a command it is piped into (`cat <<EOF | sh`)
+fn runs_heredoc(line: &str, start: usize) -> bool {
+    let first = split_segments(&line[..start]).len().saturating_sub(1);
+    let segments = split_segments(line);
+    segments.iter()
+        .enumerate()
+        .skip(first)
+        .take_while(|(index, segment)| *index == first || segment.piped)
+        .filter_map(|(_, segment)| segment.program())
+        .any(|program| {
+            // Versioned interpreters such as python3.12 or perl5
+            let base = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+            HEREDOC_RUNNERS.contains(&base)
+        })
+}
+
+fn syntax_patterns() -> &'static [(Regex, &'static str)] {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            // Function headers: `name() {` and `function name {`
+            (r"^\s*(?:function\s+)?[A-Za-z_][\w-]*\s*\(\s*\)\s*\{?", ""),
+            (r"^\s*function\s+[A-Za-z_][\w-]*\s*\{?", ""),
+            // Loop headers: `for x in a b; do`, `select x in ...`
+            (r"^\s*(?:for|select)\s+[A-Za-z_]\w*(?:\s+in\b[^;]*)?\s*(?:;\s*|$)", ""),
+            (r"^\s*for\s*\(\(.*?\)\)\s*(?:;\s*|$)", ""),
+            // `case x in` and case patterns such as `start)`
+            (r"^\s*case\b.*\bin\s*$", ""),
+            (r#"^\s*\(?[\w*.|"'-]+\)\s*"#, ""),
+            // Case terminators
+            (r";;&?|;&", ";"),
+            // Reserved words that introduce a command
+            (r"(^|;|&&|\|\|)\s*(?:then|do|else|elif|if|while|until|!|\{)(?:\s+|$)", "$1 "),
+            // A closing word carrying a redirection (`done < input.txt`) keeps the redirection
+            (r"(^|;)\s*(?:fi|done|esac|\})\s*([<>])", "$1 true $2"),
+            // Reserved words that close a compound command
+            (r"(^|;)\s*(?:fi|done|esac|\})\s*(?:;|$)", "$1"),
+        ]
+        .into_iter()
+        .filter_map(|(pattern, replacement)| Regex::new(pattern).ok().map(|regex| (regex, replacement)))
+        .collect()
+    })
+}
+
+/// Remove shell grammar that is not itself a command
+fn strip_compound_syntax(line: &str) -> String {
+    let mut text = line.to_string();
+    loop {
+        let mut next = text.clone();
+        for (pattern, replacement) in syntax_patterns() {
+            next = pattern.replace_all(&next, *replacement).to_string();
+        }
+        if next == text {
+            break;
+        }
+        text = next;
+    }
+    text.trim().trim_matches(';').trim().to_string()
+}
+
+fn quotes_balanced(text: &str) -> bool {
+    let mut in_single = false;
+    let mut in_double = false;
+    let mut escaped = false;
+    for c in text.chars() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if !in_single => escaped = true,
+            '\'' if !in_double => in_single = !in_single,
+            '"' if !in_single => in_double = !in_double,
+            _ => {}
+        }
+    }
+    !in_single && !in_double
+}
+
+fn continues(text: &str) -> bool {
+    let trimmed = text.trim_end();
+    (trimmed.ends_with('\\') && !trimmed.ends_with("\\\\"))
+        || trimmed.ends_with('|')
+        || trimmed.ends_with("&&")
+        || !quotes_balanced(text)
+}
+
+/// Split a script into statements, joining continuation lines and skipping heredoc bodies
+/// unless a shell, an interpreter or ssh runs them
+pub fn split_statements(script: &str) -> Vec<Statement> {
+    let mut statements = Vec::new();
+    let mut lines = script.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        // Join continuation lines into one logical line
+        let mut logical = line.to_string();
+        while continues(&logical) {
+            let Some((_, next)) = lines.next() else { break };
+            if logical.trim_end().ends_with('\\') {
+                logical = logical.trim_end().trim_end_matches('\\').trim_end().to_string();
+                logical.push(' ');
+                logical.push_str(next.trim());
+            } else {
+                logical.push('\n');
+                logical.push_str(next);
+            }
+        }
+
+        // Heredoc bodies are data, not statements, unless the command runs them
+        let mut bodies = Vec::new();
+        for caps in heredoc_regex().captures_iter(&logical) {
+            let delimiter = caps.get(1).or(caps.get(2)).or(caps.get(3)).map(|m| m.as_str()).unwrap_or_default();
+            let runs = runs_heredoc(&logical, caps.get(0).map_or(0, |m| m.start()));
+            let mut body = Vec::new();
+            let mut first_line = None;
+            for (body_index, body_line) in lines.by_ref() {
+                if body_line.trim() == delimiter {
+                    break;
+                }
+                first_line.get_or_insert(body_index);
+                body.push(body_line);
+            }
+            if let (true, Some(first_line)) = (runs, first_line) {
+                bodies.push((first_line, body.join("\n")));
+            }
+        }
+
+        let text = strip_compound_syntax(&logical);
+        if !text.is_empty() {
+            statements.push(Statement { line: index + 1, text });
+        }
+        for (first_line, body) in bodies {
+            statements.extend(split_statements(&body).into_iter().map(|statement| Statement {
+                line: statement.line + first_line,
+                text: statement.text,
+            }));
+        }
+    }
+
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(script: &str) -> Vec<String> {
+        split_statements(script).into_iter().map(|s| s.text).collect()
+    }
+
+    #[test]
+    fn test_simple_lines() {
+        let statements = split_statements("echo one\n\n# comment\nls -la\n");
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0], Statement { line: 1, text: "echo one".to_string() });
+        assert_eq!(statements[1].line, 4);
+    }
+
+    #[test]
+    fn test_compound_commands() {
+        let script = r#"
+cleanup() {
+    rm -rf "$1"
+}
+for f in *.log; do
+    gzip "$f"
+done
+if [ -d build ]; then rm -rf build; fi
+while read -r line; do echo "$line"; done < input.txt
+"#;
+        assert_eq!(texts(script), vec![
+            r#"rm -rf "$1""#,
+            r#"gzip "$f""#,
+            "[ -d build ]; rm -rf build",
+            r#"read -r line; echo "$line"; true < input.txt"#,
+        ]);
+    }
+
+    #[test]
+    fn test_heredocs_and_continuations() {
+        let script = "cat > notes.md <<'EOF'\nrm -rf / is dangerous\nEOF\ntar czf out.tgz \\\n  src docs\necho done";
+        let statements = split_statements(script);
+        assert_eq!(statements.len(), 3);
+        assert_eq!(statements[0].text, "cat > notes.md <<'EOF'");
+        assert_eq!(statements[1].text, "tar czf out.tgz src docs");
+        assert_eq!(statements[2].line, 6);
+    }
+
+    #[test]
+    fn test_heredocs_that_run() {
+        let script = "bash <<EOF\nrm -rf ~\nEOF\nssh host <<'EOF'\ncd /srv\nEOF\nsudo python3 <<EOF\nimport shutil\nEOF";
+        let statements = split_statements(script);
+        assert_eq!(texts(script), vec!["bash <<EOF", "rm -rf ~", "ssh host <<'EOF'", "cd /srv", "sudo python3 <<EOF", "import shutil"]);
+        assert_eq!(statements[1].line, 2);
+        assert_eq!(statements[3].line, 5);
+        assert_eq!(statements[5].line, 8);
+
+        // A body piped into a shell runs too; one written to a file stays data
+        assert_eq!(texts("cat <<EOF | bash\nrm -rf ~\nEOF"), vec!["cat <<EOF | bash", "rm -rf ~"]);
+        assert_eq!(texts("cat <<EOF > run.sh\nrm -rf ~\nEOF"), vec!["cat <<EOF > run.sh"]);
+    }
+
+    #[test]
+    fn test_case_statement() {
+        let script = "case \"$1\" in\n  start) systemctl start app ;;\n  *) echo usage ;;\nesac";
+        assert_eq!(texts(script), vec!["systemctl start app", "echo usage"]);
+    }
+
+    #[test]
+    fn test_report_overall() {
+        let report = ScriptReport {
+            lines: vec![
+                LineVerdict { line: 1, text: "ls".to_string(), result: SafetyResult::Safe },
+                LineVerdict {
+                    line: 5,
+                    text: "rm -rf ~".to_string(),
+                    result: SafetyResult::Blocked("Dangerous command".to_string()),
+                },
+            ],
+        };
+        match report.overall() {
+            SafetyResult::Blocked(reason) => {
+                assert!(reason.starts_with("Line 5"));
+                assert!(reason.contains("BLOCKED  rm -rf ~"));
+            }
+            other => panic!("Expected blocked, got {:?}", other),
+        }
+    }
+}
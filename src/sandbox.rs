@@ -0,0 +1,565 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use tokio::process::Command as TokioCommand;
+
+/// Configuration for sandboxed "inspect first" execution
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxConfig {
+    /// Run commands the model marked auto_execute but the safety checker is unsure about
+    /// in the sandbox instead of prompting first. Off by default: the sandbox limits writes
+    /// and sockets, but is not a boundary to run unreviewed commands behind
+    pub auto_for_uncertain: bool,
+    /// Whether the working directory is writable inside the sandbox
+    pub allow_cwd_writes: bool,
+    /// Additional paths that stay writable inside the sandbox
+    pub writable_paths: Vec<PathBuf>,
+    /// Whether sandboxed commands may use the network
+    pub allow_network: bool,
+}
+
+/// Answer to an execution prompt that offers an "inspect first" sandbox run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunChoice {
+    Execute,
+    Sandbox,
+    Skip,
+}
+
+impl RunChoice {
+    pub fn parse(input: &str) -> Self {
+        match input.trim().to_lowercase().as_str() {
+            "y" | "yes" => RunChoice::Execute,
+            "s" | "sandbox" => RunChoice::Sandbox,
+            _ => RunChoice::Skip,
+        }
+    }
+}
+
+/// Resolved sandbox policy for a single execution
+#[derive(Debug)]
+pub struct SandboxPolicy {
+    writable_paths: Vec<PathBuf>,
+    allow_network: bool,
+    scratch_dir: TempDir,
+}
+
+impl SandboxPolicy {
+    pub fn from_config(config: &SandboxConfig) -> Result<Self> {
+        let scratch_dir = tempfile::Builder::new()
+            .prefix("commandgpt-sandbox-")
+            .tempdir()
+            .context("Failed to create sandbox scratch directory")?;
+
+        let mut writable_paths = vec![
+            scratch_dir.path().to_path_buf(),
+            PathBuf::from("/dev/null"),
+        ];
+
+        if config.allow_cwd_writes {
+            if let Ok(cwd) = std::env::current_dir() {
+                writable_paths.push(cwd);
+            }
+        }
+
+        writable_paths.extend(config.writable_paths.iter().map(|p| expand_home(p)));
+
+        Ok(Self {
+            writable_paths,
+            allow_network: config.allow_network,
+            scratch_dir,
+        })
+    }
+
+    /// Scratch directory that is always writable and exported as TMPDIR
+    pub fn scratch_dir(&self) -> &Path {
+        self.scratch_dir.path()
+    }
+
+    pub fn writable_paths(&self) -> &[PathBuf] {
+        &self.writable_paths
+    }
+
+    pub fn allows_network(&self) -> bool {
+        self.allow_network
+    }
+
+    /// Configure a command so it runs inside the sandbox. The returned guard owns
+    /// kernel objects the child inherits and must outlive the spawn call.
+    pub fn apply(&self, cmd: &mut TokioCommand) -> Result<SandboxGuard> {
+        cmd.env("TMPDIR", self.scratch_dir())
+            .env("COMMANDGPT_SANDBOX", "1");
+        imp::apply(self, cmd)
+    }
+
+    /// Short human readable summary of the restrictions in effect
+    pub fn describe(&self) -> String {
+        let writable: Vec<String> = self.writable_paths.iter()
+            .map(|p| p.display().to_string())
+            .collect();
+
+        format!(
+            "read-only filesystem (writable: {}), network {}",
+            writable.join(", "),
+            if self.allow_network { "enabled" } else { "disabled" }
+        )
+    }
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs_next::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+pub use imp::{is_supported, SandboxGuard};
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::SandboxPolicy;
+    use anyhow::{Context, Result};
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::os::unix::ffi::OsStrExt;
+    use tokio::process::Command as TokioCommand;
+
+    // Landlock ABI definitions (linux/landlock.h), not exposed by libc
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+    const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+    const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+    const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+    const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+    const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH_CURRENT: u32 = 0xC000_003E;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH_CURRENT: u32 = 0xC000_00B7;
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const AUDIT_ARCH_CURRENT: u32 = 0;
+
+    /// Set in syscall numbers of the x32 ABI, which shares the x86_64 audit arch
+    #[cfg(target_arch = "x86_64")]
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    /// `clone` flags that create namespaces (CLONE_NEWTIME is not in libc on every target)
+    const CLONE_NEW_FLAGS: u32 = (libc::CLONE_NEWNS | libc::CLONE_NEWCGROUP | libc::CLONE_NEWUTS | libc::CLONE_NEWIPC
+        | libc::CLONE_NEWUSER | libc::CLONE_NEWPID | libc::CLONE_NEWNET) as u32 | 0x80;
+
+    #[repr(C)]
+    struct LandlockRulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct LandlockPathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    /// Kernel objects shared with the sandboxed child
+    pub struct SandboxGuard {
+        _ruleset: OwnedFd,
+    }
+
+    /// Landlock ABI version supported by the running kernel, if any
+    fn landlock_abi() -> Option<i64> {
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<LandlockRulesetAttr>(),
+                0usize,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        if abi > 0 { Some(abi) } else { None }
+    }
+
+    pub fn is_supported() -> bool {
+        cfg!(any(target_arch = "x86_64", target_arch = "aarch64")) && landlock_abi().is_some()
+    }
+
+    fn write_access_for_abi(abi: i64) -> u64 {
+        let mut access = ACCESS_FS_WRITE_FILE
+            | ACCESS_FS_REMOVE_DIR
+            | ACCESS_FS_REMOVE_FILE
+            | ACCESS_FS_MAKE_CHAR
+            | ACCESS_FS_MAKE_DIR
+            | ACCESS_FS_MAKE_REG
+            | ACCESS_FS_MAKE_SOCK
+            | ACCESS_FS_MAKE_FIFO
+            | ACCESS_FS_MAKE_BLOCK
+            | ACCESS_FS_MAKE_SYM;
+        if abi >= 2 {
+            access |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            access |= ACCESS_FS_TRUNCATE;
+        }
+        access
+    }
+
+    /// Build a Landlock ruleset that denies every write except below the allowlisted paths
+    fn build_ruleset(policy: &SandboxPolicy) -> Result<OwnedFd> {
+        let abi = landlock_abi()
+            .context("Landlock is not available on this kernel")?;
+        let handled = write_access_for_abi(abi);
+
+        let attr = LandlockRulesetAttr { handled_access_fs: handled };
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const LandlockRulesetAttr,
+                std::mem::size_of::<LandlockRulesetAttr>(),
+                0u32,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to create Landlock ruleset");
+        }
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+
+        for path in policy.writable_paths() {
+            let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+                continue;
+            };
+            let path_fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+            if path_fd < 0 {
+                log::debug!("Skipping missing sandbox path: {}", path.display());
+                continue;
+            }
+            let path_fd = unsafe { OwnedFd::from_raw_fd(path_fd) };
+
+            // Directory-only rights are rejected by the kernel for regular files
+            let allowed = if path.is_dir() {
+                handled
+            } else {
+                handled & (ACCESS_FS_WRITE_FILE | ACCESS_FS_TRUNCATE)
+            };
+
+            let rule = LandlockPathBeneathAttr {
+                allowed_access: allowed,
+                parent_fd: path_fd.as_raw_fd(),
+            };
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_add_rule,
+                    ruleset.as_raw_fd(),
+                    LANDLOCK_RULE_PATH_BENEATH,
+                    &rule as *const LandlockPathBeneathAttr,
+                    0u32,
+                )
+            };
+            if ret < 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("Failed to allow writes to {}", path.display()));
+            }
+        }
+
+        Ok(ruleset)
+    }
+
+    fn bpf_stmt(code: u32, k: u32) -> libc::sock_filter {
+        libc::sock_filter { code: code as u16, jt: 0, jf: 0, k }
+    }
+
+    fn bpf_jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter { code: code as u16, jt, jf, k }
+    }
+
+    /// Build a seccomp filter that refuses namespace, mount, tracing and kernel module
+    /// syscalls, and internet and Unix sockets when the network is disabled. Unix sockets
+    /// reach services outside the sandbox (the Docker daemon, the systemd user bus) that
+    /// would run commands with none of its restrictions; `connect` cannot be filtered by
+    /// path, so they cannot be created at all. io_uring is refused because its socket and
+    /// connect operations never pass through the `socket()` check, and `clone3` because its
+    /// flags live in memory the filter cannot read
+    fn build_seccomp_filter(allow_network: bool) -> Vec<libc::sock_filter> {
+        let denied = [
+            libc::SYS_ptrace,
+            libc::SYS_mount,
+            libc::SYS_umount2,
+            libc::SYS_pivot_root,
+            libc::SYS_setns,
+            libc::SYS_unshare,
+            libc::SYS_keyctl,
+            libc::SYS_add_key,
+            libc::SYS_request_key,
+            libc::SYS_bpf,
+            libc::SYS_perf_event_open,
+            libc::SYS_kexec_load,
+            libc::SYS_init_module,
+            libc::SYS_finit_module,
+            libc::SYS_delete_module,
+            libc::SYS_reboot,
+            libc::SYS_swapon,
+            libc::SYS_swapoff,
+            libc::SYS_io_uring_setup,
+        ];
+        let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+        let deny_net = libc::SECCOMP_RET_ERRNO | libc::EACCES as u32;
+
+        let mut filter = vec![
+            // Refuse to run under a foreign syscall ABI
+            bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 4),
+            bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, AUDIT_ARCH_CURRENT, 1, 0),
+            bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 0),
+        ];
+
+        // x32 syscall numbers would match none of the x86_64 numbers below
+        #[cfg(target_arch = "x86_64")]
+        {
+            filter.push(bpf_jump(libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K, X32_SYSCALL_BIT, 0, 1));
+            filter.push(bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS));
+        }
+
+        for nr in denied {
+            filter.push(bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, nr as u32, 0, 1));
+            filter.push(bpf_stmt(libc::BPF_RET | libc::BPF_K, deny));
+        }
+
+        // clone3 fails as unsupported, so libc falls back to clone, whose flags are checked
+        filter.push(bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::SYS_clone3 as u32, 0, 1));
+        filter.push(bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ERRNO | libc::ENOSYS as u32));
+
+        // clone(flags, ...): refuse new namespaces; anything else is allowed from here
+        filter.push(bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::SYS_clone as u32, 0, 4));
+        filter.push(bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 16));
+        filter.push(bpf_jump(libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K, CLONE_NEW_FLAGS, 0, 1));
+        filter.push(bpf_stmt(libc::BPF_RET | libc::BPF_K, deny));
+        filter.push(bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+
+        if !allow_network {
+            // socket(domain, ...): reject the denied families; socketpair stays allowed, its
+            // sockets cannot reach anything outside the process tree
+            let families = [libc::AF_UNIX, libc::AF_INET, libc::AF_INET6, libc::AF_PACKET];
+            let count = families.len();
+            filter.push(bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::SYS_socket as u32, 0, (count + 2) as u8));
+            filter.push(bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 16));
+            for (i, family) in families.iter().enumerate() {
+                let last = i + 1 == count;
+                filter.push(bpf_jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, *family as u32, (count - 1 - i) as u8, u8::from(last)));
+            }
+            filter.push(bpf_stmt(libc::BPF_RET | libc::BPF_K, deny_net));
+        }
+
+        filter.push(bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+        filter
+    }
+
+    /// Write a whole buffer to a procfs file using only async-signal-safe calls
+    fn write_proc_file(path: &[u8], content: &[u8]) -> io::Result<()> {
+        let fd = unsafe { libc::open(path.as_ptr() as *const libc::c_char, libc::O_WRONLY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let written = unsafe { libc::write(fd, content.as_ptr() as *const libc::c_void, content.len()) };
+        unsafe { libc::close(fd) };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn apply(policy: &SandboxPolicy, cmd: &mut TokioCommand) -> Result<SandboxGuard> {
+        let ruleset = build_ruleset(policy)?;
+        let ruleset_fd = ruleset.as_raw_fd();
+        let filter = build_seccomp_filter(policy.allows_network());
+        let isolate_network = !policy.allows_network();
+
+        // Everything the child needs is prepared here; the pre_exec hook only issues syscalls
+        let uid_map = format!("{0} {0} 1\n", unsafe { libc::geteuid() }).into_bytes();
+        let gid_map = format!("{0} {0} 1\n", unsafe { libc::getegid() }).into_bytes();
+
+        unsafe {
+            cmd.pre_exec(move || {
+                // A fresh network namespace has only a downed loopback interface. Unprivileged
+                // user namespaces may be disabled; the seccomp socket filter still applies then.
+                if isolate_network && libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) == 0 {
+                    let _ = write_proc_file(b"/proc/self/setgroups\0", b"deny");
+                    let _ = write_proc_file(b"/proc/self/uid_map\0", &uid_map);
+                    let _ = write_proc_file(b"/proc/self/gid_map\0", &gid_map);
+                }
+
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                if libc::syscall(libc::SYS_landlock_restrict_self, ruleset_fd, 0u32) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let prog = libc::sock_fprog {
+                    len: filter.len() as libc::c_ushort,
+                    filter: filter.as_ptr() as *mut libc::sock_filter,
+                };
+                if libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &prog as *const libc::sock_fprog) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                Ok(())
+            });
+        }
+
+        Ok(SandboxGuard { _ruleset: ruleset })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use super::SandboxPolicy;
+    use anyhow::Result;
+    use tokio::process::Command as TokioCommand;
+
+    pub struct SandboxGuard;
+
+    pub fn is_supported() -> bool {
+        false
+    }
+
+    pub fn apply(_policy: &SandboxPolicy, _cmd: &mut TokioCommand) -> Result<SandboxGuard> {
+        anyhow::bail!("Sandboxed execution requires Linux with Landlock support")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::CommandExecutor;
+
+    fn test_policy(writable: Vec<PathBuf>) -> SandboxPolicy {
+        let config = SandboxConfig {
+            writable_paths: writable,
+            ..Default::default()
+        };
+        SandboxPolicy::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn test_default_config() {
+        let config = SandboxConfig::default();
+
+        assert!(!config.auto_for_uncertain);
+        assert!(!config.allow_cwd_writes);
+        assert!(!config.allow_network);
+        assert!(config.writable_paths.is_empty());
+    }
+
+    #[test]
+    fn test_run_choice_parse() {
+        assert_eq!(RunChoice::parse("y"), RunChoice::Execute);
+        assert_eq!(RunChoice::parse("YES"), RunChoice::Execute);
+        assert_eq!(RunChoice::parse("s"), RunChoice::Sandbox);
+        assert_eq!(RunChoice::parse(" sandbox\n"), RunChoice::Sandbox);
+        assert_eq!(RunChoice::parse(""), RunChoice::Skip);
+        assert_eq!(RunChoice::parse("n"), RunChoice::Skip);
+    }
+
+    #[test]
+    fn test_policy_writable_paths() {
+        let policy = test_policy(vec![PathBuf::from("/tmp/extra")]);
+
+        assert!(policy.writable_paths().iter().any(|p| p == policy.scratch_dir()));
+        assert!(policy.writable_paths().contains(&PathBuf::from("/dev/null")));
+        assert!(policy.writable_paths().contains(&PathBuf::from("/tmp/extra")));
+        assert!(policy.describe().contains("network disabled"));
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_blocks_writes_outside_allowlist() {
+        if !is_supported() {
+            return;
+        }
+
+        let outside = tempfile::TempDir::new().unwrap();
+        let target = outside.path().join("created.txt");
+        let policy = test_policy(Vec::new());
+
+        let executor = CommandExecutor::new();
+        let result = executor
+            .execute_sandboxed(&format!("echo data > {}", target.display()), &policy)
+            .await
+            .unwrap();
+
+        assert!(!result.success);
+        assert!(!target.exists());
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_blocks_unix_sockets() {
+        if !is_supported() {
+            return;
+        }
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("service.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        let connect = format!(
+            "python3 -c 'import socket; socket.socket(socket.AF_UNIX).connect(\"{}\")'",
+            socket.display()
+        );
+
+        let executor = CommandExecutor::new();
+        if !executor.execute(&connect).await.unwrap().success {
+            return; // No python3 to test with
+        }
+        let result = executor.execute_sandboxed(&connect, &test_policy(Vec::new())).await.unwrap();
+        assert!(!result.success);
+        assert!(result.stderr.contains("Permission denied"));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[tokio::test]
+    async fn test_sandbox_blocks_namespace_syscalls() {
+        if !is_supported() {
+            return;
+        }
+
+        // clone(CLONE_NEWUSER | SIGCHLD), clone3 and io_uring_setup, through raw syscall numbers
+        let probe = "python3 -c 'import ctypes, os\nlibc = ctypes.CDLL(None, use_errno=True)\nfor nr, args in [(56, (0x10000000 | 17, 0, 0, 0, 0)), (435, (0, 0)), (425, (1, 0))]:\n    ret = libc.syscall(nr, *args)\n    if ret == 0: os._exit(0)\n    print(nr, ret, ctypes.get_errno())'";
+
+        let executor = CommandExecutor::new();
+        if !executor.execute("python3 -c pass").await.unwrap().success {
+            return; // No python3 to test with
+        }
+        let result = executor.execute_sandboxed(probe, &test_policy(Vec::new())).await.unwrap();
+        let lines: Vec<&str> = result.stdout.lines().collect();
+        assert_eq!(lines, vec![
+            format!("56 -1 {}", libc::EPERM),
+            format!("435 -1 {}", libc::ENOSYS),
+            format!("425 -1 {}", libc::EPERM),
+        ], "{}", result.stderr);
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_allows_scratch_writes() {
+        if !is_supported() {
+            return;
+        }
+
+        let policy = test_policy(Vec::new());
+        let executor = CommandExecutor::new();
+        let result = executor
+            .execute_sandboxed("echo data > \"$TMPDIR/out.txt\" && cat \"$TMPDIR/out.txt\"", &policy)
+            .await
+            .unwrap();
+
+        assert!(result.success);
+        assert!(result.sandboxed);
+        assert_eq!(result.stdout.trim(), "data");
+    }
+}
@@ -0,0 +1,413 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command as TokioCommand};
+
+use crate::environment::ChildEnvironment;
+use crate::output::{self, Capture, OutputConfig, Tee};
+use crate::process::{self, Target};
+use crate::shell::{Shell, ShellKind};
+use crate::usage::ResourceUsage;
+
+/// Variables the shell keeps up to date itself, which say nothing about what a command did
+const BOOKKEEPING: &[&str] = &["_", "PWD", "OLDPWD", "SHLVL", "COLUMNS", "LINES"];
+
+/// Longest variable value shown to the model
+const MAX_VALUE_CHARS: usize = 200;
+
+/// How long the shell gets to read its startup files and report its state
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long output may stay open after a timed-out command's group was stopped
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where a session's shell is and what it has set, as of the last command
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionState {
+    pub cwd: PathBuf,
+    pub env: BTreeMap<String, String>,
+}
+
+impl SessionState {
+    /// The state file the command wrapper writes: exit code, working directory, the output
+    /// of `times` (empty lines for fish), then `env` output. Returns the CPU time the shell
+    /// and its children used so far along with the state
+    fn parse(text: &str) -> Option<(i32, Option<CpuTimes>, Self)> {
+        let mut lines = text.lines();
+        let status = lines.next()?.trim().parse().ok()?;
+        let cwd = PathBuf::from(lines.next()?);
+        // `times` reports the shell's own CPU time, which builtins and loops use, then its children's
+        let cpu = match (parse_times(lines.next()?), parse_times(lines.next()?)) {
+            (Some((user, system)), Some((children_user, children_system))) => {
+                Some((user + children_user, system + children_system))
+            }
+            _ => None,
+        };
+
+        let mut env: BTreeMap<String, String> = BTreeMap::new();
+        let mut last: Option<String> = None;
+        for line in lines {
+            match line.split_once('=').filter(|(name, _)| is_name(name)) {
+                Some((name, value)) => {
+                    env.insert(name.to_string(), value.to_string());
+                    last = Some(name.to_string());
+                }
+                // A value with newlines continues on the following lines
+                None => {
+                    if let Some(value) = last.as_ref().and_then(|name| env.get_mut(name)) {
+                        value.push('\n');
+                        value.push_str(line);
+                    }
+                }
+            }
+        }
+        Some((status, cpu, Self { cwd, env }))
+    }
+
+    /// Variables set, changed (`Some`) or unset (`None`) since `baseline`
+    pub fn changes(&self, baseline: &SessionState) -> Vec<(String, Option<String>)> {
+        let changed = self.env.iter()
+            .filter(|(name, value)| baseline.env.get(*name) != Some(*value))
+            .map(|(name, value)| (name.clone(), Some(value.clone())));
+        let unset = baseline.env.keys()
+            .filter(|name| !self.env.contains_key(*name))
+            .map(|name| (name.clone(), None));
+        changed.chain(unset)
+            .filter(|(name, _)| !BOOKKEEPING.contains(&name.as_str()))
+            .collect()
+    }
+
+    /// The session's variables for a command run outside it
+    pub fn vars(&self) -> impl Iterator<Item = (OsString, OsString)> + '_ {
+        self.env.iter().map(|(name, value)| (OsString::from(name), OsString::from(value)))
+    }
+}
+
+/// What the model is told about the session: where it is and what earlier commands changed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionContext {
+    pub cwd: PathBuf,
+    pub changes: Vec<(String, Option<String>)>,
+}
+
+impl SessionContext {
+    /// A variable's value for the prompt, shortened when long
+    pub fn describe(value: &str) -> String {
+        let value = value.replace('\n', "\\n");
+        match value.char_indices().nth(MAX_VALUE_CHARS) {
+            Some((end, _)) => format!("{}…", &value[..end]),
+            None => value,
+        }
+    }
+}
+
+/// User and system CPU time
+type CpuTimes = (Duration, Duration);
+
+/// How a command run in the session ended
+#[derive(Debug)]
+pub struct SessionRun {
+    pub stdout: Capture,
+    pub stderr: Capture,
+    /// The command's exit code, or the shell's own status when the command ended the shell
+    pub status: SessionStatus,
+    /// CPU time of the shell and the processes it waited for while running the command, when
+    /// the shell reports it; memory and I/O are only accounted to the shell as a whole
+    pub usage: Option<ResourceUsage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionStatus {
+    Exited(i32),
+    /// The shell itself exited, e.g. the command was `exit`; the session has to be restarted
+    Closed(ExitStatus),
+    /// The command ran past its time limit and was stopped together with the shell
+    TimedOut,
+}
+
+/// A long-lived shell that one-line commands are written to, so `cd`, `export`, aliases and
+/// sourced files carry over from one command to the next
+pub struct ShellSession {
+    shell: Shell,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+    stderr: ChildStderr,
+    state_file: NamedTempFile,
+    baseline: SessionState,
+    state: SessionState,
+    /// CPU time used by the shell and its children up to the last command, which the next
+    /// one is measured from
+    cpu: Option<CpuTimes>,
+    closed: bool,
+}
+
+impl ShellSession {
+    /// Start the shell in its own process group with the filtered environment
+    pub async fn start(shell: &Shell, environment: &ChildEnvironment) -> Result<Self> {
+        let mut cmd = TokioCommand::new(&shell.path);
+        if shell.kind != ShellKind::Fish {
+            cmd.arg("-s");
+        }
+        environment.apply(&mut cmd);
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true);
+
+        let mut child = cmd.spawn().context("Failed to start the shell session")?;
+        let stdin = child.stdin.take().context("Failed to open the shell's stdin")?;
+        let stdout = child.stdout.take().context("Failed to capture the shell's stdout")?;
+        let stderr = child.stderr.take().context("Failed to capture the shell's stderr")?;
+        let state_file = NamedTempFile::new().context("Failed to create the session state file")?;
+
+        let mut session = Self {
+            shell: shell.clone(),
+            child,
+            stdin,
+            stdout,
+            stderr,
+            state_file,
+            baseline: SessionState::default(),
+            state: SessionState::default(),
+            cpu: None,
+            closed: false,
+        };
+        session.write(&session.prelude()).await?;
+        let run = session.run(":", &Tee::default(), &OutputConfig::default(), STARTUP_TIMEOUT).await?;
+        if session.closed {
+            anyhow::bail!("The shell session exited while starting: {}", run.stderr.finish().0.trim());
+        }
+        session.baseline = session.state.clone();
+        Ok(session)
+    }
+
+    /// The shell's process group, which signals for the running command go to
+    pub fn pgid(&self) -> Option<i32> {
+        self.child.id().map(|pid| pid as i32)
+    }
+
+    /// The shell exited and a new session is needed
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn state(&self) -> &SessionState {
+        &self.state
+    }
+
+    pub fn context(&self) -> SessionContext {
+        SessionContext { cwd: self.state.cwd.clone(), changes: self.state.changes(&self.baseline) }
+    }
+
+    /// Run a one-line command in the shell, passing its output on like a fresh process would.
+    /// A command still running after `limit` is stopped, and the shell with it
+    pub async fn run(&mut self, command: &str, tee: &Tee, config: &OutputConfig, limit: Duration) -> Result<SessionRun> {
+        let nonce = uuid::Uuid::new_v4().simple().to_string();
+        let sentinel = format!("\x1e{}\x1e", nonce);
+        self.write(&self.wrap(command, &nonce)).await?;
+
+        let pgid = self.pgid();
+        let pumping = output::pump_until(
+            &mut self.stdout,
+            &mut self.stderr,
+            tee,
+            config,
+            Some(sentinel.as_bytes()),
+        );
+        tokio::pin!(pumping);
+
+        let (stdout, stderr, finished) = match tokio::time::timeout(limit, &mut pumping).await {
+            Ok(pumped) => pumped?,
+            Err(_) => {
+                self.closed = true;
+                if let Some(pgid) = pgid {
+                    if let Err(e) = process::terminate(&mut self.child, Target::Group(pgid), process::GRACE_PERIOD).await {
+                        log::warn!("Failed to stop the timed-out shell session: {}", e);
+                    }
+                }
+                // The output written before the limit is still worth keeping
+                let (stdout, stderr, _) = tokio::time::timeout(DRAIN_TIMEOUT, pumping).await
+                    .context("The timed-out command left its output open")??;
+                return Ok(SessionRun { stdout, stderr, status: SessionStatus::TimedOut, usage: None });
+            }
+        };
+
+        if !finished {
+            self.closed = true;
+            let status = self.child.wait().await.context("Failed to wait for the shell session")?;
+            return Ok(SessionRun { stdout, stderr, status: SessionStatus::Closed(status), usage: None });
+        }
+
+        let text = std::fs::read_to_string(self.state_file.path()).context("Failed to read the session state")?;
+        let (status, cpu, state) = SessionState::parse(&text).context("The session state file is malformed")?;
+        let usage = match (self.cpu, cpu) {
+            (Some((user, system)), Some((user_now, system_now))) => Some(ResourceUsage::cpu(
+                user_now.saturating_sub(user),
+                system_now.saturating_sub(system),
+            )),
+            _ => None,
+        };
+        self.state = state;
+        self.cpu = cpu;
+        Ok(SessionRun { stdout, stderr, status: SessionStatus::Exited(status), usage })
+    }
+
+    /// Stop the shell and whatever it is still running, e.g. after a timeout
+    pub async fn terminate(mut self) {
+        if let Some(pgid) = self.pgid() {
+            if let Err(e) = process::terminate(&mut self.child, Target::Group(pgid), process::GRACE_PERIOD).await {
+                log::warn!("Failed to stop the shell session: {}", e);
+            }
+        }
+    }
+
+    async fn write(&mut self, text: &str) -> Result<()> {
+        if let Err(e) = async {
+            self.stdin.write_all(text.as_bytes()).await?;
+            self.stdin.flush().await
+        }.await {
+            self.closed = true;
+            return Err(e).context("Failed to write to the shell session");
+        }
+        Ok(())
+    }
+
+    /// Keep the shell alive when Ctrl+C stops a command, abandoning the rest of the command
+    /// line like an interactive shell does, save the session's stdout and stderr where
+    /// commands cannot redirect them, and let bash expand aliases without a terminal
+    fn prelude(&self) -> String {
+        match self.shell.kind {
+            ShellKind::Fish => "function __commandgpt_interrupt --on-signal INT; end\n".to_string(),
+            ShellKind::Bash => "trap 'return 130' INT\nexec 8>&1 9>&2\nshopt -s expand_aliases\n".to_string(),
+            _ => "trap 'return 130' INT\nexec 8>&1 9>&2\n".to_string(),
+        }
+    }
+
+    /// The command with its stdin detached from the session's, followed by writing its
+    /// exit code, the working directory, the CPU time used so far and the environment to the
+    /// state file, then the sentinel on both streams. `times` runs in the shell itself, not
+    /// in a subshell, which would report nothing. POSIX shells run it as a function so the INT trap can
+    /// return from it; fish functions would keep variables set with `set` local
+    fn wrap(&self, command: &str, nonce: &str) -> String {
+        let state = self.shell.quote(&self.state_file.path().to_string_lossy());
+        match self.shell.kind {
+            ShellKind::Fish => format!(
+                "begin\n{command}\nend </dev/null\nset __commandgpt_status $status\n\
+                 begin; printf '%s\\n' $__commandgpt_status $PWD '' ''; env; end >{state} 2>/dev/null\n\
+                 printf '\\036%s\\036' {nonce}; printf '\\036%s\\036' {nonce} >&2\n"
+            ),
+            _ => format!(
+                "__commandgpt_run() {{\n{command}\n}}\n__commandgpt_run </dev/null\n__commandgpt_status=$?\n\
+                 {{ printf '%s\\n' \"$__commandgpt_status\" \"$PWD\"; times; env; }} >{state} 2>/dev/null\n\
+                 printf '\\036%s\\036' {nonce} >&8; printf '\\036%s\\036' {nonce} >&9\n"
+            ),
+        }
+    }
+}
+
+/// The user and system time on a line of `times` output, e.g. "0m1.250s 0m0.031s"
+fn parse_times(line: &str) -> Option<CpuTimes> {
+    let mut times = line.split_whitespace().filter_map(|word| {
+        let seconds = word.strip_suffix('s')?;
+        let (minutes, seconds) = seconds.split_once('m').unwrap_or(("0", seconds));
+        let seconds = minutes.parse::<u64>().ok()? as f64 * 60.0 + seconds.parse::<f64>().ok()?;
+        Some(Duration::from_secs_f64(seconds))
+    });
+    Some((times.next()?, times.next()?))
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn session() -> ShellSession {
+        let shell = Shell::from_program("/bin/sh").unwrap();
+        ShellSession::start(&shell, &ChildEnvironment::inherit(&Default::default())).await.unwrap()
+    }
+
+    async fn run(session: &mut ShellSession, command: &str) -> (String, String, SessionStatus) {
+        let run = session.run(command, &Tee::default(), &OutputConfig::default(), Duration::from_secs(10)).await.unwrap();
+        (run.stdout.finish().0, run.stderr.finish().0, run.status)
+    }
+
+    #[tokio::test]
+    async fn test_state_carries_over() {
+        // A variable this test owns, so other tests changing the process environment do not interfere
+        let shell = Shell::from_program("/bin/sh").unwrap();
+        let vars = std::env::vars_os().chain([("COMMANDGPT_SESSION_TEST".into(), "1".into())]);
+        let environment = ChildEnvironment::from_vars(&Default::default(), vars);
+        let mut session = ShellSession::start(&shell, &environment).await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+
+        let cd = format!("cd {}", session.shell.quote(&dir.to_string_lossy()));
+        assert_eq!(run(&mut session, &cd).await.2, SessionStatus::Exited(0));
+        run(&mut session, "export GREETING='hello\nworld'; unset COMMANDGPT_SESSION_TEST").await;
+
+        let (stdout, stderr, status) = run(&mut session, "pwd; echo \"$GREETING\"; echo oops >&2; false").await;
+        assert_eq!(stdout, format!("{}\nhello\nworld\n", dir.display()));
+        assert_eq!(stderr, "oops\n");
+        assert_eq!(status, SessionStatus::Exited(1));
+
+        let context = session.context();
+        assert_eq!(context.cwd, dir);
+        assert!(context.changes.contains(&("GREETING".to_string(), Some("hello\nworld".to_string()))));
+        assert!(context.changes.contains(&("COMMANDGPT_SESSION_TEST".to_string(), None)));
+        assert!(!context.changes.iter().any(|(name, _)| name == "PWD"));
+
+        let busy = "i=0; while [ $i -lt 20000 ]; do i=$((i + 1)); done";
+        let run = session.run(busy, &Tee::default(), &OutputConfig::default(), Duration::from_secs(10)).await.unwrap();
+        assert!(run.usage.is_some_and(|usage| usage.cpu_time() > Duration::ZERO && usage.counters.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_output_without_newline_and_exit() {
+        let mut session = session().await;
+        assert_eq!(run(&mut session, "printf partial").await.0, "partial");
+
+        // Commands cannot hide the sentinel by redirecting the shell's streams
+        run(&mut session, "exec >/dev/null").await;
+        assert_eq!(run(&mut session, "echo hidden; echo shown >&2").await.1, "shown\n");
+
+        let (_, _, status) = run(&mut session, "exit 3").await;
+        assert!(matches!(status, SessionStatus::Closed(status) if status.code() == Some(3)));
+        assert!(session.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_timeout_stops_the_session() {
+        let mut session = session().await;
+        let run = session.run("echo started; sleep 30", &Tee::default(), &OutputConfig::default(), Duration::from_millis(300))
+            .await
+            .unwrap();
+        assert_eq!(run.status, SessionStatus::TimedOut);
+        assert_eq!(run.stdout.finish().0, "started\n");
+        assert!(session.is_closed());
+    }
+
+    #[test]
+    fn test_parse_state() {
+        let (status, cpu, state) = SessionState::parse("127\n/tmp\n0m0.01s 0m0.00s\n1m2.500s 0m0.250s\nA=1\nB=two\nlines\n=odd\n").unwrap();
+        assert_eq!(status, 127);
+        assert_eq!(cpu, Some((Duration::from_millis(62_510), Duration::from_millis(250))));
+        assert_eq!(state.cwd, PathBuf::from("/tmp"));
+        assert_eq!(state.env["A"], "1");
+        assert_eq!(state.env["B"], "two\nlines\n=odd");
+        assert!(SessionState::parse("").is_none());
+        assert_eq!(SessionState::parse("0\n/tmp\n\n\nA=1\n").unwrap().1, None);
+        assert_eq!(SessionContext::describe(&"x".repeat(300)).chars().count(), MAX_VALUE_CHARS + 1);
+    }
+}
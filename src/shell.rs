@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use tokio::process::Command as TokioCommand;
+
+/// Which shell runs generated commands (`[shell]` in config.toml)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShellConfig {
+    /// A shell name looked up on PATH ("bash") or a full path ("/opt/homebrew/bin/fish").
+    /// Defaults to $SHELL, then zsh, bash or sh, whichever is installed
+    pub program: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    Zsh,
+    Bash,
+    Sh,
+    Dash,
+    Fish,
+}
+
+impl ShellKind {
+    /// Recognise a shell from its file name, ignoring version suffixes such as `bash5`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.' || c == '-') {
+            "zsh" => Some(ShellKind::Zsh),
+            "bash" => Some(ShellKind::Bash),
+            "sh" => Some(ShellKind::Sh),
+            "dash" => Some(ShellKind::Dash),
+            "fish" => Some(ShellKind::Fish),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ShellKind::Zsh => "zsh",
+            ShellKind::Bash => "bash",
+            ShellKind::Sh => "sh",
+            ShellKind::Dash => "dash",
+            ShellKind::Fish => "fish",
+        }
+    }
+
+    fn is_posix(self) -> bool {
+        self != ShellKind::Fish
+    }
+}
+
+/// The shell commands are run with, and what that means for invoking and writing them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shell {
+    pub kind: ShellKind,
+    pub path: PathBuf,
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.kind.name(), self.path.display())
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::detect(&ShellConfig::default())
+    }
+}
+
+impl Shell {
+    /// The configured shell, else the user's login shell, else the first of zsh, bash and sh found
+    pub fn detect(config: &ShellConfig) -> Self {
+        if let Some(program) = &config.program {
+            match Self::from_program(program) {
+                Some(shell) => return shell,
+                None => log::warn!("Shell '{}' is not a supported shell that is installed, detecting one instead", program),
+            }
+        }
+
+        std::env::var("SHELL")
+            .ok()
+            .and_then(|shell| Self::from_program(&shell))
+            .or_else(|| ["zsh", "bash", "sh"].iter().find_map(|name| Self::from_program(name)))
+            .unwrap_or_else(|| Self { kind: ShellKind::Sh, path: PathBuf::from("/bin/sh") })
+    }
+
+    /// A supported shell given by name or path, if it is installed
+    pub fn from_program(program: &str) -> Option<Self> {
+        let path = if program.contains('/') { PathBuf::from(program) } else { find_in_path(program)? };
+        let kind = ShellKind::from_name(path.file_name()?.to_str()?)?;
+        path.is_file().then_some(Self { kind, path })
+    }
+
+    /// Run a one-line command
+    pub fn command(&self, command: &str) -> TokioCommand {
+        let mut cmd = TokioCommand::new(&self.path);
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+
+    /// First lines of a script file: the interpreter, and stopping at the first failure where
+    /// the shell supports it (fish has no equivalent of `set -e`)
+    pub fn script_header(&self) -> String {
+        if self.kind.is_posix() {
+            format!("#!{}\nset -e\n\n", self.path.display())
+        } else {
+            format!("#!{}\n\n", self.path.display())
+        }
+    }
+
+    /// Parse a command without running it; succeeds when the syntax is valid
+    pub fn syntax_check(&self, command: &str) -> TokioCommand {
+        let mut cmd = TokioCommand::new(&self.path);
+        match self.kind {
+            ShellKind::Fish => cmd.arg("--no-execute").arg("-c").arg(command),
+            _ => cmd.arg("-n").arg("-c").arg(command),
+        };
+        cmd
+    }
+
+    /// Quote a word so the shell passes it through literally
+    pub fn quote(&self, word: &str) -> String {
+        match self.kind {
+            ShellKind::Fish => format!("'{}'", word.replace('\\', "\\\\").replace('\'', "\\'")),
+            _ => format!("'{}'", word.replace('\'', "'\\''")),
+        }
+    }
+
+    /// How the model should write commands for this shell
+    pub fn dialect(&self) -> String {
+        match self.kind {
+            ShellKind::Zsh | ShellKind::Bash => format!("{} syntax", self.kind.name()),
+            ShellKind::Sh | ShellKind::Dash => {
+                "POSIX sh syntax: no [[ ]], arrays, brace expansion or other bash extensions".to_string()
+            }
+            ShellKind::Fish => {
+                "fish syntax, which is not POSIX: `set NAME value` instead of NAME=value, `(cmd)` for command \
+                 substitution, `; and`/`; or` or `&&`/`||`, no heredocs"
+                    .to_string()
+            }
+        }
+    }
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_else(|| vec![PathBuf::from("/bin"), PathBuf::from("/usr/bin")])
+        .into_iter()
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell(kind: ShellKind, path: &str) -> Shell {
+        Shell { kind, path: PathBuf::from(path) }
+    }
+
+    #[test]
+    fn test_shell_kinds() {
+        assert_eq!(ShellKind::from_name("zsh"), Some(ShellKind::Zsh));
+        assert_eq!(ShellKind::from_name("bash5"), Some(ShellKind::Bash));
+        assert_eq!(ShellKind::from_name("fish"), Some(ShellKind::Fish));
+        assert_eq!(ShellKind::from_name("python3"), None);
+
+        let sh = Shell::from_program("/bin/sh").unwrap();
+        assert_eq!(sh.kind, ShellKind::Sh);
+        assert!(Shell::from_program("/nonexistent/bash").is_none());
+        assert_eq!(Shell::detect(&ShellConfig { program: Some("/bin/sh".to_string()) }), sh);
+    }
+
+    #[test]
+    fn test_dialects() {
+        let bash = shell(ShellKind::Bash, "/bin/bash");
+        let fish = shell(ShellKind::Fish, "/usr/bin/fish");
+        assert_eq!(bash.script_header(), "#!/bin/bash\nset -e\n\n");
+        assert_eq!(fish.script_header(), "#!/usr/bin/fish\n\n");
+        assert_eq!(bash.quote("it's"), r"'it'\''s'");
+        assert_eq!(fish.quote(r"it's a \ "), r"'it\'s a \\ '");
+        assert!(fish.dialect().contains("not POSIX"));
+    }
+
+    #[tokio::test]
+    async fn test_syntax_check() {
+        let sh = Shell::from_program("/bin/sh").unwrap();
+        assert!(sh.syntax_check("ls -la | grep x").status().await.unwrap().success());
+        assert!(!sh.syntax_check("echo 'unclosed").stderr(std::process::Stdio::null()).status().await.unwrap().success());
+
+        let output = sh.command(&format!("printf %s {}", sh.quote("a 'b' $c"))).output().await.unwrap();
+        assert_eq!(output.stdout, b"a 'b' $c");
+    }
+}
@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use std::time::Duration;
 use chrono::{DateTime, Utc};
 
+use crate::redact::Redactor;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TelemetryEvent {
     pub event_type: String,
@@ -190,36 +192,8 @@ impl TelemetryCollector {
     }
 
     fn clean_command_for_telemetry(&self, command: &str) -> String {
-        let mut cleaned = command.to_string();
-        
         // Remove potential secrets (API keys, passwords, etc.)
-        let secret_patterns = vec![
-            // Environment variables with secrets (broader pattern)
-            regex::Regex::new(r"([A-Z_]*(?:KEY|SECRET|PASSWORD|TOKEN)[^=]*=[^\s]+)").unwrap(),
-            // MySQL passwords: -p'password' or -ppassword or --password=password
-            regex::Regex::new(r"-p['\x22]?[^'\x22\s]+['\x22]?").unwrap(),
-            regex::Regex::new(r"--password=[^\s]+").unwrap(),
-            // SSH key patterns: -i path_to_key
-            regex::Regex::new(r"-i\s+[^\s]*(?:secret|private|rsa)[^\s]*").unwrap(),
-            // Sudo with password pipe
-            regex::Regex::new(r"echo\s+['\x22][^'\x22]*['\x22][^|]*\|\s*sudo\s+-S").unwrap(),
-            // curl with basic auth
-            regex::Regex::new(r"-u\s+[^:\s]+:[^\s]+").unwrap(),
-            // docker login with password
-            regex::Regex::new(r"docker\s+login[^-]*-p\s+[^\s]+").unwrap(),
-            // git clone with credentials in URL
-            regex::Regex::new(r"https://[^:]+:[^@]+@").unwrap(),
-            // OpenAI API keys
-            regex::Regex::new(r"sk-[A-Za-z0-9]{48}").unwrap(),
-            // Long alphanumeric strings (potential API keys) - but only if longer than 32 chars
-            regex::Regex::new(r"\b[A-Za-z0-9_\-]{32,}\b").unwrap(),
-            // Base64 strings
-            regex::Regex::new(r"[A-Za-z0-9+/]{40,}={0,2}").unwrap(),
-        ];
-
-        for pattern in secret_patterns {
-            cleaned = pattern.replace_all(&cleaned, "[REDACTED]").to_string();
-        }
+        let mut cleaned = Redactor::default().redact(command);
 
         // Remove file paths that might contain usernames
         if let Some(home_dir) = dirs_next::home_dir() {
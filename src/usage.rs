@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+use crate::output;
+
+/// What the kernel accounted to a command: the shell or script that ran it and every
+/// process it waited for. Processes left running in the background are not included
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    pub user_time: Duration,
+    pub system_time: Duration,
+    /// Memory, I/O and scheduling counters; commands in the persistent session only report CPU time
+    #[serde(default)]
+    pub counters: Option<Counters>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Counters {
+    /// Peak resident set of the largest single process, in bytes
+    pub max_rss: u64,
+    pub block_reads: u64,
+    pub block_writes: u64,
+    /// Waits for I/O or other resources
+    pub voluntary_switches: u64,
+    /// Preemptions by the scheduler
+    pub involuntary_switches: u64,
+}
+
+impl ResourceUsage {
+    pub fn from_rusage(usage: &libc::rusage) -> Self {
+        Self {
+            user_time: from_timeval(usage.ru_utime),
+            system_time: from_timeval(usage.ru_stime),
+            counters: Some(Counters {
+                max_rss: max_rss_bytes(usage.ru_maxrss),
+                block_reads: usage.ru_inblock as u64,
+                block_writes: usage.ru_oublock as u64,
+                voluntary_switches: usage.ru_nvcsw as u64,
+                involuntary_switches: usage.ru_nivcsw as u64,
+            }),
+        }
+    }
+
+    /// CPU time alone, as reported by a shell's `times` builtin
+    pub fn cpu(user_time: Duration, system_time: Duration) -> Self {
+        Self { user_time, system_time, counters: None }
+    }
+
+    pub fn cpu_time(&self) -> Duration {
+        self.user_time + self.system_time
+    }
+
+    pub fn max_rss(&self) -> Option<u64> {
+        self.counters.map(|counters| counters.max_rss)
+    }
+}
+
+impl fmt::Display for ResourceUsage {
+    /// e.g. "CPU 1.20s user + 0.31s system, peak memory 45.2 MiB, 12 blocks read, 340 written, 96 context switches (3 involuntary)"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CPU {:.2}s user + {:.2}s system", self.user_time.as_secs_f64(), self.system_time.as_secs_f64())?;
+        if let Some(counters) = &self.counters {
+            write!(
+                f,
+                ", peak memory {}, {} blocks read, {} written, {} context switches ({} involuntary)",
+                output::format_bytes(counters.max_rss),
+                counters.block_reads,
+                counters.block_writes,
+                counters.voluntary_switches + counters.involuntary_switches,
+                counters.involuntary_switches
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn from_timeval(time: libc::timeval) -> Duration {
+    Duration::from_secs(time.tv_sec.max(0) as u64) + Duration::from_micros(time.tv_usec.max(0) as u64)
+}
+
+/// macOS reports the peak resident set in bytes, Linux and the BSDs in kilobytes
+fn max_rss_bytes(max_rss: libc::c_long) -> u64 {
+    let max_rss = max_rss.max(0) as u64;
+    if cfg!(target_os = "macos") {
+        max_rss
+    } else {
+        max_rss * 1024
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_rusage_and_display() {
+        let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+        rusage.ru_utime = libc::timeval { tv_sec: 1, tv_usec: 200_000 };
+        rusage.ru_stime = libc::timeval { tv_sec: 0, tv_usec: 310_000 };
+        rusage.ru_maxrss = if cfg!(target_os = "macos") { 2048 * 1024 } else { 2048 };
+        rusage.ru_inblock = 12;
+        rusage.ru_oublock = 340;
+        rusage.ru_nvcsw = 93;
+        rusage.ru_nivcsw = 3;
+
+        let usage = ResourceUsage::from_rusage(&rusage);
+        assert_eq!(usage.cpu_time(), Duration::from_millis(1510));
+        assert_eq!(usage.max_rss(), Some(2 * 1024 * 1024));
+        assert_eq!(
+            usage.to_string(),
+            "CPU 1.20s user + 0.31s system, peak memory 2.0 MiB, 12 blocks read, 340 written, 96 context switches (3 involuntary)"
+        );
+
+        let cpu = ResourceUsage::cpu(Duration::from_millis(50), Duration::ZERO);
+        assert_eq!(cpu.to_string(), "CPU 0.05s user + 0.00s system");
+        assert_eq!(cpu.max_rss(), None);
+    }
+}